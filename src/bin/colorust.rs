@@ -1,18 +1,46 @@
-use color_eyre::Result;
-use colorust::gui::ColorustApp;
+use std::{path::PathBuf, process::Command};
+
+use clap::Parser;
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use colorust::gui::{ArgPurpose, ColorustApp, FileState};
 use eframe::NativeOptions;
+use log::info;
+
+#[derive(Parser)]
+#[command(author, version)]
+struct Cli {
+    /// Render without the GUI: load a serialized file state (JSON)
+    /// and convert it to the given output file
+    #[arg(long, num_args = 2, value_names = ["STATE", "OUTPUT"])]
+    render: Option<Vec<PathBuf>>,
+}
 
 fn main() -> Result<()> {
     colorust::init_logging()?;
 
+    let cli = Cli::parse();
+    if let Some([state, output]) = cli.render.as_deref() {
+        let code = render(state.clone(), output.clone())?;
+        std::process::exit(code);
+    }
+
     let (request_tx, request_rx) = flume::unbounded();
     let (response_tx, response_rx) = flume::unbounded();
 
-    std::thread::spawn(move || colorust::ffmpeg::Thread::new(request_rx, response_tx).run());
+    let workers = std::env::var("COLORUST_WORKERS")
+        .ok()
+        .and_then(|workers| workers.parse().ok())
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(2, |n| n.get().min(4)));
+    std::thread::spawn(move || {
+        colorust::ffmpeg::Thread::new(request_rx, response_tx, workers).run()
+    });
 
     let native_options = NativeOptions::default();
     eframe::run_native(
-        "Colorust",
+        colorust::gui::APP_NAME,
         native_options,
         Box::new(|cc| Ok(Box::new(ColorustApp::new(cc, request_tx, response_rx)))),
     )
@@ -20,3 +48,20 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+fn render(state: PathBuf, output: PathBuf) -> Result<i32> {
+    let json = std::fs::read_to_string(state).wrap_err("Could not read state file")?;
+    let mut file_state: FileState =
+        serde_json::from_str(&json).wrap_err("Could not parse state file")?;
+    file_state.set_output(output);
+
+    let args = file_state.to_ffmpeg_args(ArgPurpose::Convert);
+    info!("Running ffmpeg {args:?}");
+    let status = Command::new("ffmpeg")
+        .args(args)
+        .status()
+        .wrap_err("Could not run ffmpeg")?;
+    status
+        .code()
+        .ok_or_else(|| eyre!("ffmpeg was terminated by a signal"))
+}
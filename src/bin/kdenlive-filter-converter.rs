@@ -2,7 +2,7 @@ use std::path::PathBuf;
 
 use clap::Parser;
 use color_eyre::eyre::{Result, WrapErr};
-use colorust::mlt::{add_filtergraph_to_producers, get_filter_strings};
+use colorust::mlt::{add_filtergraph_to_producers, get_filter_strings, read_mlt};
 use log::debug;
 use roxmltree::Document;
 
@@ -35,7 +35,7 @@ fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
-    let mlt = std::fs::read_to_string(cli.input).wrap_err("Could not read input file")?;
+    let mlt = read_mlt(cli.input).wrap_err("Could not read input file")?;
     let doc = Document::parse(&mlt).wrap_err("Could not parse input file as XML")?;
 
     let filter_strings = get_filter_strings(&doc.root());
@@ -43,7 +43,7 @@ fn main() -> Result<()> {
 
     let insert_into = cli
         .insert_into
-        .map(|p| std::fs::read_to_string(p).wrap_err("Could not read insert_into file"));
+        .map(|p| read_mlt(p).wrap_err("Could not read insert_into file"));
 
     let insert_into = match insert_into {
         None => mlt,
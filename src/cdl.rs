@@ -0,0 +1,147 @@
+use std::path::Path;
+
+use roxmltree::{Document, Node};
+
+use crate::ffmpeg::FilterCdl;
+
+/// One `ColorCorrection` of an ASC CDL file.
+#[derive(Debug, PartialEq)]
+pub struct CdlCorrection {
+    pub id: Option<String>,
+    pub filter: FilterCdl,
+}
+
+/// Reads the color corrections of a `.cdl`, `.cc` or `.ccc` file.
+pub fn read_cdl(path: impl AsRef<Path>) -> Result<Vec<CdlCorrection>, String> {
+    let xml = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse_cdl(&xml)
+}
+
+/// Parses the `ColorCorrection` elements anywhere in the document, so the single correction
+/// of a `.cc`, the collection of a `.ccc` and the `ColorDecisionList` of a `.cdl` all work.
+pub fn parse_cdl(xml: &str) -> Result<Vec<CdlCorrection>, String> {
+    let doc = Document::parse(xml).map_err(|e| e.to_string())?;
+    let corrections = doc
+        .descendants()
+        .filter(|n| n.has_tag_name("ColorCorrection"))
+        .map(|n| parse_correction(&n))
+        .collect::<Result<Vec<_>, _>>()?;
+    if corrections.is_empty() {
+        return Err("No ColorCorrection found".to_string());
+    }
+    Ok(corrections)
+}
+
+fn parse_correction(node: &Node) -> Result<CdlCorrection, String> {
+    let id = node.attribute("id").map(str::to_string);
+    let default = FilterCdl::default();
+    let sop = child(node, "SOPNode");
+    let triple = |name: &str, default: [f32; 3]| match sop.as_ref().and_then(|n| child(n, name)) {
+        Some(n) => parse_values::<3>(&n, name),
+        None => Ok(default),
+    };
+    // SATNode in CDL 1.01, SatNode in later versions
+    let saturation = match child(node, "SatNode")
+        .or_else(|| child(node, "SATNode"))
+        .and_then(|n| child(&n, "Saturation"))
+    {
+        Some(n) => parse_values::<1>(&n, "Saturation")?[0],
+        None => default.saturation,
+    };
+    Ok(CdlCorrection {
+        id,
+        filter: FilterCdl {
+            is_active: true,
+            slope: triple("Slope", default.slope)?,
+            offset: triple("Offset", default.offset)?,
+            power: triple("Power", default.power)?,
+            saturation,
+        },
+    })
+}
+
+fn child<'a, 'input>(node: &Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn parse_values<const N: usize>(node: &Node, name: &str) -> Result<[f32; N], String> {
+    let text = node.text().unwrap_or_default();
+    let values = text
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<f32>, _>>()
+        .ok()
+        .and_then(|values| values.try_into().ok());
+    values.ok_or_else(|| format!("{name} needs {N} numbers, got {:?}", text.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffmpeg::Filter;
+
+    #[test]
+    fn color_correction_collection() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <ColorCorrectionCollection xmlns="urn:ASC:CDL:v1.01">
+             <ColorCorrection id="shot_010">
+              <SOPNode>
+               <Slope>1.1 1.0 0.9</Slope>
+               <Offset>-0.02 0 0.01</Offset>
+               <Power>1 1 1.2</Power>
+              </SOPNode>
+              <SATNode>
+               <Saturation>0.8</Saturation>
+              </SATNode>
+             </ColorCorrection>
+             <ColorCorrection>
+              <SOPNode>
+               <Slope>2 2 2</Slope>
+              </SOPNode>
+             </ColorCorrection>
+            </ColorCorrectionCollection>"#;
+        let corrections = parse_cdl(xml).unwrap();
+        assert_eq!(corrections.len(), 2);
+        assert_eq!(corrections[0].id.as_deref(), Some("shot_010"));
+        assert_eq!(
+            corrections[0].filter,
+            FilterCdl {
+                is_active: true,
+                slope: [1.1, 1.0, 0.9],
+                offset: [-0.02, 0., 0.01],
+                power: [1., 1., 1.2],
+                saturation: 0.8,
+            }
+        );
+        assert_eq!(corrections[1].id, None);
+        assert_eq!(corrections[1].filter.offset, [0.; 3]);
+        assert_eq!(corrections[1].filter.saturation, 1.);
+
+        let filter_string = corrections[1].filter.to_filter_string();
+        assert_eq!(
+            filter_string,
+            r"lutrgb=r=pow(clip(val/maxval*2+0\,0\,1)\,1)*maxval:g=pow(clip(val/maxval*2+0\,0\,1)\,1)*maxval:b=pow(clip(val/maxval*2+0\,0\,1)\,1)*maxval"
+        );
+        let filter_string = corrections[0].filter.to_filter_string();
+        assert!(filter_string.starts_with(r"lutrgb=r=pow(clip(val/maxval*1.1-0.02\,0\,1)\,1)"));
+        assert!(filter_string
+            .ends_with(",colorchannelmixer=rr=0.8425:rg=0.143:rb=0.0144:gr=0.0425:gg=0.943:gb=0.0144:br=0.0425:bg=0.143:bb=0.8144"));
+    }
+
+    #[test]
+    fn invalid_cdl() {
+        assert!(parse_cdl("<ColorDecisionList/>").is_err());
+        assert!(parse_cdl("not xml").is_err());
+        let xml = r#"<ColorDecisionList>
+             <ColorDecision>
+              <ColorCorrection>
+               <SOPNode><Slope>1 1</Slope></SOPNode>
+              </ColorCorrection>
+             </ColorDecision>
+            </ColorDecisionList>"#;
+        assert_eq!(
+            parse_cdl(xml).unwrap_err(),
+            r#"Slope needs 3 numbers, got "1 1""#
+        );
+    }
+}
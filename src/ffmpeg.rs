@@ -1,817 +1,6393 @@
-use egui::{CollapsingHeader, ComboBox, DragValue, Slider, SliderClamping};
+use egui::{
+    collapsing_header::CollapsingState, load::SizedTexture, CollapsingHeader, ComboBox, DragValue,
+    RichText, Sense, Slider, SliderClamping, Vec2,
+};
 use egui_file::FileDialog;
+use egui_plot::{Line, Plot, PlotPoints};
 use flume::{Receiver, Sender};
-use image::{ImageReader, RgbaImage};
+use image::{DynamicImage, ImageBuffer, ImageReader, Rgba, RgbaImage};
 use log::info;
 use roxmltree::Node;
 use serde::{Deserialize, Serialize};
-use std::{path::PathBuf, process::Command};
+use std::{
+    any::Any,
+    collections::{BTreeMap, BTreeSet, HashSet, VecDeque},
+    fmt::Write,
+    io::{BufRead, BufReader, Cursor, Seek},
+    ops::{Range, RangeInclusive},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+};
 
-use crate::{gui::GuiElement, mlt::get_property_value};
+use crate::{
+    filtergraph::{
+        escape_separators, filter_names, get_param_value, get_param_value_or, split_top_level,
+        FilterSegment,
+    },
+    gui::GuiElement,
+    mlt::get_property_value,
+};
 
 #[derive(Debug)]
-pub enum Request {
-    ExtractFrame { args: Vec<String>, output: PathBuf },
-    Play { args: Vec<String> },
-}
-
-#[derive(Debug)]
-pub enum Response {
-    Image(RgbaImage),
-    Error(String),
-}
-
-#[typetag::serde(tag = "type")]
-pub trait CliOption: GuiElement {
-    fn to_option_args(&self) -> Vec<String>;
+pub enum FrameOutput {
+    File(PathBuf),
+    Pipe(FrameFormat),
 }
 
-#[typetag::serde(tag = "type")]
-pub trait Filter: GuiElement {
-    fn to_filter_string(&self) -> String;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameFormat {
+    Bmp,
+    Jpeg,
+    /// 16 bits per channel for precise scopes.
+    Png16,
 }
 
-#[derive(Default, Serialize, Deserialize)]
-pub struct FilterOption {
-    pub filters: Vec<Box<dyn Filter>>,
-}
+impl FrameFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Bmp => "bmp",
+            Self::Jpeg => "jpg",
+            Self::Png16 => "png",
+        }
+    }
 
-#[typetag::serde]
-impl CliOption for FilterOption {
-    fn to_option_args(&self) -> Vec<String> {
-        if self.filters.is_empty() || self.filters.iter().all(|f| !f.is_active()) {
-            return vec![];
+    fn vcodec(&self) -> &'static str {
+        match self {
+            Self::Bmp => "bmp",
+            Self::Jpeg => "mjpeg",
+            Self::Png16 => "png",
         }
-        let s = "-vf".to_string();
-        let filter_string = self
-            .filters
-            .iter()
-            .filter_map(|f| {
-                if f.is_active() {
-                    Some(f.to_filter_string())
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>()
-            .join(",");
+    }
 
-        vec![s, filter_string]
+    fn pix_fmt_args(&self) -> Vec<String> {
+        match self {
+            Self::Bmp | Self::Jpeg => vec![],
+            Self::Png16 => vec!["-pix_fmt".to_string(), "rgba64be".to_string()],
+        }
     }
 }
 
-#[typetag::serde]
-impl GuiElement for FilterOption {
-    fn name(&self) -> &'static str {
-        "Filters"
-    }
+/// A frame with 16 bits per channel.
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
 
-    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
-        for filter in self.filters.iter_mut() {
-            CollapsingHeader::new(filter.name()).show(ui, |ui| {
-                filter.draw(ctx, ui);
-            });
+impl FrameOutput {
+    fn format(&self) -> FrameFormat {
+        match self {
+            Self::File(path) => [FrameFormat::Jpeg, FrameFormat::Png16]
+                .into_iter()
+                .find(|format| {
+                    path.extension()
+                        .is_some_and(|extension| extension == format.extension())
+                })
+                .unwrap_or(FrameFormat::Bmp),
+            Self::Pipe(format) => *format,
         }
     }
+
+    pub fn to_option_args(&self) -> Vec<String> {
+        let mut args = self.format().pix_fmt_args();
+        args.extend(match self {
+            Self::File(path) => vec![path.to_string_lossy().to_string()],
+            Self::Pipe(format) => vec![
+                "-f".to_string(),
+                "image2pipe".to_string(),
+                "-vcodec".to_string(),
+                format.vcodec().to_string(),
+                "pipe:1".to_string(),
+            ],
+        });
+        args
+    }
 }
 
-#[derive(Default, Serialize, Deserialize)]
-pub struct SkipOption {
-    pub seconds: u64,
+pub type JobId = u64;
+
+#[derive(Debug)]
+pub enum Request {
+    ExtractFrame {
+        id: JobId,
+        args: Vec<String>,
+        output: FrameOutput,
+    },
+    Prefetch {
+        input: PathBuf,
+        args: Vec<String>,
+        output: FrameOutput,
+    },
+    Play {
+        args: Vec<String>,
+    },
+    /// Encodes a proxy clip to `output` and opens it with [`player_command`].
+    PlayProxy {
+        args: Vec<String>,
+        output: PathBuf,
+        player: String,
+    },
+    Probe {
+        input: PathBuf,
+    },
+    /// Runs ffmpeg to completion, e.g. to grade a still image.
+    Convert {
+        input: PathBuf,
+        args: Vec<String>,
+    },
+    /// Extracts a small frame for the filter list, `key` identifies it in the thumbnail cache.
+    Thumbnail {
+        key: String,
+        args: Vec<String>,
+        output: FrameOutput,
+    },
+    /// Detects the version and the filters of the installed ffmpeg.
+    DetectCapabilities,
 }
 
-#[typetag::serde]
-impl CliOption for SkipOption {
-    fn to_option_args(&self) -> Vec<String> {
-        vec!["-ss".to_string(), self.seconds.to_string()]
+impl Request {
+    /// The id of a frame extraction, which the GUI matches its response to.
+    fn job_id(&self) -> Option<JobId> {
+        match self {
+            Self::ExtractFrame { id, .. } => Some(*id),
+            _ => None,
+        }
     }
-}
 
-#[typetag::serde]
-impl GuiElement for SkipOption {
-    fn name(&self) -> &'static str {
-        "Skip seconds"
+    fn is_low_priority(&self) -> bool {
+        matches!(self, Self::Prefetch { .. } | Self::Thumbnail { .. })
     }
 
-    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) {
-        ui.add(DragValue::new(&mut self.seconds));
+    /// Playback and encodes run one at a time on their own thread, so they never hold the
+    /// workers that extract frames. Players are only started there, so an open player does
+    /// not hold back encodes.
+    fn is_serial(&self) -> bool {
+        matches!(
+            self,
+            Self::Play { .. } | Self::PlayProxy { .. } | Self::Convert { .. }
+        )
     }
 }
 
-#[derive(Default, Serialize, Deserialize)]
-pub struct NumberOfFramesOption {
-    pub frames: u64,
+#[derive(Debug)]
+pub enum Response {
+    Image {
+        id: JobId,
+        image: RgbaImage,
+        /// The frame at full precision if it was extracted with more than 8 bits per channel.
+        precise: Option<Rgba16Image>,
+    },
+    Prefetched {
+        input: PathBuf,
+        result: Result<RgbaImage, String>,
+    },
+    Probed {
+        input: PathBuf,
+        result: Result<ProbeInfo, String>,
+    },
+    /// Position of a running conversion in seconds of output.
+    ConvertProgress {
+        input: PathBuf,
+        seconds: f64,
+    },
+    Converted {
+        input: PathBuf,
+        result: Result<(), String>,
+    },
+    Thumbnail {
+        key: String,
+        result: Result<RgbaImage, String>,
+    },
+    Capabilities(FfmpegCapabilities),
+    /// `id` is set if a frame extraction failed.
+    Error {
+        id: Option<JobId>,
+        error: String,
+    },
 }
 
-#[typetag::serde]
-impl CliOption for NumberOfFramesOption {
-    fn to_option_args(&self) -> Vec<String> {
-        vec!["-frames:v".to_string(), self.frames.to_string()]
-    }
+/// What the installed ffmpeg supports.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FfmpegCapabilities {
+    pub version: Option<String>,
+    /// `None` if the filters could not be listed, all filters are assumed to exist then.
+    pub filters: Option<HashSet<String>>,
 }
 
-#[typetag::serde]
-impl GuiElement for NumberOfFramesOption {
-    fn name(&self) -> &'static str {
-        "Namber of frames"
+impl FfmpegCapabilities {
+    pub fn detect() -> Self {
+        let run = |arg: &str| match Command::new("ffmpeg").args(["-hide_banner", arg]).output() {
+            Ok(output) if output.status.success() => {
+                Some(String::from_utf8_lossy(&output.stdout).to_string())
+            }
+            Ok(output) => {
+                log::warn!(
+                    "ffmpeg {arg} failed: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                );
+                None
+            }
+            Err(e) => {
+                log::error!("Could not run ffmpeg: {e}");
+                None
+            }
+        };
+        Self {
+            version: run("-version").and_then(|output| Self::parse_version(&output)),
+            filters: run("-filters").map(|output| Self::parse_filters(&output)),
+        }
     }
 
-    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) {
-        ui.add(DragValue::new(&mut self.frames));
+    /// Parses the first line of `ffmpeg -version`, e.g. `ffmpeg version 6.1.1 Copyright ...`.
+    fn parse_version(output: &str) -> Option<String> {
+        let mut words = output.lines().next()?.split_whitespace();
+        words.find(|word| *word == "version")?;
+        words.next().map(ToString::to_string)
     }
-}
 
-#[derive(Default, Serialize, Deserialize)]
-pub struct InputFile {
-    pub path: PathBuf,
-    #[serde(skip)]
-    pub dialog: Option<FileDialog>,
-}
+    /// Parses the list of `ffmpeg -filters`, whose lines look like
+    /// ` TSC colorbalance      V->V       Adjust the color balance.`
+    fn parse_filters(output: &str) -> HashSet<String> {
+        output
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let (_flags, name, io) = (fields.next()?, fields.next()?, fields.next()?);
+                io.contains("->").then(|| name.to_string())
+            })
+            .collect()
+    }
 
-#[typetag::serde]
-impl CliOption for InputFile {
-    fn to_option_args(&self) -> Vec<String> {
-        vec!["-i".to_string(), self.path.to_string_lossy().to_string()]
+    pub fn is_available(&self, ffmpeg_filter: &str) -> bool {
+        self.filters
+            .as_ref()
+            .is_none_or(|filters| filters.contains(ffmpeg_filter))
     }
-}
 
-#[typetag::serde]
-impl GuiElement for InputFile {
-    fn name(&self) -> &'static str {
-        "Input file"
+    /// The ffmpeg filters used by `filter` that the installed ffmpeg lacks.
+    pub fn missing_filters(&self, filter: &dyn Filter) -> Vec<String> {
+        filter
+            .ffmpeg_filters()
+            .into_iter()
+            .filter(|name| !self.is_available(name))
+            .collect()
     }
 
-    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
-        let mut path = self.path.to_string_lossy();
-        if ui.text_edit_singleline(path.to_mut()).changed() {
-            self.path = PathBuf::from(path.to_string());
-        }
-        if ui.button("Open").clicked() {
-            let mut dialog = FileDialog::open_file(if self.path.is_dir() || self.path.is_file() {
-                Some(self.path.clone())
-            } else {
-                None
-            });
-            dialog.open();
-            self.dialog = Some(dialog);
-        }
-        if let Some(dialog) = &mut self.dialog {
-            if dialog.show(ctx).selected() {
-                if let Some(path) = dialog.path() {
-                    self.path = path.to_path_buf();
-                }
+    /// Explains why a filter is disabled.
+    pub fn missing_text(&self, missing: &[String]) -> String {
+        let version = self.version.as_deref().unwrap_or("unknown");
+        format!(
+            "The installed ffmpeg (version {version}) lacks the filter(s): {}",
+            missing.join(", ")
+        )
+    }
+}
+
+/// Color properties of the first video stream as reported by ffprobe.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ColorMetadata {
+    pub color_space: Option<String>,
+    pub color_transfer: Option<String>,
+    pub color_primaries: Option<String>,
+    /// `tv` for limited or `pc` for full range.
+    #[serde(default)]
+    pub color_range: Option<String>,
+    #[serde(default)]
+    pub pix_fmt: Option<String>,
+}
+
+impl ColorMetadata {
+    fn from_ffprobe_json(json: &str) -> Result<Self, String> {
+        let mut metadata = ffprobe_video_streams::<ColorMetadata>(json)?
+            .into_iter()
+            .next()
+            .ok_or("No video stream")?;
+        for value in [
+            &mut metadata.color_space,
+            &mut metadata.color_transfer,
+            &mut metadata.color_primaries,
+            &mut metadata.color_range,
+            &mut metadata.pix_fmt,
+        ] {
+            if value.as_deref() == Some("unknown") {
+                *value = None;
             }
         }
+        Ok(metadata)
     }
 }
 
-#[derive(Default, Serialize, Deserialize)]
-pub struct OutputFile {
-    pub path: PathBuf,
-    #[serde(skip)]
-    pub dialog: Option<FileDialog>,
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProbeInfo {
+    pub color: ColorMetadata,
+    pub video_streams: Vec<VideoStream>,
+    /// Duration of the input in seconds.
+    pub duration: Option<f64>,
+    /// Complete output of ffprobe as JSON.
+    pub raw: String,
 }
 
-#[typetag::serde]
-impl CliOption for OutputFile {
-    fn to_option_args(&self) -> Vec<String> {
-        vec![self.path.to_string_lossy().to_string()]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct VideoStream {
+    /// Absolute stream index within the file.
+    pub index: usize,
+    pub codec_name: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_attached_pic",
+        rename = "disposition"
+    )]
+    pub attached_pic: bool,
+}
+
+fn deserialize_attached_pic<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<bool, D::Error> {
+    #[derive(Deserialize)]
+    struct Disposition {
+        #[serde(default)]
+        attached_pic: u8,
     }
+
+    Ok(Disposition::deserialize(deserializer)?.attached_pic == 1)
 }
 
-#[typetag::serde]
-impl GuiElement for OutputFile {
-    fn name(&self) -> &'static str {
-        "Output file"
+impl VideoStream {
+    fn list_from_ffprobe_json(json: &str) -> Result<Vec<Self>, String> {
+        ffprobe_video_streams(json)
     }
+}
 
-    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
-        let mut path = self.path.to_string_lossy();
-        if ui.text_edit_singleline(path.to_mut()).changed() {
-            self.path = PathBuf::from(path.to_string());
-        }
-        if ui.button("Open").clicked() {
-            let mut dialog = FileDialog::save_file(if self.path.is_file() {
-                Some(self.path.clone())
-            } else if let Some(parent) = self.path.parent() {
-                if parent.is_dir() {
-                    Some(parent.to_path_buf())
-                } else {
-                    None
-                }
-            } else {
-                None
-            });
-            dialog.open();
-            self.dialog = Some(dialog);
+/// The video streams in ffprobe's JSON output. Streams without a type are taken as video.
+fn ffprobe_video_streams<T: serde::de::DeserializeOwned>(json: &str) -> Result<Vec<T>, String> {
+    #[derive(Deserialize)]
+    struct Streams {
+        streams: Vec<serde_json::Value>,
+    }
+
+    serde_json::from_str::<Streams>(json)
+        .map_err(|e| e.to_string())?
+        .streams
+        .into_iter()
+        .filter(|stream| {
+            stream
+                .get("codec_type")
+                .is_none_or(|codec_type| codec_type == "video")
+        })
+        .map(|stream| serde_json::from_value(stream).map_err(|e| e.to_string()))
+        .collect()
+}
+
+impl std::fmt::Display for VideoStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "#{} {}",
+            self.index,
+            self.codec_name.as_deref().unwrap_or("unknown")
+        )?;
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            write!(f, " {width}x{height}")?;
         }
-        if let Some(dialog) = &mut self.dialog {
-            if dialog.show(ctx).selected() {
-                if let Some(path) = dialog.path() {
-                    self.path = path.to_path_buf();
-                }
-            }
+        if self.attached_pic {
+            write!(f, " (cover art)")?;
         }
+        Ok(())
     }
 }
 
-#[derive(Default, Serialize, Deserialize)]
-pub struct Encoder {
-    pub expression: String,
+/// Verbosity of ffmpeg's own output for frame extraction.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FfmpegLogLevel {
+    Quiet,
+    #[default]
+    Error,
+    Warning,
+    Info,
+    Verbose,
 }
 
-#[typetag::serde]
-impl CliOption for Encoder {
-    fn to_option_args(&self) -> Vec<String> {
-        if self.expression.is_empty() {
-            vec![]
-        } else {
-            vec!["-c:v".to_string(), self.expression.clone()]
+impl FfmpegLogLevel {
+    pub const ALL: [Self; 5] = [
+        Self::Quiet,
+        Self::Error,
+        Self::Warning,
+        Self::Info,
+        Self::Verbose,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Quiet => "quiet",
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Info => "info",
+            Self::Verbose => "verbose",
         }
     }
-}
 
-#[typetag::serde]
-impl GuiElement for Encoder {
-    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) {
-        ui.text_edit_singleline(&mut self.expression);
+    /// The `level` flag prefixes every line with its level so it can be routed into our log.
+    pub fn to_option_args(&self) -> Vec<String> {
+        vec!["-loglevel".to_string(), format!("level+{}", self.as_str())]
     }
+}
 
-    fn name(&self) -> &'static str {
-        "Encoder"
+/// Forwards ffmpeg's stderr to the log, using the level tag of each line.
+fn log_ffmpeg_output(stderr: &str) {
+    for line in stderr.lines().filter(|l| !l.trim().is_empty()) {
+        log::log!(target: "ffmpeg", ffmpeg_line_level(line), "{line}");
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
-pub struct FilterExposure {
-    pub is_active: bool,
-    pub exposure: f32,
-    pub black: f32,
+fn ffmpeg_line_level(line: &str) -> log::Level {
+    let tags = [
+        ("[panic]", log::Level::Error),
+        ("[fatal]", log::Level::Error),
+        ("[error]", log::Level::Error),
+        ("[warning]", log::Level::Warn),
+        ("[info]", log::Level::Info),
+        ("[verbose]", log::Level::Info),
+        ("[debug]", log::Level::Debug),
+        ("[trace]", log::Level::Trace),
+    ];
+    tags.into_iter()
+        .find(|(tag, _)| line.contains(tag))
+        .map_or(log::Level::Info, |(_, level)| level)
 }
 
-#[typetag::serde]
-impl Filter for FilterExposure {
-    fn to_filter_string(&self) -> String {
-        format!("exposure=exposure={}:black={}", self.exposure, self.black)
+#[typetag::serde(tag = "type")]
+pub trait CliOption: GuiElement {
+    fn to_option_args(&self) -> Vec<String>;
+    fn placement(&self) -> OptionPlacement {
+        OptionPlacement::AfterInput
     }
 }
 
-#[typetag::serde]
-impl GuiElement for FilterExposure {
-    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) {
-        ui.checkbox(&mut self.is_active, "Active");
-        ui.add(
-            Slider::new(&mut self.exposure, -3.0..=3.0)
-                .clamping(SliderClamping::Always)
-                .text("Exposure"),
-        );
-        ui.add(
-            Slider::new(&mut self.black, -1.0..=1.0)
-                .clamping(SliderClamping::Always)
-                .text("Black level"),
-        );
-    }
+/// Where an option goes in the ffmpeg command. Options before `-i` apply to the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionPlacement {
+    BeforeInput,
+    AfterInput,
+}
 
-    fn name(&self) -> &'static str {
-        "Exposure"
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 
-    fn is_active(&self) -> bool {
-        self.is_active
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
     }
 }
 
-impl TryFrom<&Node<'_, '_>> for FilterExposure {
-    type Error = ();
+pub trait BoxedDefault {
+    fn boxed_default(&self) -> Box<dyn Filter>;
+}
 
-    fn try_from(value: &Node) -> Result<Self, Self::Error> {
-        if get_property_value(value, "mlt_service") != Some("avfilter.exposure".to_string()) {
-            return Err(());
-        }
-        let exposure = get_property_value(value, "av.exposure").ok_or(())?;
-        let black = get_property_value(value, "av.black").ok_or(())?;
-        let disabled = get_property_value(value, "disable").unwrap_or(0) == 1;
+impl<T: Filter + Default + 'static> BoxedDefault for T {
+    fn boxed_default(&self) -> Box<dyn Filter> {
+        Box::<T>::default()
+    }
+}
 
-        Ok(Self {
-            is_active: !disabled,
-            exposure,
-            black,
-        })
+#[typetag::serde(tag = "type")]
+pub trait Filter: GuiElement + AsAny + BoxedDefault {
+    fn to_filter_string(&self) -> String;
+    /// Names of the ffmpeg filters that the filter string uses.
+    fn ffmpeg_filters(&self) -> Vec<String> {
+        filter_names(&self.to_filter_string())
+    }
+    fn sample_aspect_ratio(&self, _width: u32, _height: u32) -> Option<f32> {
+        None
     }
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct FilterLut {
-    pub is_active: bool,
-    pub file: String,
-    pub interpolation: String,
+/// ffmpeg filters that change colors and belong before a creative LUT.
+const GRADING_FILTERS: &[&str] = &[
+    "colorbalance",
+    "colorchannelmixer",
+    "colorcontrast",
+    "colorcorrect",
+    "colorlevels",
+    "colortemperature",
+    "curves",
+    "eq",
+    "exposure",
+    "hue",
+    "huesaturation",
+    "lutrgb",
+    "lutyuv",
+    "normalize",
+    "selectivecolor",
+    "vibrance",
+];
+
+fn uses_lut(filter: &dyn Filter) -> bool {
+    filter.ffmpeg_filters().iter().any(|f| f == "lut3d")
 }
 
-impl Default for FilterLut {
-    fn default() -> Self {
-        Self {
-            is_active: false,
-            file: String::new(),
-            interpolation: "tetrahedral".to_string(),
-        }
-    }
+fn is_grading(filter: &dyn Filter) -> bool {
+    filter
+        .ffmpeg_filters()
+        .iter()
+        .any(|f| GRADING_FILTERS.contains(&f.as_str()))
 }
 
-#[typetag::serde]
-impl Filter for FilterLut {
-    fn to_filter_string(&self) -> String {
-        format!("lut3d=file={}:interp={}", self.file, self.interpolation)
+/// Formats a float parameter for a filter string, rounded to 4 decimals and without a sign on
+/// zero so slider artifacts like `1.0000001` or `-0` do not end up in the command.
+pub fn format_param(value: f32) -> String {
+    let rounded = (value * 10000.).round() / 10000.;
+    if rounded == 0. {
+        "0".to_string()
+    } else {
+        rounded.to_string()
     }
 }
 
-impl TryFrom<&Node<'_, '_>> for FilterLut {
-    type Error = ();
+/// Narrower range for one slider of a filter, used to limit how far it can be moved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RangeLock {
+    /// Name of the filter as shown in the GUI.
+    pub filter: String,
+    /// Text of the slider.
+    pub param: String,
+    pub min: f64,
+    pub max: f64,
+}
 
-    fn try_from(value: &Node) -> Result<Self, Self::Error> {
-        if get_property_value(value, "mlt_service") != Some("avfilter.lut3d".to_string()) {
-            return Err(());
-        }
-        let file = get_property_value(value, "av.file").ok_or(())?;
-        let interpolation = get_property_value(value, "av.interp").ok_or(())?;
-        let disabled = get_property_value(value, "disable").unwrap_or(0) == 1;
+/// Id of the range locks in egui's temporary data, set by the GUI while they are applied.
+pub fn range_locks_id() -> egui::Id {
+    egui::Id::new("range_locks")
+}
 
-        Ok(Self {
-            is_active: !disabled,
-            file,
-            interpolation,
-        })
+/// Returns `range` narrowed to the matching lock. Locks outside of `range` are ignored.
+pub fn locked_range<Num: egui::emath::Numeric>(
+    locks: &[RangeLock],
+    filter: &str,
+    param: &str,
+    range: RangeInclusive<Num>,
+) -> RangeInclusive<Num> {
+    let Some(lock) = locks
+        .iter()
+        .find(|lock| lock.filter == filter && lock.param == param)
+    else {
+        return range;
+    };
+    let min = lock.min.max(range.start().to_f64());
+    let max = lock.max.min(range.end().to_f64());
+    if min > max {
+        return range;
     }
+    Num::from_f64(min)..=Num::from_f64(max)
 }
 
-#[typetag::serde]
-impl GuiElement for FilterLut {
-    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) {
-        ui.checkbox(&mut self.is_active, "Active");
-        ui.text_edit_singleline(&mut self.file);
-        ComboBox::from_label("Interpolation")
-            .selected_text(&self.interpolation)
-            .show_ui(ui, |ui| {
-                ui.selectable_value(&mut self.interpolation, "nearest".to_string(), "nearest");
-                ui.selectable_value(
-                    &mut self.interpolation,
-                    "trilinear".to_string(),
-                    "trilinear",
-                );
-                ui.selectable_value(
-                    &mut self.interpolation,
-                    "tetrahedral".to_string(),
-                    "tetrahedral",
-                );
-            });
-    }
+fn param_slider<'a, Num: egui::emath::Numeric>(
+    ctx: &egui::Context,
+    filter: &str,
+    value: &'a mut Num,
+    range: RangeInclusive<Num>,
+    text: &str,
+) -> Slider<'a> {
+    let range = match ctx.data(|d| d.get_temp::<Arc<Vec<RangeLock>>>(range_locks_id())) {
+        Some(locks) => locked_range(&locks, filter, text, range),
+        None => range,
+    };
+    // A value outside of a locked range is kept until it is edited
+    Slider::new(value, range)
+        .text(text)
+        .clamping(SliderClamping::Edits)
+}
 
-    fn name(&self) -> &'static str {
-        "LUT"
-    }
+/// Saved settings of single filters as serialized filters, by filter name and preset name.
+pub type FilterPresets = BTreeMap<String, BTreeMap<String, String>>;
 
-    fn is_active(&self) -> bool {
-        self.is_active
-    }
+/// Id of the filter presets in egui's temporary data, set by the GUI before drawing filters.
+pub fn filter_presets_id() -> egui::Id {
+    egui::Id::new("filter_presets")
 }
 
-#[derive(Default, Serialize, Deserialize)]
-pub struct FilterScale {
-    pub is_active: bool,
-    pub width: u64,
-    pub height: u64,
+/// Id of the [`PresetChange`]s made while drawing filters, which the GUI applies afterwards.
+pub fn preset_changes_id() -> egui::Id {
+    egui::Id::new("filter_preset_changes")
 }
 
-#[typetag::serde]
-impl Filter for FilterScale {
-    fn to_filter_string(&self) -> String {
-        format!("scale={}:{}", self.width, self.height)
+#[derive(Debug, Clone, PartialEq)]
+pub enum PresetChange {
+    Save {
+        filter: String,
+        name: String,
+        preset: String,
+    },
+    Delete {
+        filter: String,
+        name: String,
+    },
+}
+
+impl PresetChange {
+    pub fn apply(self, presets: &mut FilterPresets) {
+        match self {
+            Self::Save {
+                filter,
+                name,
+                preset,
+            } => {
+                presets.entry(filter).or_default().insert(name, preset);
+            }
+            Self::Delete { filter, name } => {
+                if let Some(filter_presets) = presets.get_mut(&filter) {
+                    filter_presets.remove(&name);
+                    if filter_presets.is_empty() {
+                        presets.remove(&filter);
+                    }
+                }
+            }
+        }
     }
 }
 
-#[typetag::serde]
-impl GuiElement for FilterScale {
-    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) {
-        ui.checkbox(&mut self.is_active, "Active");
+/// Menu with the presets of `filter`, to apply, delete or save them. Returns whether a preset
+/// was applied.
+fn draw_presets_menu(
+    ctx: &egui::Context,
+    ui: &mut egui::Ui,
+    id: egui::Id,
+    filter: &mut Box<dyn Filter>,
+) -> bool {
+    let presets = ctx
+        .data(|d| d.get_temp::<Arc<FilterPresets>>(filter_presets_id()))
+        .unwrap_or_default();
+    let filter_presets = presets.get(filter.name());
+    let mut applied = false;
+    let mut changes = vec![];
+    let label = if filter_presets.is_some() {
+        "★"
+    } else {
+        "☆"
+    };
+    ui.menu_button(label, |ui| {
+        for (name, preset) in filter_presets.into_iter().flatten() {
+            ui.horizontal(|ui| {
+                if ui.button(name).clicked() {
+                    match serde_json::from_str::<Box<dyn Filter>>(preset) {
+                        Ok(preset) if preset.name() == filter.name() => {
+                            *filter = preset;
+                            applied = true;
+                        }
+                        Ok(_) => log::error!("Preset {name} is for another filter"),
+                        Err(e) => log::error!("Could not apply preset {name}: {e}"),
+                    }
+                    ui.close_menu();
+                }
+                if ui.small_button("🗑").on_hover_text("Delete").clicked() {
+                    changes.push(PresetChange::Delete {
+                        filter: filter.name().to_string(),
+                        name: name.clone(),
+                    });
+                }
+            });
+        }
+        if filter_presets.is_some() {
+            ui.separator();
+        }
+        let name_id = id.with("preset_name");
+        let mut name = ctx
+            .data(|d| d.get_temp::<String>(name_id))
+            .unwrap_or_default();
         ui.horizontal(|ui| {
-            ui.label("Width");
-            ui.add(DragValue::new(&mut self.width));
+            ui.add(
+                egui::TextEdit::singleline(&mut name)
+                    .hint_text("Preset name")
+                    .desired_width(120.),
+            );
+            if ui
+                .add_enabled(!name.trim().is_empty(), egui::Button::new("Save"))
+                .on_hover_text("Save the settings of this filter, replacing a preset of that name")
+                .clicked()
+            {
+                changes.push(PresetChange::Save {
+                    filter: filter.name().to_string(),
+                    name: name.trim().to_string(),
+                    preset: serde_json::to_string(filter.as_ref()).unwrap(),
+                });
+                name.clear();
+                ui.close_menu();
+            }
         });
-        ui.horizontal(|ui| {
-            ui.label("Heigth");
-            ui.add(DragValue::new(&mut self.height));
+        ctx.data_mut(|d| d.insert_temp(name_id, name));
+    })
+    .response
+    .on_hover_text("Presets");
+    if !changes.is_empty() {
+        ctx.data_mut(|d| {
+            d.get_temp_mut_or_default::<Vec<PresetChange>>(preset_changes_id())
+                .extend(changes)
         });
     }
+    applied
+}
 
-    fn name(&self) -> &'static str {
-        "Scale"
-    }
+pub type FilterFactory = fn() -> Box<dyn Filter>;
 
-    fn is_active(&self) -> bool {
-        self.is_active
-    }
+fn new_filter<T: Filter + Default + 'static>() -> Box<dyn Filter> {
+    Box::<T>::default()
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct FilterEq {
+/// All filter types that can be added in the GUI, by name.
+pub const FILTER_REGISTRY: &[(&str, FilterFactory)] = &[
+    ("Scale", || {
+        Box::new(FilterScale {
+            is_active: false,
+            width: 1280,
+            height: 720,
+        })
+    }),
+    ("Exposure", new_filter::<FilterExposure>),
+    ("Color temperature", new_filter::<FilterColortemp>),
+    ("RGB gain", new_filter::<FilterRgbGain>),
+    ("ASC CDL", new_filter::<FilterCdl>),
+    ("LUT", new_filter::<FilterLut>),
+    ("Eq", new_filter::<FilterEq>),
+    ("Color balance", new_filter::<FilterColorBalance>),
+    ("Color key", new_filter::<FilterColorKey>),
+    ("Normalize", new_filter::<FilterNormalize>),
+    ("Tone curve", new_filter::<FilterToneCurve>),
+    ("Limiter", new_filter::<FilterLimiter>),
+    ("Colorspace", new_filter::<FilterColorspace>),
+    ("Pixel format", new_filter::<FilterFormat>),
+    ("Sample aspect ratio", new_filter::<FilterSetSar>),
+    ("Display aspect ratio", new_filter::<FilterSetDar>),
+    ("Deshake", new_filter::<FilterDeshake>),
+    ("Custom filter(s)", new_filter::<FilterCustom>),
+];
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct FilterOption {
+    pub filters: Vec<Box<dyn Filter>>,
+    /// Optional stages over `filters`. Each group owns the next `len` filters, the last group
+    /// also owns any filters added since. Without groups the chain is shown flat.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<FilterGroup>,
+    /// Number of filters at the front of `filters` that are pinned there. They belong to the
+    /// first group and other filters cannot be moved before them.
+    #[serde(default)]
+    pub pinned: usize,
+    /// Index of a filter that is applied on its own for previews.
+    #[serde(skip)]
+    pub solo: Option<usize>,
+    #[serde(default)]
+    pub mask: FilterMask,
+    /// Before/after thumbnails per filter, provided by the GUI.
+    #[serde(skip)]
+    pub thumbnails: Vec<Option<[SizedTexture; 2]>>,
+    /// Filters of the installed ffmpeg, provided by the GUI.
+    #[serde(skip)]
+    pub capabilities: Arc<FfmpegCapabilities>,
+    /// Pixel format of the input, provided by the GUI. The mask converts back to it.
+    #[serde(skip)]
+    pub input_format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MaskShape {
+    #[default]
+    Ellipse,
+    Rectangle,
+}
+
+/// Restricts the filter chain to a region of the frame.
+///
+/// With an active mask the chain `CHAIN` is emitted as the filtergraph
+///
+/// ```text
+/// split[base][fg];
+/// [fg]CHAIN,format=rgba,geq=r=r(X\,Y):g=g(X\,Y):b=b(X\,Y):a=ALPHA[graded];
+/// [base]format=rgba[ungraded];
+/// [ungraded][graded]overlay=format=auto
+/// ```
+///
+/// i.e. the graded copy gets an alpha channel from the mask shape and is laid over the
+/// untouched frame, followed by `format=` the input's pixel format if it is known. It has a
+/// single input and output, so it still works with `-vf` and filters can be appended to it.
+/// The chain must not change the frame size.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterMask {
     pub is_active: bool,
-    pub contrast: f32,
-    pub brightness: f32,
-    pub saturation: f32,
-    pub gamma: f32,
-    pub gamma_r: f32,
-    pub gamma_g: f32,
-    pub gamma_b: f32,
+    pub shape: MaskShape,
+    /// Relative to the frame size, from 0 to 1.
+    pub center: [f32; 2],
+    /// Relative to the frame size, from 0 to 1.
+    pub size: [f32; 2],
+    /// Width of the soft edge relative to the mask size.
+    pub feather: f32,
+    pub invert: bool,
 }
 
-impl Default for FilterEq {
+impl Default for FilterMask {
     fn default() -> Self {
         Self {
             is_active: false,
-            contrast: 1.,
-            brightness: 0.,
-            saturation: 1.,
-            gamma: 1.,
-            gamma_r: 1.,
-            gamma_g: 1.,
-            gamma_b: 1.,
+            shape: MaskShape::Ellipse,
+            center: [0.5, 0.5],
+            size: [0.5, 0.5],
+            feather: 0.2,
+            invert: false,
         }
     }
 }
 
-#[typetag::serde]
-impl Filter for FilterEq {
-    fn to_filter_string(&self) -> String {
-        format!(
-            "eq=contrast={}:brightness={}:saturation={}:gamma={}:gamma_r={}:gamma_g={}:gamma_b={}",
-            self.contrast,
-            self.brightness,
-            self.saturation,
-            self.gamma,
-            self.gamma_r,
-            self.gamma_g,
-            self.gamma_b
-        )
+impl FilterMask {
+    fn alpha_expression(&self) -> String {
+        let [cx, cy] = self.center.map(format_param);
+        let [w, h] = self.size.map(|s| format_param((s / 2.).max(0.001)));
+        let dx = format!("(X-{cx}*W)/({w}*W)");
+        let dy = format!("(Y-{cy}*H)/({h}*H)");
+        let distance = match self.shape {
+            MaskShape::Ellipse => format!("hypot({dx}\\,{dy})"),
+            MaskShape::Rectangle => format!("max(abs({dx})\\,abs({dy}))"),
+        };
+        let alpha = if self.feather > 0. {
+            format!(
+                "255*clip((1-{distance})/{}\\,0\\,1)",
+                format_param(self.feather)
+            )
+        } else {
+            format!("255*lte({distance}\\,1)")
+        };
+        if self.invert {
+            format!("255-{alpha}")
+        } else {
+            alpha
+        }
     }
-}
 
-#[typetag::serde]
-impl GuiElement for FilterEq {
-    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) {
-        ui.checkbox(&mut self.is_active, "Active");
-        ui.add(
-            Slider::new(&mut self.contrast, 0.0..=3.0)
-                .clamping(SliderClamping::Always)
-                .logarithmic(true)
-                .text("Contrast"),
-        );
-        ui.add(
-            Slider::new(&mut self.brightness, -1.0..=1.1)
-                .clamping(SliderClamping::Always)
-                .logarithmic(true)
-                .text("Brightness"),
-        );
-        ui.add(
-            Slider::new(&mut self.saturation, 0.0..=3.0)
-                .clamping(SliderClamping::Always)
-                .text("Saturation"),
-        );
-        ui.add(
-            Slider::new(&mut self.gamma, 0.1..=10.0)
-                .clamping(SliderClamping::Always)
-                .logarithmic(true)
-                .text("Gamma"),
-        );
-        ui.add(
-            Slider::new(&mut self.gamma_r, 0.1..=10.0)
-                .clamping(SliderClamping::Always)
-                .logarithmic(true)
-                .text("Gamma R"),
-        );
-        ui.add(
-            Slider::new(&mut self.gamma_g, 0.1..=10.0)
-                .clamping(SliderClamping::Always)
-                .logarithmic(true)
-                .text("Gamma G"),
-        );
-        ui.add(
-            Slider::new(&mut self.gamma_b, 0.1..=10.0)
-                .clamping(SliderClamping::Always)
-                .logarithmic(true)
-                .text("Gamma B"),
+    /// Wraps `chain` so it only applies inside the mask, see [`FilterMask`].
+    pub fn apply(&self, chain: String, input_format: Option<&str>) -> String {
+        if !self.is_active {
+            return chain;
+        }
+        let mut filter_string = format!(
+            "split[base][fg];[fg]{chain},format=rgba,\
+             geq=r=r(X\\,Y):g=g(X\\,Y):b=b(X\\,Y):a={}[graded];\
+             [base]format=rgba[ungraded];[ungraded][graded]overlay=format=auto",
+            self.alpha_expression()
         );
+        if let Some(format) = input_format {
+            write!(filter_string, ",format={format}").unwrap();
+        }
+        filter_string
     }
 
-    fn name(&self) -> &'static str {
-        "Eq"
+    fn draw(&mut self, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        ui.horizontal(|ui| {
+            response |= ui.selectable_value(&mut self.shape, MaskShape::Ellipse, "Ellipse");
+            response |= ui.selectable_value(&mut self.shape, MaskShape::Rectangle, "Rectangle");
+        });
+        let [center_x, center_y] = &mut self.center;
+        let [width, height] = &mut self.size;
+        for (label, value) in [
+            ("Center x", center_x),
+            ("Center y", center_y),
+            ("Width", width),
+            ("Height", height),
+            ("Feather", &mut self.feather),
+        ] {
+            response |= ui.add(Slider::new(value, 0.0..=1.0).text(label));
+        }
+        response |= ui.checkbox(&mut self.invert, "Invert");
+        response
     }
+}
 
-    fn is_active(&self) -> bool {
-        self.is_active
-    }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilterGroup {
+    pub name: String,
+    pub len: usize,
 }
 
-impl TryFrom<&Node<'_, '_>> for FilterEq {
-    type Error = ();
+/// Filters are trait objects, so cloning goes through their serialized form.
+impl Clone for FilterOption {
+    fn clone(&self) -> Self {
+        serde_json::from_value(serde_json::to_value(self).unwrap()).unwrap()
+    }
+}
 
-    fn try_from(value: &Node) -> Result<Self, Self::Error> {
-        if get_property_value(value, "mlt_service") != Some("avfilter.eq".to_string()) {
-            return Err(());
-        }
-        let contrast = get_property_value(value, "av.contrast").ok_or(())?;
-        let brightness = get_property_value(value, "av.brightness").ok_or(())?;
-        let saturation = get_property_value(value, "av.saturation").ok_or(())?;
-        let gamma = get_property_value(value, "av.gamma").ok_or(())?;
-        let gamma_r = get_property_value(value, "av.gamma_r").ok_or(())?;
-        let gamma_g = get_property_value(value, "av.gamma_g").ok_or(())?;
-        let gamma_b = get_property_value(value, "av.gamma_b").ok_or(())?;
-        let disabled = get_property_value(value, "disable").unwrap_or(0) == 1;
+impl FilterOption {
+    /// Sample aspect ratio of the filtered output, taken from the last active filter that sets one.
+    pub fn sample_aspect_ratio(&self, width: u32, height: u32) -> f32 {
+        self.filters
+            .iter()
+            .rev()
+            .filter(|f| f.is_active())
+            .find_map(|f| f.sample_aspect_ratio(width, height))
+            .unwrap_or(1.0)
+    }
 
-        Ok(Self {
-            is_active: !disabled,
-            contrast,
-            brightness,
-            saturation,
-            gamma,
-            gamma_r,
-            gamma_g,
-            gamma_b,
-        })
+    pub fn find<T: Filter + 'static>(&self) -> Option<&T> {
+        self.filters
+            .iter()
+            .find_map(|f| f.as_ref().as_any().downcast_ref::<T>())
     }
-}
 
-#[derive(Serialize, Deserialize)]
-pub struct FilterColortemp {
-    pub is_active: bool,
-    pub temperature: u32,
-}
+    pub fn find_mut<T: Filter + 'static>(&mut self) -> Option<&mut T> {
+        self.filters
+            .iter_mut()
+            .find_map(|f| f.as_mut().as_any_mut().downcast_mut::<T>())
+    }
 
-impl Default for FilterColortemp {
-    fn default() -> Self {
-        Self {
-            is_active: false,
-            temperature: 6500,
+    /// Makes the group lengths add up to the number of filters again.
+    fn fix_groups(&mut self) {
+        self.pinned = self.pinned.min(self.filters.len());
+        let mut remaining = self.filters.len();
+        for group in self.groups.iter_mut() {
+            group.len = group.len.min(remaining);
+            remaining -= group.len;
+        }
+        if let Some(last) = self.groups.last_mut() {
+            last.len += remaining;
+        }
+        // Pinned filters are taken from the following groups
+        let mut missing = self
+            .pinned
+            .saturating_sub(self.groups.first().map_or(self.pinned, |g| g.len));
+        for g in 1..self.groups.len() {
+            let taken = missing.min(self.groups[g].len);
+            self.groups[g].len -= taken;
+            self.groups[0].len += taken;
+            missing -= taken;
         }
     }
-}
 
-#[typetag::serde]
-impl Filter for FilterColortemp {
-    fn to_filter_string(&self) -> String {
-        format!("colortemperature=temperature={}:pl=1", self.temperature)
+    pub fn is_pinned(&self, i: usize) -> bool {
+        i < self.pinned
     }
-}
 
-#[typetag::serde]
-impl GuiElement for FilterColortemp {
-    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) {
-        ui.checkbox(&mut self.is_active, "Active");
-        ui.add(
-            Slider::new(&mut self.temperature, 1000..=40000)
-                .clamping(SliderClamping::Always)
-                .logarithmic(true)
-                .text("Temperature"),
-        );
+    fn group_ranges(&self) -> Vec<Range<usize>> {
+        let mut start = 0;
+        self.groups
+            .iter()
+            .map(|group| {
+                start += group.len;
+                start - group.len..start
+            })
+            .collect()
     }
 
-    fn name(&self) -> &'static str {
-        "Color temperature"
+    /// Filter arguments for previews, which respect [`Self::solo`].
+    pub fn preview_option_args(&self) -> Vec<String> {
+        match self.solo {
+            Some(i) if i < self.filters.len() => self.solo_option_args(i),
+            _ => self.to_option_args(),
+        }
     }
 
-    fn is_active(&self) -> bool {
-        self.is_active
+    /// Filter arguments that apply only the filter at `i`, still limited by the mask.
+    pub fn solo_option_args(&self, i: usize) -> Vec<String> {
+        vec![
+            "-vf".to_string(),
+            self.mask.apply(
+                self.filters[i].to_filter_string(),
+                self.input_format.as_deref(),
+            ),
+        ]
     }
-}
 
-impl TryFrom<&Node<'_, '_>> for FilterColortemp {
-    type Error = ();
+    /// The comma-joined chain of all active filters including the mask, if any is active.
+    pub fn filter_string(&self) -> Option<String> {
+        let filter_string = self
+            .filters
+            .iter()
+            .filter(|f| f.is_active())
+            .map(|f| f.to_filter_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        (!filter_string.is_empty())
+            .then(|| self.mask.apply(filter_string, self.input_format.as_deref()))
+    }
 
-    fn try_from(value: &Node) -> Result<Self, Self::Error> {
-        if get_property_value(value, "mlt_service") != Some("avfilter.colortemperature".to_string())
-        {
-            return Err(());
+    /// Likely mistakes in the active chain, derived from the emitted filter strings so custom
+    /// filters are covered too. They do not keep the chain from being used.
+    pub fn chain_warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        let mut scales = 0;
+        let mut size: Option<(u64, u64)> = None;
+        let segments = self
+            .filters
+            .iter()
+            .filter(|f| f.is_active())
+            .flat_map(|f| split_top_level(&f.to_filter_string(), ','))
+            .map(|s| FilterSegment::parse(&s));
+        for segment in segments {
+            match segment.name.as_str() {
+                "scale" => {
+                    scales += 1;
+                    size = get_param_value(&segment, "w", 0).zip(get_param_value(&segment, "h", 1));
+                }
+                "crop" => {
+                    let crop: Option<(u64, u64)> = get_param_value(&segment, "w", 0)
+                        .or_else(|| get_param_value(&segment, "out_w", 0))
+                        .zip(
+                            get_param_value(&segment, "h", 1)
+                                .or_else(|| get_param_value(&segment, "out_h", 1)),
+                        );
+                    if let (Some((crop_w, crop_h)), Some((w, h))) = (crop, size) {
+                        if crop_w > w || crop_h > h {
+                            warnings.push(format!(
+                                "Crop to {crop_w}x{crop_h} is larger than the scaled frame of {w}x{h}"
+                            ));
+                        }
+                    }
+                }
+                "lut3d" => match get_param_value::<String>(&segment, "file", 0) {
+                    Some(file) if file.is_empty() => {
+                        warnings.push("LUT filter has no file".to_string())
+                    }
+                    Some(file) if !Path::new(&file).is_file() => {
+                        warnings.push(format!("LUT file {file} does not exist"))
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
         }
-        let temperature = get_property_value(value, "av.temperature").ok_or(())?;
-        let disabled = get_property_value(value, "disable").unwrap_or(0) == 1;
-
-        Ok(Self {
-            is_active: !disabled,
-            temperature,
-        })
+        if scales > 1 {
+            warnings.insert(0, format!("{scales} scale filters are active"));
+        }
+        warnings.extend(self.mask_warning());
+        for filter in self.filters.iter().filter(|f| f.is_active()) {
+            let missing = self.capabilities.missing_filters(filter.as_ref());
+            if !missing.is_empty() {
+                warnings.push(format!(
+                    "{}: {}",
+                    filter.name(),
+                    self.capabilities.missing_text(&missing)
+                ));
+            }
+        }
+        warnings
     }
-}
-
-#[derive(Default, Serialize, Deserialize)]
-pub struct FilterColorBalance {
-    pub is_active: bool,
-    pub shadows_red: f32,
-    pub shadows_green: f32,
-    pub shadows_blue: f32,
-    pub midtones_red: f32,
-    pub midtones_green: f32,
-    pub midtones_blue: f32,
-    pub highlights_red: f32,
-    pub highlights_green: f32,
-    pub highlights_blue: f32,
-    pub preserve_lightness: bool,
-}
 
-#[typetag::serde]
-impl Filter for FilterColorBalance {
-    fn to_filter_string(&self) -> String {
-        format!(
-            "colorbalance=rs={}:gs={}:bs={}:rm={}:gm={}:bm={}:rh={}:gh={}:bh={}",
-            self.shadows_red,
-            self.shadows_green,
-            self.shadows_blue,
-            self.midtones_red,
-            self.midtones_green,
-            self.midtones_blue,
-            self.highlights_red,
-            self.highlights_green,
-            self.highlights_blue
-        )
+    /// The mask is laid over the unfiltered frame, which fails if the chain changes its size.
+    fn mask_warning(&self) -> Option<String> {
+        let scaled = self
+            .filters
+            .iter()
+            .filter(|f| f.is_active())
+            .any(|f| f.ffmpeg_filters().iter().any(|name| name == "scale"));
+        (self.mask.is_active && scaled)
+            .then(|| "The mask only works if no active filter scales the frame".to_string())
     }
-}
 
-#[typetag::serde]
-impl GuiElement for FilterColorBalance {
-    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) {
-        ui.checkbox(&mut self.is_active, "Active");
-        ui.label("Shadows");
-        ui.add(
-            Slider::new(&mut self.shadows_red, -1.0..=1.01)
-                .clamping(SliderClamping::Always)
-                .text("Red"),
-        );
-        ui.add(
-            Slider::new(&mut self.shadows_green, -1.0..=1.01)
-                .clamping(SliderClamping::Always)
-                .text("Green"),
-        );
-        ui.add(
-            Slider::new(&mut self.shadows_blue, -1.0..=1.01)
-                .clamping(SliderClamping::Always)
-                .text("Blue"),
-        );
-        ui.label("Midtones");
-        ui.add(
-            Slider::new(&mut self.midtones_red, -1.0..=1.01)
-                .clamping(SliderClamping::Always)
-                .text("Red"),
-        );
-        ui.add(
-            Slider::new(&mut self.midtones_green, -1.0..=1.01)
-                .clamping(SliderClamping::Always)
-                .text("Green"),
-        );
-        ui.add(
-            Slider::new(&mut self.midtones_blue, -1.0..=1.01)
-                .clamping(SliderClamping::Always)
-                .text("Blue"),
-        );
-        ui.label("Highlights");
-        ui.add(
-            Slider::new(&mut self.highlights_red, -1.0..=1.01)
-                .clamping(SliderClamping::Always)
-                .text("Red"),
-        );
-        ui.add(
-            Slider::new(&mut self.highlights_green, -1.0..=1.01)
-                .clamping(SliderClamping::Always)
-                .text("Green"),
-        );
-        ui.add(
-            Slider::new(&mut self.highlights_blue, -1.0..=1.01)
-                .clamping(SliderClamping::Always)
-                .text("Blue"),
-        );
+    /// Names of the active filters that change colors after the first active LUT. Creative
+    /// LUTs expect the grade to be done before them.
+    pub fn grading_after_lut(&self) -> Vec<&'static str> {
+        self.filters
+            .iter()
+            .filter(|f| f.is_active())
+            .map(|f| f.as_ref())
+            .skip_while(|f| !uses_lut(*f))
+            .skip(1)
+            .filter(|f| !uses_lut(*f) && is_grading(*f))
+            .map(|f| f.name())
+            .collect()
     }
 
-    fn name(&self) -> &'static str {
-        "Color balance"
+    /// Moves each active LUT filter behind the last active filter that changes colors, within
+    /// the filters it can be moved among. Returns whether a filter was moved.
+    pub fn move_luts_last(&mut self) -> bool {
+        self.fix_groups();
+        let mut moved = false;
+        let mut i = 0;
+        while i < self.filters.len() {
+            let filter = &self.filters[i];
+            if !filter.is_active()
+                || filter
+                    .as_ref()
+                    .as_any()
+                    .downcast_ref::<FilterLut>()
+                    .is_none()
+            {
+                i += 1;
+                continue;
+            }
+            let last_grading = self.movable_range(i).rev().find(|&j| {
+                let f = self.filters[j].as_ref();
+                f.is_active() && !uses_lut(f) && is_grading(f)
+            });
+            match last_grading {
+                Some(j) if j > i => {
+                    let filter = self.filters.remove(i);
+                    self.filters.insert(j, filter);
+                    self.solo = None;
+                    moved = true;
+                }
+                _ => i += 1,
+            }
+        }
+        moved
     }
 
-    fn is_active(&self) -> bool {
-        self.is_active
+    /// Indices that the filter at `i` can be moved to. Pinned filters move among themselves,
+    /// the others only behind them and within their group.
+    fn movable_range(&self, i: usize) -> Range<usize> {
+        if i < self.pinned {
+            return 0..self.pinned;
+        }
+        let range = self
+            .group_of(i)
+            .map_or(0..self.filters.len(), |g| self.group_ranges()[g].clone());
+        range.start.max(self.pinned)..range.end
     }
-}
 
-#[derive(Default, Serialize, Deserialize)]
-pub struct FilterCustom {
-    pub is_active: bool,
-    pub expression: String,
+    fn group_of(&self, i: usize) -> Option<usize> {
+        self.group_ranges().iter().position(|r| r.contains(&i))
+    }
 }
 
 #[typetag::serde]
-impl Filter for FilterCustom {
-    fn to_filter_string(&self) -> String {
-        self.expression.clone()
+impl CliOption for FilterOption {
+    fn to_option_args(&self) -> Vec<String> {
+        match self.filter_string() {
+            Some(filter_string) => vec!["-vf".to_string(), filter_string],
+            None => vec![],
+        }
     }
 }
 
 #[typetag::serde]
-impl GuiElement for FilterCustom {
-    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) {
-        ui.checkbox(&mut self.is_active, "Active");
-        ui.text_edit_singleline(&mut self.expression);
-    }
-
+impl GuiElement for FilterOption {
     fn name(&self) -> &'static str {
-        "Custom filter(s)"
+        "Filters"
     }
 
-    fn is_active(&self) -> bool {
-        self.is_active
-    }
-}
-
-pub struct Thread {
-    pub request_rx: Receiver<Request>,
-    pub response_tx: Sender<Response>,
-}
-
-impl Thread {
-    pub fn new(request_rx: Receiver<Request>, response_tx: Sender<Response>) -> Self {
-        Self {
-            request_rx,
-            response_tx,
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.allocate_response(Vec2::ZERO, Sense::hover());
+        let mut action = None;
+        let mut group_action = None;
+        if self.groups.is_empty() {
+            self.draw_filters(ctx, ui, 0..self.filters.len(), &mut response, &mut action);
+            if ui.small_button("Group filters").clicked() {
+                self.groups.push(FilterGroup {
+                    name: "Filters".to_string(),
+                    len: self.filters.len(),
+                });
+            }
+        } else {
+            self.fix_groups();
+            for (g, range) in self.group_ranges().into_iter().enumerate() {
+                let name = format!("{} ({})", self.groups[g].name, range.len());
+                let collapsing_response = CollapsingHeader::new(RichText::new(name).strong())
+                    .id_salt(("filter_group", g))
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Name");
+                            ui.text_edit_singleline(&mut self.groups[g].name);
+                        });
+                        self.draw_filters(ctx, ui, range, &mut response, &mut action);
+                    });
+                collapsing_response.header_response.context_menu(|ui| {
+                    for (label, a) in [
+                        ("Move up", GroupAction::MoveUp),
+                        ("Move down", GroupAction::MoveDown),
+                        ("Ungroup", GroupAction::Ungroup),
+                    ] {
+                        if ui.button(label).clicked() {
+                            group_action = Some((g, a));
+                            ui.close_menu();
+                        }
+                    }
+                });
+            }
+            if ui.small_button("Add group").clicked() {
+                self.groups.push(FilterGroup {
+                    name: format!("Group {}", self.groups.len() + 1),
+                    len: 0,
+                });
+            }
+        }
+        let marker = if self.mask.is_active { "●" } else { "○" };
+        CollapsingHeader::new(format!("{marker} Mask"))
+            .id_salt("filter_mask")
+            .show(ui, |ui| {
+                ui.label("Applies the filters only inside the mask. Draw it on the preview.");
+                if let Some(warning) = self.mask_warning() {
+                    ui.colored_label(ui.visuals().warn_fg_color, warning);
+                }
+                response |= self.mask.draw(ui);
+            });
+        if let Some((i, action)) = action {
+            if self.apply_action(ctx, i, action) {
+                response.mark_changed();
+            }
+        }
+        if let Some((g, action)) = group_action {
+            if self.apply_group_action(g, action) {
+                response.mark_changed();
+            }
         }
+        response
     }
+}
 
-    pub fn run(&mut self) -> ! {
-        loop {
-            if let Ok(request) = self.request_rx.recv() {
-                log::info!("Received request: {request:?}");
-                match request {
-                    Request::ExtractFrame { args, output } => {
-                        match self.extract_frame(args, output) {
-                            Ok(response) => self.response_tx.send(response).unwrap(),
-                            Err(e) => self.response_tx.send(Response::Error(e)).unwrap(),
+impl FilterOption {
+    fn draw_filters(
+        &mut self,
+        ctx: &egui::Context,
+        ui: &mut egui::Ui,
+        range: Range<usize>,
+        response: &mut egui::Response,
+        action: &mut Option<(usize, FilterAction)>,
+    ) {
+        let is_grouped = !self.groups.is_empty();
+        for (i, filter) in self.filters[range.clone()].iter_mut().enumerate() {
+            let i = range.start + i;
+            let marker = if self.solo == Some(i) {
+                "◎"
+            } else if filter.is_active() {
+                "●"
+            } else {
+                "○"
+            };
+            let is_pinned = i < self.pinned;
+            let pin = if is_pinned { " 📌" } else { "" };
+            let missing = self.capabilities.missing_filters(filter.as_ref());
+            let id = ui.make_persistent_id((filter.name(), i));
+            let mut header_clicked = false;
+            let mut preset_applied = false;
+            let (_, header_response, body_response) =
+                CollapsingState::load_with_default_open(ctx, id, false)
+                    .show_header(ui, |ui| {
+                        let mut title = RichText::new(format!("{marker} {}{pin}", filter.name()));
+                        let modified = is_modified(filter.as_ref());
+                        if modified {
+                            title = title.strong();
                         }
-                    }
-                    Request::Play { args } => {
-                        let ffmpeg_output = Command::new("ffplay").args(args).output().unwrap();
-                        if !ffmpeg_output.status.success() {
-                            log::error!(
-                                "ffmpeg output:\ncode: {}, \n{}\n{}",
-                                ffmpeg_output.status.code().unwrap(),
-                                String::from_utf8(ffmpeg_output.stdout).unwrap(),
-                                String::from_utf8(ffmpeg_output.stderr).unwrap(),
+                        if !missing.is_empty() {
+                            title = title.weak();
+                        }
+                        let mut title_response = ui.add(
+                            egui::Label::new(title)
+                                .selectable(false)
+                                .sense(Sense::click()),
+                        );
+                        if modified {
+                            title_response =
+                                title_response.on_hover_text("Differs from the defaults");
+                        }
+                        if !missing.is_empty() {
+                            title_response = title_response
+                                .on_hover_text(self.capabilities.missing_text(&missing));
+                        }
+                        header_clicked = title_response.clicked();
+                        preset_applied = draw_presets_menu(ctx, ui, id, filter);
+                        if let Some(Some([before, after])) = self.thumbnails.get(i) {
+                            ui.add_space(
+                                (ui.available_width() - before.size.x - after.size.x).max(0.),
+                            );
+                            ui.image(*before).on_hover_text("Before");
+                            ui.image(*after).on_hover_text("After");
+                        }
+                    })
+                    .body(|ui| {
+                        let mut is_solo = self.solo == Some(i);
+                        let mut response = ui
+                            .toggle_value(&mut is_solo, "Solo")
+                            .on_hover_text("Preview with only this filter applied");
+                        if response.changed() {
+                            self.solo = is_solo.then_some(i);
+                        }
+                        if missing.is_empty() {
+                            response |= filter.draw(ctx, ui);
+                        } else {
+                            ui.colored_label(
+                                ui.visuals().warn_fg_color,
+                                self.capabilities.missing_text(&missing),
                             );
+                            response |= ui.add_enabled_ui(false, |ui| filter.draw(ctx, ui)).inner;
+                        }
+                        let filter_string = filter.to_filter_string();
+                        if ui
+                            .add(
+                                egui::Label::new(RichText::new(&filter_string).monospace().weak())
+                                    .sense(Sense::click()),
+                            )
+                            .on_hover_text("Click to copy")
+                            .clicked()
+                        {
+                            ctx.copy_text(filter_string);
+                        }
+                        response
+                    });
+            if preset_applied {
+                response.mark_changed();
+            }
+            if header_clicked {
+                if let Some(mut state) = CollapsingState::load(ctx, id) {
+                    state.toggle(ui);
+                    state.store(ctx);
+                }
+            }
+            header_response.response.context_menu(|ui| {
+                for (label, filter_action) in [
+                    ("Reset", FilterAction::Reset),
+                    ("Duplicate", FilterAction::Duplicate),
+                    ("Copy", FilterAction::Copy),
+                    ("Paste", FilterAction::Paste),
+                    ("Remove", FilterAction::Remove),
+                    ("Move to top", FilterAction::MoveToTop),
+                    ("Move to bottom", FilterAction::MoveToBottom),
+                ] {
+                    if ui.button(label).clicked() {
+                        *action = Some((i, filter_action));
+                        ui.close_menu();
+                    }
+                }
+                let (label, filter_action) = if is_pinned {
+                    ("Unpin", FilterAction::Unpin)
+                } else {
+                    ("Pin to top", FilterAction::Pin)
+                };
+                if ui
+                    .button(label)
+                    .on_hover_text("Pinned filters stay at the start of the chain")
+                    .clicked()
+                {
+                    *action = Some((i, filter_action));
+                    ui.close_menu();
+                }
+                if is_grouped && !is_pinned {
+                    ui.separator();
+                    for (label, filter_action) in [
+                        ("Move to previous group", FilterAction::MoveToPreviousGroup),
+                        ("Move to next group", FilterAction::MoveToNextGroup),
+                    ] {
+                        if ui.button(label).clicked() {
+                            *action = Some((i, filter_action));
+                            ui.close_menu();
                         }
                     }
                 }
+            });
+            if let Some(filter_response) = body_response {
+                *response |= filter_response.inner;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FilterAction {
+    Reset,
+    Duplicate,
+    Copy,
+    Paste,
+    Remove,
+    MoveToTop,
+    MoveToBottom,
+    MoveToPreviousGroup,
+    MoveToNextGroup,
+    Pin,
+    Unpin,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum GroupAction {
+    MoveUp,
+    MoveDown,
+    Ungroup,
+}
+
+impl FilterOption {
+    /// Returns whether the filter chain was changed.
+    fn apply_action(&mut self, ctx: &egui::Context, i: usize, action: FilterAction) -> bool {
+        let clipboard_id = egui::Id::new("filter_clipboard");
+        self.fix_groups();
+        let ranges = self.group_ranges();
+        let group = self.group_of(i);
+        let is_pinned = i < self.pinned;
+        let range = self.movable_range(i);
+        if !matches!(action, FilterAction::Reset | FilterAction::Copy) {
+            // Indices change
+            self.solo = None;
+        }
+        match action {
+            FilterAction::Reset => self.filters[i] = self.filters[i].boxed_default(),
+            FilterAction::Duplicate => {
+                let filter = clone_filter(self.filters[i].as_ref());
+                self.filters.insert(i + 1, filter);
+                if let Some(g) = group {
+                    self.groups[g].len += 1;
+                }
+                if is_pinned {
+                    self.pinned += 1;
+                }
+            }
+            FilterAction::Copy => {
+                let filter = serde_json::to_string(self.filters[i].as_ref()).unwrap();
+                ctx.data_mut(|d| d.insert_temp(clipboard_id, filter));
+                return false;
+            }
+            FilterAction::Paste => {
+                let Some(filter) = ctx.data(|d| d.get_temp::<String>(clipboard_id)) else {
+                    return false;
+                };
+                match serde_json::from_str(&filter) {
+                    Ok(filter) => self.filters.insert(i + 1, filter),
+                    Err(e) => {
+                        log::error!("Could not paste filter: {e}");
+                        return false;
+                    }
+                }
+                if let Some(g) = group {
+                    self.groups[g].len += 1;
+                }
+                if is_pinned {
+                    self.pinned += 1;
+                }
+            }
+            FilterAction::Remove => {
+                self.filters.remove(i);
+                if let Some(g) = group {
+                    self.groups[g].len -= 1;
+                }
+                if is_pinned {
+                    self.pinned -= 1;
+                }
+            }
+            FilterAction::MoveToTop => {
+                let filter = self.filters.remove(i);
+                self.filters.insert(range.start, filter);
+            }
+            FilterAction::MoveToBottom => {
+                let filter = self.filters.remove(i);
+                self.filters.insert(range.end - 1, filter);
+            }
+            FilterAction::MoveToPreviousGroup | FilterAction::MoveToNextGroup => {
+                let Some(g) = group.filter(|_| !is_pinned) else {
+                    return false;
+                };
+                let (target, position) = match action {
+                    FilterAction::MoveToPreviousGroup if g > 0 => (g - 1, ranges[g - 1].end),
+                    FilterAction::MoveToNextGroup if g + 1 < ranges.len() => {
+                        (g + 1, ranges[g + 1].start - 1)
+                    }
+                    _ => return false,
+                };
+                let filter = self.filters.remove(i);
+                self.filters.insert(position, filter);
+                self.groups[g].len -= 1;
+                self.groups[target].len += 1;
+            }
+            FilterAction::Pin => {
+                if is_pinned {
+                    return false;
+                }
+                let filter = self.filters.remove(i);
+                self.filters.insert(self.pinned, filter);
+                if let Some(g) = group.filter(|g| *g > 0) {
+                    self.groups[g].len -= 1;
+                    self.groups[0].len += 1;
+                }
+                self.pinned += 1;
             }
+            FilterAction::Unpin => {
+                if !is_pinned {
+                    return false;
+                }
+                let filter = self.filters.remove(i);
+                self.filters.insert(self.pinned - 1, filter);
+                self.pinned -= 1;
+            }
+        }
+        true
+    }
+
+    /// Returns whether the filter chain was changed.
+    fn apply_group_action(&mut self, g: usize, action: GroupAction) -> bool {
+        self.fix_groups();
+        match action {
+            GroupAction::MoveUp if g > 0 => self.swap_groups(g - 1),
+            GroupAction::MoveDown if g + 1 < self.groups.len() => self.swap_groups(g),
+            GroupAction::Ungroup => {
+                let group = self.groups.remove(g);
+                // The filters stay where they are and join the neighbouring group.
+                if let Some(neighbour) = self.groups.get_mut(g.saturating_sub(1)) {
+                    neighbour.len += group.len;
+                }
+                return false;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Swaps group `g` with the one after it, including their filters. Pinned filters stay in
+    /// front and thus move to the new first group.
+    fn swap_groups(&mut self, g: usize) {
+        let ranges = self.group_ranges();
+        let pinned = if g == 0 { self.pinned } else { 0 };
+        self.filters[ranges[g].start + pinned..ranges[g + 1].end]
+            .rotate_left(ranges[g].len() - pinned);
+        self.groups.swap(g, g + 1);
+        self.groups[g].len += pinned;
+        self.groups[g + 1].len -= pinned;
+    }
+}
+
+pub fn clone_filter(filter: &dyn Filter) -> Box<dyn Filter> {
+    serde_json::from_value(serde_json::to_value(filter).unwrap()).unwrap()
+}
+
+/// Whether resetting `filter` would change any of its parameters. Being active is not counted.
+pub fn is_modified(filter: &dyn Filter) -> bool {
+    let parameters = |filter: &dyn Filter| {
+        let mut value = serde_json::to_value(filter).unwrap();
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("is_active");
+        }
+        value
+    };
+    parameters(filter) != parameters(filter.boxed_default().as_ref())
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct SkipOption {
+    pub seconds: u64,
+    /// Seek after decoding (`-ss` after `-i`) instead of jumping to the nearest keyframe.
+    #[serde(default)]
+    pub accurate: bool,
+}
+
+impl SkipOption {
+    pub fn args_before_input(&self) -> Vec<String> {
+        if self.accurate {
+            vec![]
+        } else {
+            self.to_option_args()
+        }
+    }
+
+    pub fn args_after_input(&self) -> Vec<String> {
+        if self.accurate {
+            self.to_option_args()
+        } else {
+            vec![]
         }
     }
+}
+
+#[typetag::serde]
+impl CliOption for SkipOption {
+    fn to_option_args(&self) -> Vec<String> {
+        vec!["-ss".to_string(), self.seconds.to_string()]
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for SkipOption {
+    fn name(&self) -> &'static str {
+        "Skip seconds"
+    }
 
-    fn extract_frame(&mut self, args: Vec<String>, output: PathBuf) -> Result<Response, String> {
-        let ffmpeg_output = Command::new("ffmpeg").args(args).output().unwrap();
-        info!("Command: {:?}", ffmpeg_output);
-        if !ffmpeg_output.status.success() {
-            log::error!(
-                "Could not extract frame:\ncode: {},\n{}\n{}",
-                ffmpeg_output.status.code().unwrap(),
-                String::from_utf8(ffmpeg_output.stdout).unwrap(),
-                String::from_utf8(ffmpeg_output.stderr).unwrap()
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.add(DragValue::new(&mut self.seconds));
+        response |= ui
+            .checkbox(&mut self.accurate, "Frame accurate")
+            .on_hover_text(
+                "Seek by decoding up to the position (-ss after -i). Exact, but slow for long \
+                 skips. Otherwise ffmpeg seeks in the input, which is fast but may land on a \
+                 nearby keyframe for some formats.",
             );
-            return Err("Could not extract frame!".to_string());
+        response
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct NumberOfFramesOption {
+    pub frames: u64,
+}
+
+#[typetag::serde]
+impl CliOption for NumberOfFramesOption {
+    fn to_option_args(&self) -> Vec<String> {
+        vec!["-frames:v".to_string(), self.frames.to_string()]
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for NumberOfFramesOption {
+    fn name(&self) -> &'static str {
+        "Namber of frames"
+    }
+
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        ui.add(DragValue::new(&mut self.frames))
+    }
+}
+
+/// Forces the frame rate of the input (`-r` before `-i`).
+#[derive(Serialize, Deserialize)]
+pub struct InputFramerateOption {
+    pub is_active: bool,
+    pub framerate: Rational,
+}
+
+impl Default for InputFramerateOption {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            framerate: Rational { num: 25, den: 1 },
         }
-        info!("Output: {:?}", output);
-        let img = ImageReader::open(output).unwrap().decode().unwrap();
-        Ok(Response::Image(img.into_rgba8()))
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use roxmltree::Document;
+#[typetag::serde]
+impl CliOption for InputFramerateOption {
+    fn to_option_args(&self) -> Vec<String> {
+        vec!["-r".to_string(), self.framerate.to_string()]
+    }
 
-    use super::*;
+    fn placement(&self) -> OptionPlacement {
+        OptionPlacement::BeforeInput
+    }
+}
 
-    #[test]
-    fn exposure_from_xml() {
-        let xml = r#"
-               <filter id="filter6">
-                <property name="mlt_service">avfilter.exposure</property>
-                <property name="kdenlive_id">avfilter.exposure</property>
-                <property name="av.exposure">00:00:00.000=0</property>
-                <property name="av.black">00:00:00.000=0</property>
-                <property name="kdenlive:collapsed">1</property>
-                <property name="disable">1</property>
-               </filter>
-            "#;
-        let doc = Document::parse(xml).unwrap();
-        let root = &doc.root();
+#[typetag::serde]
+impl GuiElement for InputFramerateOption {
+    fn name(&self) -> &'static str {
+        "Input frame rate"
+    }
 
-        let filter = root.try_into();
-        assert_eq!(
-            filter,
-            Ok(FilterExposure {
-                is_active: false,
-                exposure: 0.0,
-                black: 0.0
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        ui.horizontal(|ui| {
+            ui.label("Frame rate")
+                .on_hover_text("Frame rate assumed for the input, e.g. for image sequences");
+            response |= rational_edit(ui, "framerate", &mut self.framerate, "24000/1001");
+        });
+        response
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+/// Ignores the audio of the input (`-an` before `-i`).
+#[derive(Default, Serialize, Deserialize)]
+pub struct NoAudioOption {
+    pub is_active: bool,
+}
+
+#[typetag::serde]
+impl CliOption for NoAudioOption {
+    fn to_option_args(&self) -> Vec<String> {
+        vec!["-an".to_string()]
+    }
+
+    fn placement(&self) -> OptionPlacement {
+        OptionPlacement::BeforeInput
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for NoAudioOption {
+    fn name(&self) -> &'static str {
+        "Ignore input audio"
+    }
+
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        ui.checkbox(&mut self.is_active, "Active")
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+/// Decodes the input with hardware acceleration (`-hwaccel` before `-i`).
+#[derive(Serialize, Deserialize)]
+pub struct HwaccelOption {
+    pub is_active: bool,
+    pub method: String,
+}
+
+impl Default for HwaccelOption {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            method: "auto".to_string(),
+        }
+    }
+}
+
+#[typetag::serde]
+impl CliOption for HwaccelOption {
+    fn to_option_args(&self) -> Vec<String> {
+        vec!["-hwaccel".to_string(), self.method.trim().to_string()]
+    }
+
+    fn placement(&self) -> OptionPlacement {
+        OptionPlacement::BeforeInput
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for HwaccelOption {
+    fn name(&self) -> &'static str {
+        "Hardware decoding"
+    }
+
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        let mut changed = false;
+        response |= ComboBox::from_label("Method")
+            .selected_text(self.method.as_str())
+            .show_ui(ui, |ui| {
+                for method in ["auto", "cuda", "vaapi", "qsv", "videotoolbox", "d3d11va"] {
+                    changed |= ui
+                        .selectable_value(&mut self.method, method.to_string(), method)
+                        .changed();
+                }
             })
+            .response;
+        if changed {
+            response.mark_changed();
+        }
+        response
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+pub type CliOptionFactory = fn() -> Box<dyn CliOption>;
+
+/// The input options that every file state offers, as factories by option name.
+pub const INPUT_OPTION_REGISTRY: &[(&str, CliOptionFactory)] = &[
+    ("Input frame rate", || {
+        Box::<InputFramerateOption>::default()
+    }),
+    ("Ignore input audio", || Box::<NoAudioOption>::default()),
+    ("Hardware decoding", || Box::<HwaccelOption>::default()),
+];
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct InputFile {
+    pub path: PathBuf,
+    #[serde(skip)]
+    pub dialog: Option<FileDialog>,
+    #[serde(skip)]
+    exif: Option<(PathBuf, Result<ExifFields, String>)>,
+    /// Index among the video streams (`0:v:N`), `None` lets ffmpeg choose.
+    #[serde(default)]
+    pub video_stream: Option<usize>,
+    #[serde(skip)]
+    pub video_streams: Vec<VideoStream>,
+    #[serde(default)]
+    pub range: InputRange,
+    /// Where the file dialog starts if `path` does not point anywhere, provided by the GUI.
+    #[serde(skip)]
+    pub default_dir: Option<PathBuf>,
+}
+
+/// Level range of a YUV source.
+///
+/// Limited ("tv") range puts black at 16 and white at 235, full ("pc") range uses 0 to 255.
+/// A forced range is tagged at the head of the filter chain, so the filters, the expansion to
+/// full range RGB for previews and conversions all read the source the same way. If a file is
+/// tagged wrongly, forcing the range fixes crushed or washed out results.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputRange {
+    /// Use the range the file is tagged with, limited if it is untagged.
+    #[default]
+    Auto,
+    Limited,
+    Full,
+}
+
+impl InputRange {
+    /// Whether the source is full range, `detected` is the range ffprobe reported.
+    pub fn is_full(self, detected: Option<&str>) -> bool {
+        match self {
+            Self::Auto => detected == Some("pc"),
+            Self::Limited => false,
+            Self::Full => true,
+        }
+    }
+
+    pub fn to_filter_string(&self) -> Option<String> {
+        match self {
+            Self::Auto => None,
+            Self::Limited => Some("setparams=range=limited".to_string()),
+            Self::Full => Some("setparams=range=full".to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for InputRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auto => write!(f, "Auto (as tagged)"),
+            Self::Limited => write!(f, "Limited (16-235)"),
+            Self::Full => write!(f, "Full (0-255)"),
+        }
+    }
+}
+
+type ExifFields = Vec<(String, String)>;
+
+#[typetag::serde]
+impl CliOption for InputFile {
+    fn to_option_args(&self) -> Vec<String> {
+        vec!["-i".to_string(), self.path.to_string_lossy().to_string()]
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for InputFile {
+    fn name(&self) -> &'static str {
+        "Input file"
+    }
+
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut path = self.path.to_string_lossy();
+        let mut response = ui.text_edit_singleline(path.to_mut());
+        if response.changed() {
+            self.path = PathBuf::from(path.to_string());
+            self.input_changed();
+        }
+        if ui.button("Open").clicked() {
+            let mut dialog = FileDialog::open_file(if self.path.is_dir() || self.path.is_file() {
+                Some(self.path.clone())
+            } else {
+                self.default_dir.clone().filter(|dir| dir.is_dir())
+            });
+            dialog.open();
+            self.dialog = Some(dialog);
+        }
+        if let Some(dialog) = &mut self.dialog {
+            if dialog.show(ctx).selected() {
+                if let Some(path) = dialog.path() {
+                    self.path = path.to_path_buf();
+                    self.input_changed();
+                    response.mark_changed();
+                }
+            }
+        }
+        if self.video_streams.len() > 1 {
+            let selected = self
+                .video_stream
+                .and_then(|n| self.video_streams.get(n))
+                .map_or("Automatic".to_string(), ToString::to_string);
+            ComboBox::from_label("Video stream")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for (n, stream) in self.video_streams.iter().enumerate() {
+                        if ui
+                            .selectable_value(&mut self.video_stream, Some(n), stream.to_string())
+                            .clicked()
+                        {
+                            response.mark_changed();
+                        }
+                    }
+                });
+        }
+        ComboBox::from_label("Source range")
+            .selected_text(self.range.to_string())
+            .show_ui(ui, |ui| {
+                for range in [InputRange::Auto, InputRange::Limited, InputRange::Full] {
+                    if ui
+                        .selectable_value(&mut self.range, range, range.to_string())
+                        .clicked()
+                    {
+                        response.mark_changed();
+                    }
+                }
+            })
+            .response
+            .on_hover_text("Overrides the range the file is tagged with");
+        if is_image_file(&self.path) {
+            self.draw_exif(ui);
+        }
+        response
+    }
+}
+
+impl InputFile {
+    /// `-map` arguments for ffmpeg. Audio is kept for conversions.
+    pub fn map_args(&self, with_audio: bool) -> Vec<String> {
+        let Some(n) = self.video_stream else {
+            return vec![];
+        };
+        let mut args = vec!["-map".to_string(), format!("0:v:{n}")];
+        if with_audio {
+            args.extend(["-map".to_string(), "0:a?".to_string()]);
+        }
+        args
+    }
+
+    /// ffplay has no `-map`, it selects streams with `-vst`.
+    pub fn ffplay_stream_args(&self) -> Vec<String> {
+        self.video_stream
+            .map(|n| vec!["-vst".to_string(), format!("v:{n}")])
+            .unwrap_or_default()
+    }
+
+    /// Uses the first video stream if there is a choice to make. A selection the file does not
+    /// have, e.g. left over from a previous input, is dropped.
+    pub fn set_video_streams(&mut self, video_streams: Vec<VideoStream>) {
+        self.video_stream = match self.video_stream {
+            Some(n) if n < video_streams.len() => Some(n),
+            _ if video_streams.len() > 1 => Some(0),
+            _ => None,
+        };
+        self.video_streams = video_streams;
+    }
+
+    /// Forgets what was probed about the previous input.
+    pub fn input_changed(&mut self) {
+        self.video_stream = None;
+        self.video_streams.clear();
+    }
+
+    fn draw_exif(&mut self, ui: &mut egui::Ui) {
+        if self.exif.as_ref().map(|(path, _)| path) != Some(&self.path) {
+            self.exif = Some((self.path.clone(), read_exif(&self.path)));
+        }
+        let Some((_, exif)) = &self.exif else {
+            return;
+        };
+        CollapsingHeader::new("EXIF").show(ui, |ui| match exif {
+            Ok(fields) if !fields.is_empty() => {
+                egui::Grid::new("exif").striped(true).show(ui, |ui| {
+                    for (name, value) in fields {
+                        ui.label(name);
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                });
+            }
+            Ok(_) => {
+                ui.label("No EXIF data");
+            }
+            Err(e) => {
+                ui.label(format!("No EXIF data: {e}"));
+            }
+        });
+    }
+}
+
+pub(crate) fn is_image_file(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+        [
+            "jpg", "jpeg", "tif", "tiff", "png", "webp", "heic", "heif", "dng",
+        ]
+        .contains(&e.to_lowercase().as_str())
+    })
+}
+
+fn read_exif(path: &Path) -> Result<ExifFields, String> {
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(file))
+        .map_err(|e| e.to_string())?;
+    let tags = [
+        ("Make", exif::Tag::Make),
+        ("Model", exif::Tag::Model),
+        ("Lens", exif::Tag::LensModel),
+        ("ISO", exif::Tag::PhotographicSensitivity),
+        ("Shutter", exif::Tag::ExposureTime),
+        ("Aperture", exif::Tag::FNumber),
+        ("Focal length", exif::Tag::FocalLength),
+        ("White balance", exif::Tag::WhiteBalance),
+        ("Date", exif::Tag::DateTimeOriginal),
+    ];
+    Ok(tags
+        .into_iter()
+        .filter_map(|(name, tag)| {
+            exif.get_field(tag, exif::In::PRIMARY).map(|field| {
+                (
+                    name.to_string(),
+                    field.display_value().with_unit(&exif).to_string(),
+                )
+            })
+        })
+        .collect())
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct OutputFile {
+    pub path: PathBuf,
+    #[serde(skip)]
+    pub dialog: Option<FileDialog>,
+    /// Where the file dialog starts if `path` does not point anywhere, provided by the GUI.
+    #[serde(skip)]
+    pub default_dir: Option<PathBuf>,
+}
+
+#[typetag::serde]
+impl CliOption for OutputFile {
+    fn to_option_args(&self) -> Vec<String> {
+        vec![self.path.to_string_lossy().to_string()]
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for OutputFile {
+    fn name(&self) -> &'static str {
+        "Output file"
+    }
+
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut path = self.path.to_string_lossy();
+        let mut response = ui.text_edit_singleline(path.to_mut());
+        if response.changed() {
+            self.path = PathBuf::from(path.to_string());
+        }
+        if ui.button("Open").clicked() {
+            let mut dialog = FileDialog::save_file(
+                if self.path.is_file() {
+                    Some(self.path.clone())
+                } else if let Some(parent) = self.path.parent() {
+                    if parent.is_dir() {
+                        Some(parent.to_path_buf())
+                    } else {
+                        None
+                    }
+                } else {
+                    None
+                }
+                .or_else(|| self.default_dir.clone().filter(|dir| dir.is_dir())),
+            );
+            dialog.open();
+            self.dialog = Some(dialog);
+        }
+        if let Some(dialog) = &mut self.dialog {
+            if dialog.show(ctx).selected() {
+                if let Some(path) = dialog.path() {
+                    self.path = path.to_path_buf();
+                    response.mark_changed();
+                }
+            }
+        }
+        if ui.button("Show in folder").clicked() {
+            if let Err(e) = show_in_folder(&self.path) {
+                log::error!("Could not open file manager: {e}");
+            }
+        }
+        response
+    }
+}
+
+/// Reveals `path` in the platform file manager or, if it does not exist yet, opens its parent.
+pub fn show_in_folder(path: &Path) -> std::io::Result<()> {
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("explorer");
+        if path.is_file() {
+            command.arg(format!("/select,{}", path.display()));
+        } else {
+            command.arg(parent);
+        }
+        command
+    } else if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        if path.is_file() {
+            command.arg("-R").arg(path);
+        } else {
+            command.arg(parent);
+        }
+        command
+    } else {
+        let mut command = Command::new("xdg-open");
+        command.arg(parent);
+        command
+    };
+    info!("Show in folder: {command:?}");
+    command.spawn().map(|_| ())
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Encoder {
+    pub expression: String,
+}
+
+#[typetag::serde]
+impl CliOption for Encoder {
+    fn to_option_args(&self) -> Vec<String> {
+        if self.expression.is_empty() {
+            vec![]
+        } else {
+            vec!["-c:v".to_string(), self.expression.clone()]
+        }
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for Encoder {
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        ui.text_edit_singleline(&mut self.expression)
+    }
+
+    fn name(&self) -> &'static str {
+        "Encoder"
+    }
+}
+
+const FILTER_METADATA_MAX_LEN: usize = 200;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct FilterMetadata {
+    pub is_active: bool,
+}
+
+impl FilterMetadata {
+    /// The chain as it is emitted, mask included, as a `comment` tag. The arguments are
+    /// passed to ffmpeg as they are, quoting for a shell is up to the caller.
+    pub fn to_option_args(&self, filter_option: &FilterOption) -> Vec<String> {
+        match filter_option.filter_string() {
+            Some(summary) if self.is_active => vec![
+                "-metadata".to_string(),
+                format!("comment={}", metadata_summary(&summary)),
+            ],
+            _ => vec![],
+        }
+    }
+}
+
+/// `summary` without control characters, at most [`FILTER_METADATA_MAX_LEN`] characters long.
+fn metadata_summary(summary: &str) -> String {
+    let summary = summary
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c });
+    if summary.clone().count() > FILTER_METADATA_MAX_LEN {
+        summary
+            .take(FILTER_METADATA_MAX_LEN - 3)
+            .chain("...".chars())
+            .collect()
+    } else {
+        summary.collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SlatePosition {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    #[default]
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+impl SlatePosition {
+    const ALL: [Self; 6] = [
+        Self::TopLeft,
+        Self::TopCenter,
+        Self::TopRight,
+        Self::BottomLeft,
+        Self::BottomCenter,
+        Self::BottomRight,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::TopLeft => "Top left",
+            Self::TopCenter => "Top center",
+            Self::TopRight => "Top right",
+            Self::BottomLeft => "Bottom left",
+            Self::BottomCenter => "Bottom center",
+            Self::BottomRight => "Bottom right",
+        }
+    }
+}
+
+/// Text burned into converted files with `drawtext`, one filter per line, placed inside the
+/// title safe area (10% margins).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Slate {
+    pub is_active: bool,
+    pub clip_name: bool,
+    pub text: String,
+    /// Running time of the output, starting at the skip seconds.
+    pub timecode: bool,
+    /// Date of the conversion.
+    pub date: bool,
+    pub position: SlatePosition,
+    pub font_size: u32,
+    pub background: bool,
+    pub background_opacity: f32,
+}
+
+impl Default for Slate {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            clip_name: true,
+            text: String::new(),
+            timecode: true,
+            date: true,
+            position: SlatePosition::default(),
+            font_size: 24,
+            background: true,
+            background_opacity: 0.5,
+        }
+    }
+}
+
+impl Slate {
+    /// The `drawtext` filters for the active lines, if any.
+    pub fn filter_string(&self, clip_name: &str, start_seconds: u64) -> Option<String> {
+        if !self.is_active {
+            return None;
+        }
+        // Literal text is drawn without expansion, so only the option separators are special
+        let mut lines = vec![];
+        if self.clip_name && !clip_name.is_empty() {
+            lines.push(format!(
+                "text='{}':expansion=none",
+                escape_drawtext(clip_name)
+            ));
+        }
+        if !self.text.trim().is_empty() {
+            lines.push(format!(
+                "text='{}':expansion=none",
+                escape_drawtext(self.text.trim())
+            ));
+        }
+        if self.timecode {
+            lines.push(format!("text='%{{pts\\:hms\\:{start_seconds}}}'"));
+        }
+        if self.date {
+            lines.push("text='%{localtime\\:%Y-%m-%d}'".to_string());
+        }
+        if lines.is_empty() {
+            return None;
+        }
+        let line_height = self.font_size.max(1) * 5 / 4;
+        let x = match self.position {
+            SlatePosition::TopLeft | SlatePosition::BottomLeft => "w*0.1",
+            SlatePosition::TopCenter | SlatePosition::BottomCenter => "(w-text_w)/2",
+            SlatePosition::TopRight | SlatePosition::BottomRight => "w*0.9-text_w",
+        };
+        let is_top = matches!(
+            self.position,
+            SlatePosition::TopLeft | SlatePosition::TopCenter | SlatePosition::TopRight
+        );
+        let mut box_options = String::new();
+        if self.background {
+            box_options = format!(
+                ":box=1:boxcolor=black@{}:boxborderw={}",
+                format_param(self.background_opacity.clamp(0., 1.)),
+                self.font_size.max(1) / 4
+            );
+        }
+        let count = lines.len() as u32;
+        let filters: Vec<_> = lines
+            .into_iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let i = i as u32;
+                let y = if is_top {
+                    format!("h*0.1+{}", i * line_height)
+                } else {
+                    format!("h*0.9-{}", (count - i) * line_height)
+                };
+                format!(
+                    "drawtext={text}:x={x}:y={y}:fontsize={}:fontcolor=white{box_options}",
+                    self.font_size.max(1)
+                )
+            })
+            .collect();
+        Some(filters.join(","))
+    }
+}
+
+/// Escapes `text` for a single quoted `drawtext` option in a filtergraph. Single quotes
+/// cannot be escaped inside the quotes and are replaced.
+fn escape_drawtext(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ':' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\'' => escaped.push('’'),
+            c if c.is_control() => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[typetag::serde]
+impl GuiElement for Slate {
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Burn into conversions");
+        response |= ui.checkbox(&mut self.clip_name, "Clip name");
+        response |= ui.add(egui::TextEdit::singleline(&mut self.text).hint_text("Text"));
+        response |= ui
+            .checkbox(&mut self.timecode, "Timecode")
+            .on_hover_text("Time of the output, starting at the skip seconds");
+        response |= ui
+            .checkbox(&mut self.date, "Date")
+            .on_hover_text("Date of the conversion");
+        let mut changed = false;
+        response |= ComboBox::from_label("Position")
+            .selected_text(self.position.label())
+            .show_ui(ui, |ui| {
+                for position in SlatePosition::ALL {
+                    changed |= ui
+                        .selectable_value(&mut self.position, position, position.label())
+                        .changed();
+                }
+            })
+            .response;
+        if changed {
+            response.mark_changed();
+        }
+        response |= ui.add(Slider::new(&mut self.font_size, 8..=200).text("Font size"));
+        ui.horizontal(|ui| {
+            response |= ui.checkbox(&mut self.background, "Background");
+            response |= ui.add_enabled(
+                self.background,
+                Slider::new(&mut self.background_opacity, 0.0..=1.0).text("Opacity"),
+            );
+        });
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Slate"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterMetadata {
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        ui.checkbox(&mut self.is_active, "Embed filters as comment")
+    }
+
+    fn name(&self) -> &'static str {
+        "Filter metadata"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct FilterExposure {
+    pub is_active: bool,
+    pub exposure: f32,
+    pub black: f32,
+    /// Show buttons that nudge the exposure in stops instead of the absolute slider.
+    #[serde(skip)]
+    pub relative: bool,
+    /// Strength of the highlight shoulder, 0 lets highlights clip.
+    #[serde(default)]
+    pub knee: f32,
+}
+
+const EXPOSURE_CURVE_SAMPLES: u16 = 16;
+
+impl FilterExposure {
+    const RANGE: std::ops::RangeInclusive<f32> = -3.0..=3.0;
+    const DEFAULT_KNEE: f32 = 0.5;
+
+    pub fn nudge(&mut self, stops: f32) {
+        self.exposure = (self.exposure + stops).clamp(*Self::RANGE.start(), *Self::RANGE.end());
+    }
+
+    /// The exposure filter's mapping of a pixel value.
+    fn scale(&self) -> f32 {
+        1. / ((-self.exposure).exp2() - self.black)
+    }
+
+    /// Rolls values above `1 - knee` off towards 1 instead of clipping them.
+    fn shoulder(&self, value: f32) -> f32 {
+        let threshold = 1. - self.knee;
+        if value <= threshold {
+            value
+        } else {
+            threshold + self.knee * (1. - (-(value - threshold) / self.knee).exp())
+        }
+    }
+
+    /// `curves` filter that goes before the exposure filter and compresses the values that
+    /// would otherwise clip, so the exposed result follows [`Self::shoulder`]. `None` if
+    /// nothing clips.
+    fn shoulder_curve(&self) -> Option<String> {
+        let scale = self.scale();
+        if self.knee <= 0. || (1. - self.black) * scale <= 1. {
+            return None;
+        }
+        let points = (0..=EXPOSURE_CURVE_SAMPLES)
+            .map(|i| {
+                let x = f32::from(i) / f32::from(EXPOSURE_CURVE_SAMPLES);
+                let exposed = (x - self.black) * scale;
+                let y = (self.shoulder(exposed) / scale + self.black).clamp(0., 1.);
+                format!("{x}/{}", (y * 1000.).round() / 1000.)
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        Some(format!("curves=master='{points}'"))
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterExposure {
+    fn to_filter_string(&self) -> String {
+        let exposure = format!(
+            "exposure=exposure={}:black={}",
+            format_param(self.exposure),
+            format_param(self.black)
+        );
+        match self.shoulder_curve() {
+            Some(curve) => format!("{curve},{exposure}"),
+            None => exposure,
+        }
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterExposure {
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.relative, false, "Absolute");
+            ui.selectable_value(&mut self.relative, true, "Relative");
+        });
+        if self.relative {
+            let mut nudged = false;
+            ui.horizontal(|ui| {
+                for (label, stops) in [("-1", -1.), ("-⅓", -1. / 3.), ("+⅓", 1. / 3.), ("+1", 1.)]
+                {
+                    if ui.button(label).on_hover_text("Stops").clicked() {
+                        self.nudge(stops);
+                        nudged = true;
+                    }
+                }
+                ui.label(format!("Exposure: {:+.2} EV", self.exposure));
+            });
+            if nudged {
+                response.mark_changed();
+            }
+        } else {
+            response |= ui.add(param_slider(
+                ctx,
+                self.name(),
+                &mut self.exposure,
+                Self::RANGE,
+                "Exposure",
+            ));
+        }
+        response |= ui.add(param_slider(
+            ctx,
+            self.name(),
+            &mut self.black,
+            -1.0..=1.0,
+            "Black level",
+        ));
+        let mut protect = self.knee > 0.;
+        let protect_response = ui
+            .checkbox(&mut protect, "Protect highlights")
+            .on_hover_text("Roll highlights off instead of clipping them when raising exposure");
+        if protect_response.changed() {
+            self.knee = if protect { Self::DEFAULT_KNEE } else { 0. };
+        }
+        response |= protect_response;
+        if protect {
+            response |= ui.add(param_slider(
+                ctx,
+                self.name(),
+                &mut self.knee,
+                0.05..=1.0,
+                "Knee",
+            ));
+        }
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Exposure"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+impl TryFrom<&Node<'_, '_>> for FilterExposure {
+    type Error = ();
+
+    fn try_from(value: &Node) -> Result<Self, Self::Error> {
+        if get_property_value(value, "mlt_service") != Some("avfilter.exposure".to_string()) {
+            return Err(());
+        }
+        let exposure = get_property_value(value, "av.exposure").ok_or(())?;
+        let black = get_property_value(value, "av.black").ok_or(())?;
+        let disabled = get_property_value(value, "disable").unwrap_or(0) == 1;
+
+        Ok(Self {
+            is_active: !disabled,
+            exposure,
+            black,
+            relative: false,
+            knee: 0.,
+        })
+    }
+}
+
+impl TryFrom<&FilterSegment> for FilterExposure {
+    type Error = ();
+
+    fn try_from(value: &FilterSegment) -> Result<Self, Self::Error> {
+        if value.name != "exposure" || !value.has_only_params(&["exposure", "black"]) {
+            return Err(());
+        }
+        let default = Self::default();
+
+        Ok(Self {
+            is_active: true,
+            exposure: get_param_value_or(value, "exposure", 0, default.exposure)?,
+            black: get_param_value_or(value, "black", 1, default.black)?,
+            relative: false,
+            knee: 0.,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FilterLut {
+    pub is_active: bool,
+    pub file: String,
+    pub interpolation: String,
+}
+
+impl Default for FilterLut {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            file: String::new(),
+            interpolation: "tetrahedral".to_string(),
+        }
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterLut {
+    fn to_filter_string(&self) -> String {
+        format!("lut3d=file={}:interp={}", self.file, self.interpolation)
+    }
+}
+
+impl TryFrom<&Node<'_, '_>> for FilterLut {
+    type Error = ();
+
+    fn try_from(value: &Node) -> Result<Self, Self::Error> {
+        if get_property_value(value, "mlt_service") != Some("avfilter.lut3d".to_string()) {
+            return Err(());
+        }
+        let file = get_property_value(value, "av.file").ok_or(())?;
+        let interpolation = get_property_value(value, "av.interp").ok_or(())?;
+        let disabled = get_property_value(value, "disable").unwrap_or(0) == 1;
+        if let Some(clut) = get_property_value::<String>(value, "av.clut").filter(|c| c != "all") {
+            log::warn!("Skipped clut={clut} of lut3d, the LUT is always applied to all frames");
+        }
+
+        Ok(Self {
+            is_active: !disabled,
+            file,
+            interpolation,
+        })
+    }
+}
+
+impl TryFrom<&FilterSegment> for FilterLut {
+    type Error = ();
+
+    fn try_from(value: &FilterSegment) -> Result<Self, Self::Error> {
+        if value.name != "lut3d" || !value.has_only_params(&["file", "clut", "interp"]) {
+            return Err(());
+        }
+        // Only the default can be written back
+        if get_param_value_or(value, "clut", 1, "all".to_string())? != "all" {
+            return Err(());
+        }
+        let default = Self::default();
+
+        Ok(Self {
+            is_active: true,
+            file: get_param_value(value, "file", 0).ok_or(())?,
+            interpolation: get_param_value_or(value, "interp", 2, default.interpolation)?,
+        })
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterLut {
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        response |= ui.text_edit_singleline(&mut self.file);
+        let mut changed = false;
+        response |= ComboBox::from_label("Interpolation")
+            .selected_text(&self.interpolation)
+            .show_ui(ui, |ui| {
+                for interpolation in ["nearest", "trilinear", "tetrahedral"] {
+                    changed |= ui
+                        .selectable_value(
+                            &mut self.interpolation,
+                            interpolation.to_string(),
+                            interpolation,
+                        )
+                        .changed();
+                }
+            })
+            .response;
+        if changed {
+            response.mark_changed();
+        }
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "LUT"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct FilterScale {
+    pub is_active: bool,
+    pub width: u64,
+    pub height: u64,
+}
+
+#[typetag::serde]
+impl Filter for FilterScale {
+    fn to_filter_string(&self) -> String {
+        format!("scale={}:{}", self.width, self.height)
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterScale {
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        response |= ui
+            .horizontal(|ui| {
+                ui.label("Width");
+                ui.add(DragValue::new(&mut self.width))
+            })
+            .inner;
+        response |= ui
+            .horizontal(|ui| {
+                ui.label("Heigth");
+                ui.add(DragValue::new(&mut self.height))
+            })
+            .inner;
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Scale"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+impl TryFrom<&FilterSegment> for FilterScale {
+    type Error = ();
+
+    fn try_from(value: &FilterSegment) -> Result<Self, Self::Error> {
+        if value.name != "scale" || !value.has_only_params(&["w", "h"]) {
+            return Err(());
+        }
+
+        Ok(Self {
+            is_active: true,
+            width: get_param_value(value, "w", 0).ok_or(())?,
+            height: get_param_value(value, "h", 1).ok_or(())?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FilterEq {
+    pub is_active: bool,
+    pub contrast: f32,
+    pub brightness: f32,
+    pub saturation: f32,
+    pub gamma: f32,
+    pub gamma_r: f32,
+    pub gamma_g: f32,
+    pub gamma_b: f32,
+}
+
+impl Default for FilterEq {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            contrast: 1.,
+            brightness: 0.,
+            saturation: 1.,
+            gamma: 1.,
+            gamma_r: 1.,
+            gamma_g: 1.,
+            gamma_b: 1.,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterEq {
+    fn to_filter_string(&self) -> String {
+        format!(
+            "eq=contrast={}:brightness={}:saturation={}:gamma={}:gamma_r={}:gamma_g={}:gamma_b={}",
+            format_param(self.contrast),
+            format_param(self.brightness),
+            format_param(self.saturation),
+            format_param(self.gamma),
+            format_param(self.gamma_r),
+            format_param(self.gamma_g),
+            format_param(self.gamma_b)
+        )
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterEq {
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        response |= ui.add(
+            param_slider(ctx, self.name(), &mut self.contrast, 0.0..=3.0, "Contrast")
+                .logarithmic(true),
+        );
+        response |= ui.add(
+            param_slider(
+                ctx,
+                self.name(),
+                &mut self.brightness,
+                -1.0..=1.1,
+                "Brightness",
+            )
+            .logarithmic(true),
+        );
+        response |= ui.add(param_slider(
+            ctx,
+            self.name(),
+            &mut self.saturation,
+            0.0..=3.0,
+            "Saturation",
+        ));
+        response |= ui.add(
+            param_slider(ctx, self.name(), &mut self.gamma, 0.1..=10.0, "Gamma").logarithmic(true),
+        );
+        response |= ui.add(
+            param_slider(ctx, self.name(), &mut self.gamma_r, 0.1..=10.0, "Gamma R")
+                .logarithmic(true),
+        );
+        response |= ui.add(
+            param_slider(ctx, self.name(), &mut self.gamma_g, 0.1..=10.0, "Gamma G")
+                .logarithmic(true),
+        );
+        response |= ui.add(
+            param_slider(ctx, self.name(), &mut self.gamma_b, 0.1..=10.0, "Gamma B")
+                .logarithmic(true),
+        );
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Eq"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+impl TryFrom<&Node<'_, '_>> for FilterEq {
+    type Error = ();
+
+    fn try_from(value: &Node) -> Result<Self, Self::Error> {
+        if get_property_value(value, "mlt_service") != Some("avfilter.eq".to_string()) {
+            return Err(());
+        }
+        let contrast = get_property_value(value, "av.contrast").ok_or(())?;
+        let brightness = get_property_value(value, "av.brightness").ok_or(())?;
+        let saturation = get_property_value(value, "av.saturation").ok_or(())?;
+        let gamma = get_property_value(value, "av.gamma").ok_or(())?;
+        let gamma_r = get_property_value(value, "av.gamma_r").ok_or(())?;
+        let gamma_g = get_property_value(value, "av.gamma_g").ok_or(())?;
+        let gamma_b = get_property_value(value, "av.gamma_b").ok_or(())?;
+        let disabled = get_property_value(value, "disable").unwrap_or(0) == 1;
+
+        Ok(Self {
+            is_active: !disabled,
+            contrast,
+            brightness,
+            saturation,
+            gamma,
+            gamma_r,
+            gamma_g,
+            gamma_b,
+        })
+    }
+}
+
+impl TryFrom<&FilterSegment> for FilterEq {
+    type Error = ();
+
+    fn try_from(value: &FilterSegment) -> Result<Self, Self::Error> {
+        if value.name != "eq"
+            || !value.has_only_params(&[
+                "contrast",
+                "brightness",
+                "saturation",
+                "gamma",
+                "gamma_r",
+                "gamma_g",
+                "gamma_b",
+            ])
+        {
+            return Err(());
+        }
+        let default = Self::default();
+
+        Ok(Self {
+            is_active: true,
+            contrast: get_param_value_or(value, "contrast", 0, default.contrast)?,
+            brightness: get_param_value_or(value, "brightness", 1, default.brightness)?,
+            saturation: get_param_value_or(value, "saturation", 2, default.saturation)?,
+            gamma: get_param_value_or(value, "gamma", 3, default.gamma)?,
+            gamma_r: get_param_value_or(value, "gamma_r", 4, default.gamma_r)?,
+            gamma_g: get_param_value_or(value, "gamma_g", 5, default.gamma_g)?,
+            gamma_b: get_param_value_or(value, "gamma_b", 6, default.gamma_b)?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterColortemp {
+    pub is_active: bool,
+    pub temperature: u32,
+    pub mix: f32,
+    /// Green (negative) to magenta (positive) shift applied through `colorbalance`.
+    pub tint: f32,
+}
+
+impl Default for FilterColortemp {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            temperature: 6500,
+            mix: 1.,
+            tint: 0.,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterColortemp {
+    fn to_filter_string(&self) -> String {
+        let mut filter_string = format!(
+            "colortemperature=temperature={}:mix={}:pl=1",
+            self.temperature,
+            format_param(self.mix)
+        );
+        if self.tint != 0. {
+            write!(
+                filter_string,
+                ",colorbalance=rm={}:gm={}:bm={}:pl=1",
+                format_param(self.tint / 2.),
+                format_param(-self.tint),
+                format_param(self.tint / 2.)
+            )
+            .unwrap();
+        }
+        filter_string
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterColortemp {
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        response |= ui.add(
+            param_slider(
+                ctx,
+                self.name(),
+                &mut self.temperature,
+                1000..=40000,
+                "Temperature",
+            )
+            .logarithmic(true),
+        );
+        response |= ui.add(param_slider(
+            ctx,
+            self.name(),
+            &mut self.mix,
+            0.0..=1.0,
+            "Mix",
+        ));
+        response |= ui.add(param_slider(
+            ctx,
+            self.name(),
+            &mut self.tint,
+            -0.5..=0.5,
+            "Tint (green/magenta)",
+        ));
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Color temperature"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+impl TryFrom<&Node<'_, '_>> for FilterColortemp {
+    type Error = ();
+
+    fn try_from(value: &Node) -> Result<Self, Self::Error> {
+        if get_property_value(value, "mlt_service") != Some("avfilter.colortemperature".to_string())
+        {
+            return Err(());
+        }
+        let temperature = get_property_value(value, "av.temperature").ok_or(())?;
+        let mix = get_property_value(value, "av.mix").unwrap_or(1.);
+        let disabled = get_property_value(value, "disable").unwrap_or(0) == 1;
+        if let Some(pl) = get_property_value::<f32>(value, "av.pl").filter(|pl| *pl != 1.) {
+            log::warn!("Skipped pl={pl} of colortemperature, lightness is always preserved");
+        }
+
+        Ok(Self {
+            is_active: !disabled,
+            temperature,
+            mix,
+            ..Default::default()
+        })
+    }
+}
+
+impl TryFrom<&FilterSegment> for FilterColortemp {
+    type Error = ();
+
+    fn try_from(value: &FilterSegment) -> Result<Self, Self::Error> {
+        if value.name != "colortemperature" || !value.has_only_params(&["temperature", "mix", "pl"])
+        {
+            return Err(());
+        }
+        // Lightness is always preserved, other values stay a custom filter
+        if get_param_value_or(value, "pl", 2, 1.)? != 1. {
+            return Err(());
+        }
+        let default = Self::default();
+
+        Ok(Self {
+            is_active: true,
+            temperature: get_param_value_or(value, "temperature", 0, default.temperature)?,
+            mix: get_param_value_or(value, "mix", 1, default.mix)?,
+            ..default
+        })
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct FilterColorBalance {
+    pub is_active: bool,
+    pub shadows_red: f32,
+    pub shadows_green: f32,
+    pub shadows_blue: f32,
+    pub midtones_red: f32,
+    pub midtones_green: f32,
+    pub midtones_blue: f32,
+    pub highlights_red: f32,
+    pub highlights_green: f32,
+    pub highlights_blue: f32,
+    pub preserve_lightness: bool,
+    /// Emit an equivalent `curves` filter for tools that lack `colorbalance`.
+    #[serde(default)]
+    pub export_as_curves: bool,
+}
+
+const COLOR_BALANCE_CURVE_SAMPLES: u16 = 16;
+
+impl FilterColorBalance {
+    /// Transfer of one channel as in ffmpeg's colorbalance. The weights of the three ranges
+    /// depend on the lightness of the pixel, which equals the value on the neutral axis.
+    fn transfer(value: f32, shadows: f32, midtones: f32, highlights: f32) -> f32 {
+        const A: f32 = 4.;
+        const B: f32 = 0.333;
+        const SCALE: f32 = 0.7;
+        let weight = |x: f32| (x * A + 0.5).clamp(0., 1.) * SCALE;
+        let shadows = shadows * weight(B - value);
+        let midtones = midtones * weight(value - B) * weight(1. - value - B) / SCALE;
+        let highlights = highlights * weight(value + B - 1.);
+        (value + shadows + midtones + highlights).clamp(0., 1.)
+    }
+
+    /// `rgb` with its HSL lightness set to `lightness`, keeping hue and saturation.
+    fn with_lightness(rgb: [f32; 3], lightness: f32) -> [f32; 3] {
+        let max = rgb.into_iter().fold(0., f32::max);
+        let min = rgb.into_iter().fold(1., f32::min);
+        let chroma = max - min;
+        if chroma == 0. {
+            return [lightness; 3];
+        }
+        let l = (max + min) / 2.;
+        let saturation = chroma / (1. - (2. * l - 1.).abs());
+        let new_chroma = saturation * (1. - (2. * lightness - 1.).abs());
+        rgb.map(|c| (lightness + (c - l) * new_chroma / chroma).clamp(0., 1.))
+    }
+
+    /// Samples the transfer of each channel along the neutral axis into a `curves` filter.
+    /// Saturated colors can come out slightly different because colorbalance weights them by
+    /// the lightness of the whole pixel rather than the channel value.
+    pub fn to_curves_filter_string(&self) -> String {
+        let samples: Vec<_> = (0..=COLOR_BALANCE_CURVE_SAMPLES)
+            .map(|i| {
+                let x = f32::from(i) / f32::from(COLOR_BALANCE_CURVE_SAMPLES);
+                let rgb = [
+                    Self::transfer(x, self.shadows_red, self.midtones_red, self.highlights_red),
+                    Self::transfer(
+                        x,
+                        self.shadows_green,
+                        self.midtones_green,
+                        self.highlights_green,
+                    ),
+                    Self::transfer(
+                        x,
+                        self.shadows_blue,
+                        self.midtones_blue,
+                        self.highlights_blue,
+                    ),
+                ];
+                // Gray keeps its lightness, which is its value
+                let rgb = if self.preserve_lightness {
+                    Self::with_lightness(rgb, x)
+                } else {
+                    rgb
+                };
+                (x, rgb)
+            })
+            .collect();
+        let points = |channel: usize| {
+            samples
+                .iter()
+                .map(|(x, rgb)| format!("{x}/{}", (rgb[channel] * 1000.).round() / 1000.))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        format!(
+            "curves=r='{}':g='{}':b='{}'",
+            points(0),
+            points(1),
+            points(2)
+        )
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterColorBalance {
+    fn to_filter_string(&self) -> String {
+        if self.export_as_curves {
+            return self.to_curves_filter_string();
+        }
+        let mut filter_string = format!(
+            "colorbalance=rs={}:gs={}:bs={}:rm={}:gm={}:bm={}:rh={}:gh={}:bh={}",
+            format_param(self.shadows_red),
+            format_param(self.shadows_green),
+            format_param(self.shadows_blue),
+            format_param(self.midtones_red),
+            format_param(self.midtones_green),
+            format_param(self.midtones_blue),
+            format_param(self.highlights_red),
+            format_param(self.highlights_green),
+            format_param(self.highlights_blue)
+        );
+        if self.preserve_lightness {
+            filter_string.push_str(":pl=1");
+        }
+        filter_string
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterColorBalance {
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        let name = self.name();
+        for (section, values) in [
+            (
+                "Shadows",
+                [
+                    &mut self.shadows_red,
+                    &mut self.shadows_green,
+                    &mut self.shadows_blue,
+                ],
+            ),
+            (
+                "Midtones",
+                [
+                    &mut self.midtones_red,
+                    &mut self.midtones_green,
+                    &mut self.midtones_blue,
+                ],
+            ),
+            (
+                "Highlights",
+                [
+                    &mut self.highlights_red,
+                    &mut self.highlights_green,
+                    &mut self.highlights_blue,
+                ],
+            ),
+        ] {
+            ui.label(section);
+            for (value, (color, text)) in
+                values
+                    .into_iter()
+                    .zip([("red", "Red"), ("green", "Green"), ("blue", "Blue")])
+            {
+                // The sliders are only labeled with their color, locks also need the section.
+                let param = format!("{section} {color}");
+                response |= ui.add(param_slider(ctx, name, value, -1.0..=1.01, &param).text(text));
+            }
+        }
+        response |= ui.checkbox(&mut self.preserve_lightness, "Preserve lightness");
+        response |= ui
+            .checkbox(&mut self.export_as_curves, "Export as curves")
+            .on_hover_text("Emit an equivalent curves filter instead of colorbalance");
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Color balance"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+impl TryFrom<&FilterSegment> for FilterColorBalance {
+    type Error = ();
+
+    fn try_from(value: &FilterSegment) -> Result<Self, Self::Error> {
+        if value.name != "colorbalance"
+            || !value.has_only_params(&["rs", "gs", "bs", "rm", "gm", "bm", "rh", "gh", "bh", "pl"])
+        {
+            return Err(());
+        }
+
+        Ok(Self {
+            is_active: true,
+            shadows_red: get_param_value_or(value, "rs", 0, 0.)?,
+            shadows_green: get_param_value_or(value, "gs", 1, 0.)?,
+            shadows_blue: get_param_value_or(value, "bs", 2, 0.)?,
+            midtones_red: get_param_value_or(value, "rm", 3, 0.)?,
+            midtones_green: get_param_value_or(value, "gm", 4, 0.)?,
+            midtones_blue: get_param_value_or(value, "bm", 5, 0.)?,
+            highlights_red: get_param_value_or(value, "rh", 6, 0.)?,
+            highlights_green: get_param_value_or(value, "gh", 7, 0.)?,
+            highlights_blue: get_param_value_or(value, "bh", 8, 0.)?,
+            preserve_lightness: get_param_value_or(value, "pl", 9, 0)? != 0,
+            export_as_curves: false,
+        })
+    }
+}
+
+fn color_to_hex(color: [u8; 3]) -> String {
+    format!("0x{:02X}{:02X}{:02X}", color[0], color[1], color[2])
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct FilterColorKey {
+    pub is_active: bool,
+    pub color: [u8; 3],
+    pub similarity: f32,
+    pub blend: f32,
+}
+
+impl Default for FilterColorKey {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            color: [0, 255, 0],
+            similarity: 0.01,
+            blend: 0.,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterColorKey {
+    fn to_filter_string(&self) -> String {
+        format!(
+            "colorkey=color={}:similarity={}:blend={}",
+            color_to_hex(self.color),
+            // Below the rounding precision of `format_param`
+            self.similarity.clamp(0.00001, 1.),
+            format_param(self.blend.clamp(0., 1.))
+        )
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterColorKey {
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        response |= ui
+            .horizontal(|ui| {
+                ui.label("Color");
+                egui::color_picker::color_edit_button_srgb(ui, &mut self.color)
+            })
+            .inner;
+        response |= ui.add(
+            param_slider(
+                ctx,
+                self.name(),
+                &mut self.similarity,
+                0.00001..=1.0,
+                "Similarity",
+            )
+            .logarithmic(true),
+        );
+        response |= ui.add(param_slider(
+            ctx,
+            self.name(),
+            &mut self.blend,
+            0.0..=1.0,
+            "Blend",
+        ));
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Color key"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+fn parse_hex_color(value: &str) -> Option<[u8; 3]> {
+    let hex = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix('#'))
+        .unwrap_or(value);
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+impl TryFrom<&FilterSegment> for FilterColorKey {
+    type Error = ();
+
+    fn try_from(value: &FilterSegment) -> Result<Self, Self::Error> {
+        if value.name != "colorkey" || !value.has_only_params(&["color", "similarity", "blend"]) {
+            return Err(());
+        }
+        let default = Self::default();
+        let color = match get_param_value::<String>(value, "color", 0) {
+            Some(color) => parse_hex_color(&color).ok_or(())?,
+            None => default.color,
+        };
+
+        Ok(Self {
+            is_active: true,
+            color,
+            similarity: get_param_value_or(value, "similarity", 1, default.similarity)?,
+            blend: get_param_value_or(value, "blend", 2, default.blend)?,
+        })
+    }
+}
+
+/// Multiplies each RGB channel by its gain, e.g. to white balance by gain.
+///
+/// Backed by ffmpeg's `colorchannelmixer` with only the diagonal terms `rr`, `gg` and `bb` set,
+/// so no channel leaks into another. YUV input is converted to RGB for it.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct FilterRgbGain {
+    pub is_active: bool,
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+}
+
+impl Default for FilterRgbGain {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            red: 1.,
+            green: 1.,
+            blue: 1.,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterRgbGain {
+    fn to_filter_string(&self) -> String {
+        format!(
+            "colorchannelmixer=rr={}:gg={}:bb={}",
+            format_param(self.red),
+            format_param(self.green),
+            format_param(self.blue)
+        )
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterRgbGain {
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        let name = self.name();
+        for (value, text) in [
+            (&mut self.red, "Red"),
+            (&mut self.green, "Green"),
+            (&mut self.blue, "Blue"),
+        ] {
+            response |= ui.add(param_slider(ctx, name, value, 0.0..=2.0, text));
+        }
+        if ui.button("Reset gains").clicked() {
+            *self = Self {
+                is_active: self.is_active,
+                ..Default::default()
+            };
+            response.mark_changed();
+        }
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "RGB gain"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+impl TryFrom<&FilterSegment> for FilterRgbGain {
+    type Error = ();
+
+    fn try_from(value: &FilterSegment) -> Result<Self, Self::Error> {
+        if value.name != "colorchannelmixer"
+            || !value.has_only_params(&["rr", "gg", "bb"])
+            || value.params.iter().any(|(key, _)| key.is_none())
+        {
+            return Err(());
+        }
+        let default = Self::default();
+        Ok(Self {
+            is_active: true,
+            red: get_param_value_or(value, "rr", 0, default.red)?,
+            green: get_param_value_or(value, "gg", 5, default.green)?,
+            blue: get_param_value_or(value, "bb", 10, default.blue)?,
+        })
+    }
+}
+
+/// Rec. 709 luma weights, which the ASC CDL saturation uses.
+const CDL_LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// ASC CDL slope, offset and power per RGB channel followed by saturation, usually imported
+/// from a `.cdl`, `.cc` or `.ccc` file.
+///
+/// The SOP part is a `lutrgb` computing `clip(in * slope + offset, 0, 1) ^ power`, the
+/// saturation a `colorchannelmixer` mixing each channel with the Rec. 709 luma.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FilterCdl {
+    pub is_active: bool,
+    pub slope: [f32; 3],
+    pub offset: [f32; 3],
+    pub power: [f32; 3],
+    pub saturation: f32,
+}
+
+impl Default for FilterCdl {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            slope: [1.; 3],
+            offset: [0.; 3],
+            power: [1.; 3],
+            saturation: 1.,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterCdl {
+    fn to_filter_string(&self) -> String {
+        let channel = |c: usize| {
+            let offset = format_param(self.offset[c]);
+            let sign = if offset.starts_with('-') { "" } else { "+" };
+            format!(
+                "pow(clip(val/maxval*{}{sign}{offset}\\,0\\,1)\\,{})*maxval",
+                format_param(self.slope[c]),
+                format_param(self.power[c])
+            )
+        };
+        let mut filter_string =
+            format!("lutrgb=r={}:g={}:b={}", channel(0), channel(1), channel(2));
+        if format_param(self.saturation) != "1" {
+            let s = self.saturation;
+            let mut params = vec![];
+            for (out, row) in ["r", "g", "b"].iter().enumerate() {
+                for (c, col) in ["r", "g", "b"].iter().enumerate() {
+                    let identity = if out == c { s } else { 0. };
+                    let value = CDL_LUMA[c] * (1. - s) + identity;
+                    params.push(format!("{row}{col}={}", format_param(value)));
+                }
+            }
+            write!(filter_string, ",colorchannelmixer={}", params.join(":")).unwrap();
+        }
+        filter_string
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterCdl {
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        let name = self.name();
+        // Imported corrections may go beyond the slider ranges, and so may typed values
+        for (values, text, range) in [
+            (&mut self.slope, "Slope", 0.0..=4.0),
+            (&mut self.offset, "Offset", -1.0..=1.0),
+            (&mut self.power, "Power", 0.1..=4.0),
+        ] {
+            ui.label(text);
+            for (value, channel) in values.iter_mut().zip(["red", "green", "blue"]) {
+                response |= ui.add(
+                    param_slider(
+                        ctx,
+                        name,
+                        value,
+                        range.clone(),
+                        &format!("{text} {channel}"),
+                    )
+                    .clamping(SliderClamping::Never),
+                );
+            }
+        }
+        response |= ui.add(
+            param_slider(ctx, name, &mut self.saturation, 0.0..=4.0, "Saturation")
+                .clamping(SliderClamping::Never),
+        );
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "ASC CDL"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FilterNormalize {
+    pub is_active: bool,
+    pub black_point: [u8; 3],
+    pub white_point: [u8; 3],
+    pub smoothing: u32,
+    pub independence: f32,
+}
+
+impl Default for FilterNormalize {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            black_point: [0, 0, 0],
+            white_point: [255, 255, 255],
+            smoothing: 25,
+            independence: 1.,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterNormalize {
+    fn to_filter_string(&self) -> String {
+        format!(
+            "normalize=blackpt={}:whitept={}:smoothing={}:independence={}",
+            color_to_hex(self.black_point),
+            color_to_hex(self.white_point),
+            self.smoothing,
+            format_param(self.independence.clamp(0., 1.))
+        )
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterNormalize {
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        response |= ui
+            .horizontal(|ui| {
+                ui.label("Black point");
+                egui::color_picker::color_edit_button_srgb(ui, &mut self.black_point)
+            })
+            .inner;
+        response |= ui
+            .horizontal(|ui| {
+                ui.label("White point");
+                egui::color_picker::color_edit_button_srgb(ui, &mut self.white_point)
+            })
+            .inner;
+        response |= ui.add(param_slider(
+            ctx,
+            self.name(),
+            &mut self.smoothing,
+            0..=250,
+            "Smoothing (frames)",
+        ));
+        response |= ui.add(param_slider(
+            ctx,
+            self.name(),
+            &mut self.independence,
+            0.0..=1.0,
+            "Independence",
+        ));
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Normalize"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+impl TryFrom<&FilterSegment> for FilterNormalize {
+    type Error = ();
+
+    fn try_from(value: &FilterSegment) -> Result<Self, Self::Error> {
+        if value.name != "normalize"
+            || !value.has_only_params(&["blackpt", "whitept", "smoothing", "independence"])
+        {
+            return Err(());
+        }
+        let default = Self::default();
+        let color = |name, position, default| match get_param_value::<String>(value, name, position)
+        {
+            Some(color) => parse_hex_color(&color).ok_or(()),
+            None => Ok(default),
+        };
+
+        Ok(Self {
+            is_active: true,
+            black_point: color("blackpt", 0, default.black_point)?,
+            white_point: color("whitept", 1, default.white_point)?,
+            smoothing: get_param_value_or(value, "smoothing", 2, default.smoothing)?,
+            independence: get_param_value_or(value, "independence", 3, default.independence)?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FilterSetSar {
+    pub is_active: bool,
+    pub num: u32,
+    pub den: u32,
+}
+
+impl Default for FilterSetSar {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            num: 1,
+            den: 1,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterSetSar {
+    fn to_filter_string(&self) -> String {
+        format!("setsar=sar={}/{}", self.num, self.den.max(1))
+    }
+
+    fn sample_aspect_ratio(&self, _width: u32, _height: u32) -> Option<f32> {
+        Some(self.num as f32 / self.den.max(1) as f32)
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterSetSar {
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        response |= draw_ratio(ui, &mut self.num, &mut self.den);
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Sample aspect ratio"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FilterSetDar {
+    pub is_active: bool,
+    pub num: u32,
+    pub den: u32,
+}
+
+impl Default for FilterSetDar {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            num: 16,
+            den: 9,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterSetDar {
+    fn to_filter_string(&self) -> String {
+        format!("setdar=dar={}/{}", self.num, self.den.max(1))
+    }
+
+    fn sample_aspect_ratio(&self, width: u32, height: u32) -> Option<f32> {
+        if width == 0 {
+            return None;
+        }
+        Some(self.num as f32 / self.den.max(1) as f32 * height as f32 / width as f32)
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterSetDar {
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        response |= draw_ratio(ui, &mut self.num, &mut self.den);
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Display aspect ratio"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+fn draw_ratio(ui: &mut egui::Ui, num: &mut u32, den: &mut u32) -> egui::Response {
+    let mut ratio = Rational {
+        num: *num,
+        den: (*den).max(1),
+    };
+    let response = rational_edit(ui, "ratio", &mut ratio, "16/9");
+    if response.changed() {
+        *num = ratio.num;
+        *den = ratio.den;
+    }
+    response
+}
+
+/// A positive fraction like `30000/1001`, emitted verbatim so frame rates and aspect ratios do
+/// not drift. It is stored as its text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rational {
+    pub num: u32,
+    pub den: u32,
+}
+
+impl Rational {
+    pub fn to_f64(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+}
+
+impl std::fmt::Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+/// Parses `num/den`, `num:den` or a decimal like `29.97`, which is turned into the exact
+/// fraction `2997/100`.
+impl std::str::FromStr for Rational {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("{s:?} is not a positive number or fraction");
+        let (num, den) = match s.split_once(['/', ':']) {
+            Some((num, den)) => (
+                num.trim().parse::<u64>().map_err(|_| invalid())?,
+                den.trim().parse::<u64>().map_err(|_| invalid())?,
+            ),
+            None => {
+                let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+                let digits = format!("{int}{frac}");
+                if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(invalid());
+                }
+                let den = u32::try_from(frac.len())
+                    .ok()
+                    .and_then(|len| 10u64.checked_pow(len))
+                    .ok_or_else(invalid)?;
+                (digits.parse::<u64>().map_err(|_| invalid())?, den)
+            }
+        };
+        if num == 0 || den == 0 {
+            return Err(invalid());
+        }
+        let gcd = gcd(num, den);
+        Ok(Self {
+            num: u32::try_from(num / gcd).map_err(|_| invalid())?,
+            den: u32::try_from(den / gcd).map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl TryFrom<String> for Rational {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Rational> for String {
+    fn from(value: Rational) -> Self {
+        value.to_string()
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Text field for a [`Rational`] that also takes decimals. The value only changes while the
+/// text parses, invalid text is shown in red and dropped when the field loses focus.
+fn rational_edit(
+    ui: &mut egui::Ui,
+    id_salt: &str,
+    value: &mut Rational,
+    hint: &str,
+) -> egui::Response {
+    let id = ui.make_persistent_id(id_salt);
+    let mut text = ui
+        .data(|d| d.get_temp::<String>(id))
+        .unwrap_or_else(|| value.to_string());
+    let is_valid = text.parse::<Rational>().is_ok();
+    let mut edit = egui::TextEdit::singleline(&mut text)
+        .id(id.with("edit"))
+        .hint_text(hint)
+        .desired_width(100.);
+    if !is_valid {
+        edit = edit.text_color(ui.visuals().error_fg_color);
+    }
+    let mut response = ui.add(edit);
+    let parsed = text.parse::<Rational>();
+    response.changed = false;
+    if let Ok(parsed) = parsed {
+        if parsed != *value {
+            *value = parsed;
+            response.changed = true;
+        }
+    }
+    if response.has_focus() {
+        ui.data_mut(|d| d.insert_temp(id, text));
+    } else {
+        ui.data_mut(|d| d.remove::<String>(id));
+    }
+    match parsed {
+        Ok(_) if value.den != 1 => response.on_hover_text(format!("≈ {:.4}", value.to_f64())),
+        Ok(_) => response,
+        Err(e) => response.on_hover_text(e),
+    }
+}
+
+/// Clamps Y, U and V to the given code values, e.g. TV range for broadcast delivery.
+#[derive(Serialize, Deserialize)]
+pub struct FilterLimiter {
+    pub is_active: bool,
+    pub y: [u8; 2],
+    pub u: [u8; 2],
+    pub v: [u8; 2],
+}
+
+impl Default for FilterLimiter {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            y: [16, 235],
+            u: [16, 240],
+            v: [16, 240],
+        }
+    }
+}
+
+impl FilterLimiter {
+    /// Luma limits in percent as shown on the waveform. Sources in limited range are expanded
+    /// to full range RGB for the scopes, so 16 is at 0 % and 235 at 100 % then.
+    pub fn luma_limits(&self, full_range: bool) -> [f64; 2] {
+        if full_range {
+            self.y.map(|v| v as f64 * 100. / u8::MAX as f64)
+        } else {
+            self.y.map(|v| (v as f64 - 16.) * 100. / 219.)
+        }
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterLimiter {
+    fn to_filter_string(&self) -> String {
+        let clip = |[min, max]: [u8; 2]| format!("clip(val\\,{min}\\,{max})");
+        format!(
+            "lutyuv=y={}:u={}:v={}",
+            clip(self.y),
+            clip(self.u),
+            clip(self.v)
+        )
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterLimiter {
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        for (range, label) in [(&mut self.y, "Y"), (&mut self.u, "U"), (&mut self.v, "V")] {
+            response |= ui
+                .horizontal(|ui| {
+                    ui.label(label);
+                    let [min, max] = range;
+                    ui.add(DragValue::new(min).range(0..=*max))
+                        | ui.label("to")
+                        | ui.add(DragValue::new(max).range(*min..=u8::MAX))
+                })
+                .inner;
+        }
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Limiter"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FilterDeshake {
+    pub is_active: bool,
+    /// Search rectangle, -1 uses the whole frame.
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+    pub rx: u32,
+    pub ry: u32,
+}
+
+impl Default for FilterDeshake {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            x: -1,
+            y: -1,
+            w: -1,
+            h: -1,
+            rx: 16,
+            ry: 16,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterDeshake {
+    fn to_filter_string(&self) -> String {
+        format!(
+            "deshake=x={}:y={}:w={}:h={}:rx={}:ry={}",
+            self.x, self.y, self.w, self.h, self.rx, self.ry
+        )
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterDeshake {
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        ui.label("A single frame preview shows no motion, use \"Play preview\" to judge it.");
+        response |= ui
+            .horizontal(|ui| {
+                ui.label("Rectangle");
+                ui.add(
+                    DragValue::new(&mut self.x)
+                        .range(-1..=i32::MAX)
+                        .prefix("x: "),
+                ) | ui.add(
+                    DragValue::new(&mut self.y)
+                        .range(-1..=i32::MAX)
+                        .prefix("y: "),
+                ) | ui.add(
+                    DragValue::new(&mut self.w)
+                        .range(-1..=i32::MAX)
+                        .prefix("w: "),
+                ) | ui.add(
+                    DragValue::new(&mut self.h)
+                        .range(-1..=i32::MAX)
+                        .prefix("h: "),
+                )
+            })
+            .inner;
+        response |= ui.add(param_slider(
+            ctx,
+            self.name(),
+            &mut self.rx,
+            0..=64,
+            "Search x",
+        ));
+        response |= ui.add(param_slider(
+            ctx,
+            self.name(),
+            &mut self.ry,
+            0..=64,
+            "Search y",
+        ));
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Deshake"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+const COLORSPACE_SPACES: &[&str] = &[
+    "bt709",
+    "fcc",
+    "bt470bg",
+    "smpte170m",
+    "smpte240m",
+    "ycgco",
+    "bt2020ncl",
+];
+const COLORSPACE_TRCS: &[&str] = &[
+    "bt709",
+    "bt470m",
+    "bt470bg",
+    "gamma22",
+    "gamma28",
+    "smpte170m",
+    "smpte240m",
+    "linear",
+    "srgb",
+    "iec61966-2-1",
+    "xvycc",
+    "iec61966-2-4",
+    "bt2020-10",
+    "bt2020-12",
+];
+const COLORSPACE_PRIMARIES: &[&str] = &[
+    "bt709",
+    "bt470m",
+    "bt470bg",
+    "smpte170m",
+    "smpte240m",
+    "smpte428",
+    "film",
+    "smpte431",
+    "smpte432",
+    "bt2020",
+    "jedec-p22",
+];
+
+/// Converts between color spaces. Empty input properties are taken from the frame.
+#[derive(Serialize, Deserialize)]
+pub struct FilterColorspace {
+    pub is_active: bool,
+    pub space: String,
+    pub trc: String,
+    pub primaries: String,
+    pub input_space: String,
+    pub input_trc: String,
+    pub input_primaries: String,
+    /// Set by the GUI after probing the input file.
+    #[serde(skip)]
+    pub detected: Option<ColorMetadata>,
+}
+
+impl Default for FilterColorspace {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            space: "bt709".to_string(),
+            trc: "bt709".to_string(),
+            primaries: "bt709".to_string(),
+            input_space: String::new(),
+            input_trc: String::new(),
+            input_primaries: String::new(),
+            detected: None,
+        }
+    }
+}
+
+impl FilterColorspace {
+    pub fn use_detected(&mut self) {
+        let Some(detected) = self.detected.as_ref() else {
+            return;
+        };
+        if let Some(space) = &detected.color_space {
+            // ffprobe and the colorspace filter name non-constant luminance BT.2020 differently
+            self.input_space = match space.as_str() {
+                "bt2020nc" => "bt2020ncl".to_string(),
+                space => space.to_string(),
+            };
+        }
+        if let Some(trc) = &detected.color_transfer {
+            self.input_trc = trc.clone();
+        }
+        if let Some(primaries) = &detected.color_primaries {
+            self.input_primaries = primaries.clone();
+        }
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterColorspace {
+    fn to_filter_string(&self) -> String {
+        let mut filter_string = format!(
+            "colorspace=space={}:trc={}:primaries={}",
+            self.space, self.trc, self.primaries
+        );
+        for (name, value) in [
+            ("ispace", &self.input_space),
+            ("itrc", &self.input_trc),
+            ("iprimaries", &self.input_primaries),
+        ] {
+            if !value.is_empty() {
+                write!(filter_string, ":{name}={value}").unwrap();
+            }
+        }
+        filter_string
+    }
+}
+
+fn colorspace_combo_box(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut String,
+    options: &[&str],
+    allow_empty: bool,
+) -> egui::Response {
+    let mut changed = false;
+    let mut response = ComboBox::from_label(label)
+        .selected_text(if value.is_empty() {
+            "from input".to_string()
+        } else {
+            value.clone()
+        })
+        .show_ui(ui, |ui| {
+            if allow_empty {
+                changed |= ui
+                    .selectable_value(value, String::new(), "from input")
+                    .changed();
+            }
+            for option in options {
+                changed |= ui
+                    .selectable_value(value, option.to_string(), *option)
+                    .changed();
+            }
+        })
+        .response;
+    if changed {
+        response.mark_changed();
+    }
+    response
+}
+
+#[typetag::serde]
+impl GuiElement for FilterColorspace {
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        response |= colorspace_combo_box(ui, "Space", &mut self.space, COLORSPACE_SPACES, false);
+        response |= colorspace_combo_box(ui, "Transfer", &mut self.trc, COLORSPACE_TRCS, false);
+        response |= colorspace_combo_box(
+            ui,
+            "Primaries",
+            &mut self.primaries,
+            COLORSPACE_PRIMARIES,
+            false,
+        );
+        ui.separator();
+        response |= colorspace_combo_box(
+            ui,
+            "Input space",
+            &mut self.input_space,
+            COLORSPACE_SPACES,
+            true,
+        );
+        response |= colorspace_combo_box(
+            ui,
+            "Input transfer",
+            &mut self.input_trc,
+            COLORSPACE_TRCS,
+            true,
+        );
+        response |= colorspace_combo_box(
+            ui,
+            "Input primaries",
+            &mut self.input_primaries,
+            COLORSPACE_PRIMARIES,
+            true,
+        );
+        if let Some(detected) = &self.detected {
+            let show = |value: &Option<String>| value.clone().unwrap_or("unknown".to_string());
+            ui.label(format!(
+                "Detected: space {}, transfer {}, primaries {}",
+                show(&detected.color_space),
+                show(&detected.color_transfer),
+                show(&detected.color_primaries)
+            ));
+            if ui.button("Use detected").clicked() {
+                self.use_detected();
+                response.mark_changed();
+            }
+        }
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Colorspace"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+/// Pixel formats offered for [`FilterFormat`], RGB first.
+const PIXEL_FORMATS: &[&str] = &[
+    "rgb24",
+    "rgb48le",
+    "gbrp",
+    "gbrp10le",
+    "gbrp16le",
+    "gbrpf32le",
+    "yuv420p",
+    "yuv422p",
+    "yuv444p",
+    "yuv420p10le",
+    "yuv422p10le",
+    "yuv444p10le",
+    "yuv444p16le",
+];
+
+/// Converts to a pixel format mid-chain, e.g. to a high bit depth before precise color
+/// operations.
+#[derive(Serialize, Deserialize)]
+pub struct FilterFormat {
+    pub is_active: bool,
+    pub pix_fmt: String,
+}
+
+impl Default for FilterFormat {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            pix_fmt: "gbrp16le".to_string(),
+        }
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterFormat {
+    fn to_filter_string(&self) -> String {
+        format!("format={}", self.pix_fmt)
+    }
+}
+
+impl TryFrom<&FilterSegment> for FilterFormat {
+    type Error = ();
+
+    fn try_from(value: &FilterSegment) -> Result<Self, Self::Error> {
+        if value.name != "format" || !value.has_only_params(&["pix_fmts"]) {
+            return Err(());
+        }
+        let pix_fmt: String = get_param_value(value, "pix_fmts", 0).ok_or(())?;
+        // Lists of formats are left to a custom filter
+        if pix_fmt.is_empty() || pix_fmt.contains('|') {
+            return Err(());
+        }
+        Ok(Self {
+            is_active: true,
+            pix_fmt,
+        })
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterFormat {
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        let mut changed = false;
+        response |= ComboBox::from_label("Pixel format")
+            .selected_text(&self.pix_fmt)
+            .show_ui(ui, |ui| {
+                for pix_fmt in PIXEL_FORMATS {
+                    changed |= ui
+                        .selectable_value(&mut self.pix_fmt, pix_fmt.to_string(), *pix_fmt)
+                        .changed();
+                }
+            })
+            .response;
+        if changed {
+            response.mark_changed();
+        }
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Pixel format"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+const TONE_CURVE_AMPLITUDE: f32 = 0.15;
+
+#[derive(Serialize, Deserialize)]
+pub struct FilterToneCurve {
+    pub is_active: bool,
+    pub shadows: f32,
+    pub darks: f32,
+    pub lights: f32,
+    pub highlights: f32,
+    /// Split between darks and lights.
+    pub pivot: f32,
+}
+
+impl Default for FilterToneCurve {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            shadows: 0.,
+            darks: 0.,
+            lights: 0.,
+            highlights: 0.,
+            pivot: 0.5,
+        }
+    }
+}
+
+impl FilterToneCurve {
+    /// Control points with one point at the center of each region, kept monotonic.
+    pub fn control_points(&self) -> Vec<[f32; 2]> {
+        let pivot = self.pivot.clamp(0.1, 0.9);
+        let splits = [0., pivot / 2., pivot, (1. + pivot) / 2., 1.];
+        let amounts = [self.shadows, self.darks, self.lights, self.highlights];
+
+        let mut points = vec![[0., 0.]];
+        for (region, amount) in amounts.iter().enumerate() {
+            let x = (splits[region] + splits[region + 1]) / 2.;
+            let previous = points.last().unwrap()[1];
+            let y = (x + amount.clamp(-1., 1.) * TONE_CURVE_AMPLITUDE).clamp(previous, 1.);
+            points.push([x, y]);
+        }
+        points.push([1., 1.]);
+        points
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterToneCurve {
+    fn to_filter_string(&self) -> String {
+        let points = self
+            .control_points()
+            .iter()
+            .map(|[x, y]| format!("{x:.3}/{y:.3}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!("curves=all='{points}'")
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterToneCurve {
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        let name = self.name();
+        for (value, text) in [
+            (&mut self.shadows, "Shadows"),
+            (&mut self.darks, "Darks"),
+            (&mut self.lights, "Lights"),
+            (&mut self.highlights, "Highlights"),
+        ] {
+            response |= ui.add(param_slider(ctx, name, value, -1.0..=1.0, text));
+        }
+        response |= ui.add(param_slider(
+            ctx,
+            self.name(),
+            &mut self.pivot,
+            0.1..=0.9,
+            "Pivot",
+        ));
+        let points: PlotPoints = self
+            .control_points()
+            .iter()
+            .map(|[x, y]| [*x as f64, *y as f64])
+            .collect();
+        Plot::new(ui.id().with("tone_curve"))
+            .width(150.)
+            .height(150.)
+            .data_aspect(1.)
+            .include_x(0.)
+            .include_x(1.)
+            .include_y(0.)
+            .include_y(1.)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .show_axes(false)
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(vec![[0., 0.], [1., 1.]])));
+                plot_ui.line(Line::new(points));
+            });
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Tone curve"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct FilterCustom {
+    pub is_active: bool,
+    pub expression: String,
+    /// The expression is a single filter, so commas and semicolons are part of it and get
+    /// escaped instead of separating filters.
+    #[serde(default)]
+    pub single_filter: bool,
+}
+
+#[typetag::serde]
+impl Filter for FilterCustom {
+    fn to_filter_string(&self) -> String {
+        if self.single_filter {
+            escape_separators(&self.expression)
+        } else {
+            self.expression.clone()
+        }
+    }
+
+    /// Custom expressions can be whole filtergraphs and have to stay editable, ffmpeg reports
+    /// unknown filters in them itself.
+    fn ffmpeg_filters(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterCustom {
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        response |= ui.text_edit_singleline(&mut self.expression);
+        response |= ui
+            .checkbox(&mut self.single_filter, "Single filter")
+            .on_hover_text("Escape commas and semicolons, e.g. for select=gt(scene,0.4)");
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        "Custom filter(s)"
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TemplateParamKind {
+    Float { min: f64, max: f64, default: f64 },
+    Int { min: i64, max: i64, default: i64 },
+    Bool { default: bool },
+    Text { default: String },
+}
+
+impl TemplateParamKind {
+    fn default_value(&self) -> TemplateValue {
+        match self {
+            Self::Float { default, .. } => TemplateValue::Float(*default),
+            Self::Int { default, .. } => TemplateValue::Int(*default),
+            Self::Bool { default } => TemplateValue::Bool(*default),
+            Self::Text { default } => TemplateValue::Text(default.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateParam {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: TemplateParamKind,
+}
+
+/// A filter from the user's filter definitions. `template` is the filter string with a
+/// `{name}` placeholder for each parameter.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FilterDefinition {
+    pub name: String,
+    pub template: String,
+    #[serde(default)]
+    pub params: Vec<TemplateParam>,
+}
+
+impl FilterDefinition {
+    fn placeholders(&self) -> Vec<&str> {
+        self.template
+            .split('{')
+            .skip(1)
+            .filter_map(|part| Some(part.split_once('}')?.0))
+            .collect()
+    }
+}
+
+/// Reads filter definitions from a JSON file holding a list of [`FilterDefinition`]s.
+pub fn read_filter_definitions(path: impl AsRef<Path>) -> Result<Vec<FilterDefinition>, String> {
+    let json = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse_filter_definitions(&json)
+}
+
+pub fn parse_filter_definitions(json: &str) -> Result<Vec<FilterDefinition>, String> {
+    let definitions: Vec<FilterDefinition> =
+        serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let mut names = HashSet::new();
+    for definition in &definitions {
+        let name = &definition.name;
+        if name.is_empty() {
+            return Err("Filter definition without a name".to_string());
+        }
+        if FILTER_REGISTRY.iter().any(|(builtin, _)| builtin == name) {
+            return Err(format!("Filter {name:?} is the name of a built-in filter"));
+        }
+        if !names.insert(name) {
+            return Err(format!("Filter {name:?} is defined more than once"));
+        }
+        if let Some(placeholder) = definition
+            .placeholders()
+            .into_iter()
+            .find(|placeholder| !definition.params.iter().any(|p| p.name == *placeholder))
+        {
+            return Err(format!(
+                "Template of {name:?} uses {{{placeholder}}}, which is not a parameter"
+            ));
+        }
+    }
+    Ok(definitions)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TemplateValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+/// Filter names have to be `&'static str` for [`GuiElement::name`]. Names of filter definitions
+/// are leaked once each, no matter how often filters are deserialized.
+fn intern_name(name: &str) -> &'static str {
+    static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+    let mut names = NAMES.lock().unwrap();
+    if let Some(name) = names.get(name) {
+        return name;
+    }
+    let name: &'static str = Box::leak(name.into());
+    names.insert(name);
+    name
+}
+
+/// A filter built from a [`FilterDefinition`]. The definition is stored with the filter, so
+/// saved states keep working when the definitions change.
+#[derive(Default, Serialize, Deserialize)]
+pub struct FilterTemplated {
+    pub is_active: bool,
+    pub definition: FilterDefinition,
+    pub values: Vec<TemplateValue>,
+}
+
+impl FilterTemplated {
+    pub fn new(definition: FilterDefinition) -> Self {
+        Self {
+            is_active: false,
+            values: definition
+                .params
+                .iter()
+                .map(|p| p.kind.default_value())
+                .collect(),
+            definition,
+        }
+    }
+
+    /// Resets values that are missing or don't fit their parameter to the default.
+    fn fit_values(&mut self) {
+        let params = &self.definition.params;
+        self.values
+            .resize_with(params.len(), || TemplateValue::Bool(false));
+        for (param, value) in params.iter().zip(self.values.iter_mut()) {
+            let fits = matches!(
+                (&param.kind, &*value),
+                (TemplateParamKind::Float { .. }, TemplateValue::Float(_))
+                    | (TemplateParamKind::Int { .. }, TemplateValue::Int(_))
+                    | (TemplateParamKind::Bool { .. }, TemplateValue::Bool(_))
+                    | (TemplateParamKind::Text { .. }, TemplateValue::Text(_))
+            );
+            if !fits {
+                *value = match (&param.kind, &*value) {
+                    (TemplateParamKind::Float { .. }, TemplateValue::Int(v)) => {
+                        TemplateValue::Float(*v as f64)
+                    }
+                    (kind, _) => kind.default_value(),
+                };
+            }
+        }
+    }
+}
+
+#[typetag::serde]
+impl Filter for FilterTemplated {
+    /// Placeholders are replaced in one pass, so values are never searched for placeholders.
+    fn to_filter_string(&self) -> String {
+        let value = |name: &str| {
+            let i = self.definition.params.iter().position(|p| p.name == name)?;
+            Some(match self.values.get(i)? {
+                TemplateValue::Bool(v) => u8::from(*v).to_string(),
+                TemplateValue::Int(v) => v.to_string(),
+                TemplateValue::Float(v) => format_param(*v as f32),
+                TemplateValue::Text(v) => escape_separators(v),
+            })
+        };
+        let mut filter_string = String::new();
+        let mut rest = self.definition.template.as_str();
+        while let Some(start) = rest.find('{') {
+            filter_string.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            match after
+                .split_once('}')
+                .and_then(|(name, tail)| Some((value(name)?, tail)))
+            {
+                Some((value, tail)) => {
+                    filter_string.push_str(&value);
+                    rest = tail;
+                }
+                None => {
+                    filter_string.push('{');
+                    rest = after;
+                }
+            }
+        }
+        filter_string.push_str(rest);
+        filter_string
+    }
+}
+
+#[typetag::serde]
+impl GuiElement for FilterTemplated {
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response {
+        self.fit_values();
+        let name = self.name();
+        let mut response = ui.checkbox(&mut self.is_active, "Active");
+        for (param, value) in self.definition.params.iter().zip(self.values.iter_mut()) {
+            let text = param.name.as_str();
+            response |= match (&param.kind, value) {
+                (TemplateParamKind::Float { min, max, .. }, TemplateValue::Float(v)) => {
+                    ui.add(param_slider(ctx, name, v, *min..=*max, text))
+                }
+                (TemplateParamKind::Int { min, max, .. }, TemplateValue::Int(v)) => {
+                    ui.add(param_slider(ctx, name, v, *min..=*max, text))
+                }
+                (TemplateParamKind::Bool { .. }, TemplateValue::Bool(v)) => ui.checkbox(v, text),
+                (TemplateParamKind::Text { .. }, TemplateValue::Text(v)) => {
+                    ui.horizontal(|ui| {
+                        ui.label(text);
+                        ui.text_edit_singleline(v)
+                    })
+                    .inner
+                }
+                _ => unreachable!("values are fitted to their parameters"),
+            };
+        }
+        response
+    }
+
+    fn name(&self) -> &'static str {
+        intern_name(&self.definition.name)
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+/// Dispatches requests: frame extractions run on a pool of workers with bounded concurrency,
+/// everything else runs serially on its own thread.
+pub struct Thread {
+    pub request_rx: Receiver<Request>,
+    pub response_tx: Sender<Response>,
+    workers: usize,
+    running: usize,
+    requests: VecDeque<Request>,
+    low_priority_requests: VecDeque<Request>,
+}
+
+impl Thread {
+    pub fn new(
+        request_rx: Receiver<Request>,
+        response_tx: Sender<Response>,
+        workers: usize,
+    ) -> Self {
+        Self {
+            request_rx,
+            response_tx,
+            workers: workers.max(1),
+            running: 0,
+            requests: VecDeque::new(),
+            low_priority_requests: VecDeque::new(),
+        }
+    }
+
+    pub fn run(&mut self) -> ! {
+        let (job_tx, job_rx) = flume::unbounded::<Request>();
+        let (done_tx, done_rx) = flume::unbounded::<()>();
+        for _ in 0..self.workers {
+            let job_rx = job_rx.clone();
+            let response_tx = self.response_tx.clone();
+            let done_tx = done_tx.clone();
+            std::thread::spawn(move || {
+                for request in job_rx.iter() {
+                    handle_request_catching(request, &response_tx);
+                    let _ = done_tx.send(());
+                }
+            });
+        }
+        let (serial_tx, serial_rx) = flume::unbounded::<Request>();
+        let response_tx = self.response_tx.clone();
+        std::thread::spawn(move || {
+            for request in serial_rx.iter() {
+                handle_request_catching(request, &response_tx);
+            }
+        });
+
+        loop {
+            // Waiting requests always go first, low priority ones only run when a worker is idle
+            while self.running < self.workers {
+                let Some(request) = self
+                    .requests
+                    .pop_front()
+                    .or_else(|| self.low_priority_requests.pop_front())
+                else {
+                    break;
+                };
+                job_tx.send(request).unwrap();
+                self.running += 1;
+            }
+
+            let event = flume::Selector::new()
+                .recv(&self.request_rx, |request| request.ok())
+                .recv(&done_rx, |_| None)
+                .wait();
+            match event {
+                Some(request) => {
+                    log::info!("Received request: {request:?}");
+                    if request.is_serial() {
+                        serial_tx.send(request).unwrap();
+                    } else if request.is_low_priority() {
+                        self.low_priority_requests.push_back(request);
+                    } else {
+                        self.requests.push_back(request);
+                    }
+                }
+                None => self.running = self.running.saturating_sub(1),
+            }
+        }
+    }
+}
+
+/// Handles `request` and turns a panic into an error, so the thread keeps serving requests and
+/// the dispatcher learns that the worker is free again.
+fn handle_request_catching(request: Request, response_tx: &Sender<Response>) {
+    let id = request.job_id();
+    let description = format!("{request:?}");
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        handle_request(request, response_tx)
+    }));
+    if let Err(panic) = result {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        log::error!("Request {description} panicked: {message}");
+        let _ = response_tx.send(Response::Error {
+            id,
+            error: format!("Internal error: {message}"),
+        });
+    }
+}
+
+fn handle_request(request: Request, response_tx: &Sender<Response>) {
+    match request {
+        Request::ExtractFrame { id, args, output } => {
+            match extract_image(args, output).and_then(split_precise) {
+                Ok((image, precise)) => response_tx
+                    .send(Response::Image { id, image, precise })
+                    .unwrap(),
+                Err(error) => response_tx
+                    .send(Response::Error {
+                        id: Some(id),
+                        error,
+                    })
+                    .unwrap(),
+            }
+        }
+        Request::Prefetch {
+            input,
+            args,
+            output,
+        } => {
+            let result = extract_frame(args, output);
+            response_tx
+                .send(Response::Prefetched { input, result })
+                .unwrap();
+        }
+        Request::Thumbnail { key, args, output } => {
+            let result = extract_frame(args, output);
+            response_tx
+                .send(Response::Thumbnail { key, result })
+                .unwrap();
+        }
+        Request::Probe { input } => {
+            let result = probe(&input);
+            response_tx
+                .send(Response::Probed { input, result })
+                .unwrap();
+        }
+        Request::Convert { input, args } => {
+            let result = convert(args, |seconds| {
+                let _ = response_tx.send(Response::ConvertProgress {
+                    input: input.clone(),
+                    seconds,
+                });
+            });
+            response_tx
+                .send(Response::Converted { input, result })
+                .unwrap();
+        }
+        Request::DetectCapabilities => {
+            let capabilities = FfmpegCapabilities::detect();
+            response_tx
+                .send(Response::Capabilities(capabilities))
+                .unwrap();
+        }
+        Request::Play { args } => {
+            let mut command = Command::new("ffplay");
+            command.args(args);
+            watch_ffplay(command, response_tx.clone());
+        }
+        Request::PlayProxy {
+            args,
+            output,
+            player,
+        } => {
+            let result = convert(args, |_| {})
+                .map_err(|e| format!("Could not encode proxy: {e}"))
+                .and_then(|()| {
+                    player_command(&player, &output)
+                        .spawn()
+                        .map_err(|e| format!("Could not open {output:?}: {e}"))
+                });
+            match result {
+                Ok(mut child) => {
+                    info!("Opened proxy {output:?}");
+                    // Waits for the player elsewhere, so it is reaped without blocking encodes
+                    std::thread::spawn(move || child.wait());
+                }
+                Err(error) => response_tx
+                    .send(Response::Error { id: None, error })
+                    .unwrap(),
+            }
+        }
+    }
+}
+
+/// Opens `path` with `player`, a command line the path is appended to, or with the system's
+/// default application if `player` is empty. A `player` that names an existing file is the
+/// program even if its path has spaces.
+pub fn player_command(player: &str, path: &Path) -> Command {
+    let player = player.trim();
+    let words = if Path::new(player).is_file() {
+        vec![player.to_string()]
+    } else {
+        command_words(player)
+    };
+    let mut words = words.into_iter();
+    let mut command = match words.next() {
+        Some(program) => {
+            let mut command = Command::new(program);
+            command.args(words);
+            command
+        }
+        None if cfg!(target_os = "windows") => {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        }
+        None if cfg!(target_os = "macos") => Command::new("open"),
+        None => Command::new("xdg-open"),
+    };
+    command.arg(path);
+    command
+}
+
+/// Splits a command line into words at whitespace outside of quotes. Single quotes keep
+/// everything. Elsewhere a backslash escapes a quote, a backslash or whitespace and is kept
+/// before anything else, so Windows paths work unquoted.
+pub fn command_words(line: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => word.get_or_insert_default().push(c),
+            (_, '\\') => {
+                let word = word.get_or_insert_default();
+                match chars.next_if(|c| matches!(c, '\'' | '"' | '\\') || c.is_whitespace()) {
+                    Some(c) => word.push(c),
+                    None => word.push('\\'),
+                }
+            }
+            (Some(_), c) => word.get_or_insert_default().push(c),
+            (None, '\'' | '"') => {
+                word.get_or_insert_default();
+                quote = Some(c);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Starts `ffplay` and waits for it on a thread of its own, which reports a failure.
+fn watch_ffplay(mut ffplay: Command, response_tx: Sender<Response>) {
+    match ffplay.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn() {
+        Ok(child) => {
+            std::thread::spawn(move || {
+                if let Err(error) = wait_for_ffplay(child) {
+                    let _ = response_tx.send(Response::Error { id: None, error });
+                }
+            });
+        }
+        Err(e) => {
+            let _ = response_tx.send(Response::Error {
+                id: None,
+                error: format!("Could not run ffplay: {e}"),
+            });
+        }
+    }
+}
+
+fn wait_for_ffplay(child: std::process::Child) -> Result<(), String> {
+    let ffplay_output = child
+        .wait_with_output()
+        .map_err(|e| format!("Could not run ffplay: {e}"))?;
+    if ffplay_output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&ffplay_output.stderr);
+    log::error!(
+        "ffplay output:\ncode: {:?}, \n{}\n{}",
+        ffplay_output.status.code(),
+        String::from_utf8_lossy(&ffplay_output.stdout),
+        stderr,
+    );
+    Err(
+        match stderr.lines().rev().find(|line| !line.trim().is_empty()) {
+            Some(line) => format!("ffplay failed: {line}"),
+            None => format!("ffplay failed with {}", ffplay_output.status),
+        },
+    )
+}
+
+/// Runs ffmpeg and reports the position it wrote up to, read from `-progress`.
+fn convert(args: Vec<String>, mut on_progress: impl FnMut(f64)) -> Result<(), String> {
+    let mut child = Command::new("ffmpeg")
+        .args(["-nostdin", "-nostats", "-progress", "pipe:1"])
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not run ffmpeg: {e}"))?;
+    // Drained on its own so ffmpeg does not block on a full stderr pipe
+    let mut stderr_pipe = child.stderr.take().unwrap();
+    let stderr = std::thread::spawn(move || {
+        let mut stderr = String::new();
+        let _ = std::io::Read::read_to_string(&mut stderr_pipe, &mut stderr);
+        stderr
+    });
+    for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if let Some(seconds) = progress_seconds(&line) {
+            on_progress(seconds);
+        }
+    }
+    let status = child
+        .wait()
+        .map_err(|e| format!("Could not wait for ffmpeg: {e}"))?;
+    let stderr = stderr.join().unwrap_or_default();
+    if status.success() {
+        Ok(())
+    } else {
+        Err(stderr.lines().last().unwrap_or("ffmpeg failed").to_string())
+    }
+}
+
+/// Parses the output position from a line of ffmpeg's `-progress` output.
+fn progress_seconds(line: &str) -> Option<f64> {
+    let micros: f64 = line.strip_prefix("out_time_us=")?.trim().parse().ok()?;
+    Some(micros / 1_000_000.)
+}
+
+fn probe(input: &Path) -> Result<ProbeInfo, String> {
+    let ffprobe_output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_format",
+            "-show_streams",
+            "-of",
+            "json",
+        ])
+        .arg(input)
+        .output()
+        .map_err(|e| format!("Could not run ffprobe: {e}"))?;
+    if !ffprobe_output.status.success() {
+        return Err(String::from_utf8_lossy(&ffprobe_output.stderr).to_string());
+    }
+    let json = String::from_utf8_lossy(&ffprobe_output.stdout);
+    Ok(ProbeInfo {
+        color: ColorMetadata::from_ffprobe_json(&json)?,
+        video_streams: VideoStream::list_from_ffprobe_json(&json)?,
+        duration: duration_from_ffprobe_json(&json),
+        raw: json.into_owned(),
+    })
+}
+
+fn duration_from_ffprobe_json(json: &str) -> Option<f64> {
+    #[derive(Deserialize)]
+    struct Format {
+        duration: Option<String>,
+    }
+    #[derive(Deserialize)]
+    struct Probe {
+        format: Option<Format>,
+    }
+
+    let probe: Probe = serde_json::from_str(json).ok()?;
+    probe.format?.duration?.parse().ok()
+}
+
+fn extract_frame(args: Vec<String>, output: FrameOutput) -> Result<RgbaImage, String> {
+    extract_image(args, output).and_then(into_rgba8)
+}
+
+/// The 8-bit frame and, for frames with 16 bits per channel, the frame at full precision.
+fn split_precise(img: DynamicImage) -> Result<(RgbaImage, Option<Rgba16Image>), String> {
+    let precise = matches!(
+        img,
+        DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_)
+    )
+    .then(|| img.to_rgba16());
+    Ok((into_rgba8(img)?, precise))
+}
+
+/// Every extraction writes to its own file, which is removed again even if ffmpeg failed halfway.
+fn extract_image(args: Vec<String>, output: FrameOutput) -> Result<DynamicImage, String> {
+    let result = run_extract_frame(args, &output);
+    if let FrameOutput::File(path) = output {
+        let _ = std::fs::remove_file(path);
+    }
+    result
+}
+
+fn run_extract_frame(args: Vec<String>, output: &FrameOutput) -> Result<DynamicImage, String> {
+    let ffmpeg_output = Command::new("ffmpeg")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Could not run ffmpeg: {e}"))?;
+    info!("Command status: {:?}", ffmpeg_output.status);
+    if !ffmpeg_output.status.success() {
+        let stderr = String::from_utf8_lossy(&ffmpeg_output.stderr);
+        log::error!(
+            "Could not extract frame:\nstatus: {},\n{}\n{stderr}",
+            ffmpeg_output.status,
+            String::from_utf8_lossy(&ffmpeg_output.stdout),
+        );
+        // ffmpeg reports a missing or unreadable input on the last line
+        return Err(match stderr.lines().last() {
+            Some(line) if !line.trim().is_empty() => format!("Could not extract frame: {line}"),
+            _ => "Could not extract frame!".to_string(),
+        });
+    }
+    log_ffmpeg_output(&String::from_utf8_lossy(&ffmpeg_output.stderr));
+    info!("Output: {:?}", output);
+    match output {
+        FrameOutput::File(path) => ImageReader::open(path)
+            .map_err(|e| format!("Could not open extracted frame: {e}"))
+            .and_then(decode_frame),
+        FrameOutput::Pipe(_) => ImageReader::new(Cursor::new(ffmpeg_output.stdout))
+            .with_guessed_format()
+            .map_err(|e| format!("Could not read extracted frame: {e}"))
+            .and_then(decode_frame),
+    }
+}
+
+fn decode_frame<R: BufRead + Seek>(reader: ImageReader<R>) -> Result<DynamicImage, String> {
+    reader
+        .decode()
+        .map_err(|e| format!("Could not decode extracted frame: {e}"))
+}
+
+/// Converts a frame to 8-bit RGBA, which the preview works on. Frames with more than 8 bits
+/// per channel (e.g. 16-bit TIFF or PNG) are rounded to the nearest 8-bit value and float
+/// frames are clamped to `0.0..=1.0` first.
+fn into_rgba8(img: DynamicImage) -> Result<RgbaImage, String> {
+    match img {
+        DynamicImage::ImageRgba8(img) => Ok(img),
+        DynamicImage::ImageLuma8(_)
+        | DynamicImage::ImageLumaA8(_)
+        | DynamicImage::ImageRgb8(_)
+        | DynamicImage::ImageLuma16(_)
+        | DynamicImage::ImageLumaA16(_)
+        | DynamicImage::ImageRgb16(_)
+        | DynamicImage::ImageRgba16(_)
+        | DynamicImage::ImageRgb32F(_)
+        | DynamicImage::ImageRgba32F(_) => Ok(img.into_rgba8()),
+        img => Err(format!(
+            "Extracted frame has unsupported pixel format {:?}",
+            img.color()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use roxmltree::Document;
+
+    use super::*;
+
+    #[test]
+    fn exposure_from_xml() {
+        let xml = r#"
+               <filter id="filter6">
+                <property name="mlt_service">avfilter.exposure</property>
+                <property name="kdenlive_id">avfilter.exposure</property>
+                <property name="av.exposure">00:00:00.000=0</property>
+                <property name="av.black">00:00:00.000=0</property>
+                <property name="kdenlive:collapsed">1</property>
+                <property name="disable">1</property>
+               </filter>
+            "#;
+        let doc = Document::parse(xml).unwrap();
+        let root = &doc.root();
+
+        let filter = root.try_into();
+        assert_eq!(
+            filter,
+            Ok(FilterExposure {
+                is_active: false,
+                exposure: 0.0,
+                black: 0.0,
+                relative: false,
+                knee: 0.,
+            })
+        );
+    }
+
+    #[test]
+    fn colorkey_filter_string() {
+        let filter = FilterColorKey {
+            is_active: true,
+            color: [0x12, 0xAB, 0x0F],
+            similarity: 0.3,
+            blend: 2.,
+        };
+        assert_eq!(
+            filter.to_filter_string(),
+            "colorkey=color=0x12AB0F:similarity=0.3:blend=1"
+        );
+
+        let filter: Box<dyn Filter> = Box::new(filter);
+        let json = serde_json::to_string(&filter).unwrap();
+        let filter: Box<dyn Filter> = serde_json::from_str(&json).unwrap();
+        assert!(filter.is_active());
+        assert_eq!(
+            filter.to_filter_string(),
+            "colorkey=color=0x12AB0F:similarity=0.3:blend=1"
+        );
+    }
+
+    #[test]
+    fn slate() {
+        let mut slate = Slate {
+            is_active: true,
+            text: "Take 2: it's good".to_string(),
+            date: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            slate.filter_string("a.mov", 90).unwrap(),
+            [
+                r"drawtext=text='a.mov':expansion=none:x=w*0.1:y=h*0.9-90:fontsize=24:fontcolor=white:box=1:boxcolor=black@0.5:boxborderw=6",
+                r"drawtext=text='Take 2\: it’s good':expansion=none:x=w*0.1:y=h*0.9-60:fontsize=24:fontcolor=white:box=1:boxcolor=black@0.5:boxborderw=6",
+                r"drawtext=text='%{pts\:hms\:90}':x=w*0.1:y=h*0.9-30:fontsize=24:fontcolor=white:box=1:boxcolor=black@0.5:boxborderw=6",
+            ]
+            .join(",")
+        );
+        assert_eq!(
+            split_top_level(&slate.filter_string("a.mov", 0).unwrap(), ',').len(),
+            3
+        );
+
+        slate = Slate {
+            is_active: true,
+            clip_name: false,
+            timecode: false,
+            position: SlatePosition::TopRight,
+            background: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            slate.filter_string("a.mov", 0).unwrap(),
+            r"drawtext=text='%{localtime\:%Y-%m-%d}':x=w*0.9-text_w:y=h*0.1+0:fontsize=24:fontcolor=white"
+        );
+        slate.date = false;
+        assert_eq!(slate.filter_string("a.mov", 0), None);
+    }
+
+    #[test]
+    fn filter_metadata_args() {
+        let mut filter_option = FilterOption {
+            filters: vec![
+                Box::new(FilterCustom {
+                    is_active: true,
+                    expression: r#"drawtext=text="$HOME""#.to_string(),
+                    single_filter: false,
+                }),
+                Box::new(FilterCustom {
+                    is_active: false,
+                    expression: "hflip".to_string(),
+                    single_filter: false,
+                }),
+            ],
+            ..Default::default()
+        };
+
+        assert!(FilterMetadata { is_active: false }
+            .to_option_args(&filter_option)
+            .is_empty());
+        assert_eq!(
+            FilterMetadata { is_active: true }.to_option_args(&filter_option),
+            vec!["-metadata", r#"comment=drawtext=text="$HOME""#]
+        );
+
+        filter_option.mask.is_active = true;
+        let args = FilterMetadata { is_active: true }.to_option_args(&filter_option);
+        assert!(args[1].starts_with(r#"comment=split[base][fg];[fg]drawtext=text="$HOME","#));
+    }
+
+    #[test]
+    fn filter_metadata_is_truncated() {
+        let summary = metadata_summary(&"a\n\"".repeat(FILTER_METADATA_MAX_LEN));
+        assert_eq!(summary.chars().count(), FILTER_METADATA_MAX_LEN);
+        assert!(summary.starts_with("a \"a"));
+        assert!(summary.ends_with("..."));
+        assert_eq!(metadata_summary("eq=gamma=1.1"), "eq=gamma=1.1");
+    }
+
+    #[test]
+    fn rational() {
+        let parse = |s: &str| s.parse::<Rational>().map(|r| r.to_string());
+        assert_eq!(parse("30000/1001").as_deref(), Ok("30000/1001"));
+        assert_eq!(parse(" 16:9 ").as_deref(), Ok("16/9"));
+        assert_eq!(parse("50/2").as_deref(), Ok("25"));
+        assert_eq!(parse("29.97").as_deref(), Ok("2997/100"));
+        assert_eq!(parse("23.976").as_deref(), Ok("2997/125"));
+        assert_eq!(parse("25").as_deref(), Ok("25"));
+        assert_eq!(parse(".5").as_deref(), Ok("1/2"));
+        for invalid in [
+            "",
+            "0",
+            "1/0",
+            "-25",
+            "1e3",
+            "abc",
+            "1.2.3",
+            "99999999999/1",
+        ] {
+            assert!(parse(invalid).is_err(), "{invalid}");
+        }
+
+        let framerate: InputFramerateOption =
+            serde_json::from_str(r#"{"is_active": true, "framerate": "24000/1001"}"#).unwrap();
+        assert_eq!(framerate.to_option_args(), ["-r", "24000/1001"]);
+        assert_eq!(
+            serde_json::to_value(&framerate).unwrap()["framerate"],
+            "24000/1001"
+        );
+    }
+
+    #[test]
+    fn aspect_ratio_filters() {
+        let sar = FilterSetSar {
+            is_active: true,
+            num: 4,
+            den: 3,
+        };
+        let dar = FilterSetDar {
+            is_active: true,
+            num: 16,
+            den: 9,
+        };
+        assert_eq!(sar.to_filter_string(), "setsar=sar=4/3");
+        assert_eq!(dar.to_filter_string(), "setdar=dar=16/9");
+
+        let mut filter_option = FilterOption {
+            filters: vec![Box::new(sar)],
+            ..Default::default()
+        };
+        assert_eq!(filter_option.sample_aspect_ratio(1440, 1080), 4.0 / 3.0);
+        filter_option.filters.push(Box::new(dar));
+        assert_eq!(filter_option.sample_aspect_ratio(1440, 1080), 4.0 / 3.0);
+        filter_option.filters.push(Box::new(FilterSetDar {
+            is_active: true,
+            num: 4,
+            den: 3,
+        }));
+        assert_eq!(filter_option.sample_aspect_ratio(1440, 1080), 1.0);
+    }
+
+    #[test]
+    fn colortemp_with_tint() {
+        let mut filter = FilterColortemp {
+            is_active: true,
+            temperature: 5600,
+            mix: 0.5,
+            tint: 0.,
+        };
+        assert_eq!(
+            filter.to_filter_string(),
+            "colortemperature=temperature=5600:mix=0.5:pl=1"
+        );
+        filter.tint = 0.2;
+        assert_eq!(
+            filter.to_filter_string(),
+            "colortemperature=temperature=5600:mix=0.5:pl=1,colorbalance=rm=0.1:gm=-0.2:bm=0.1:pl=1"
+        );
+
+        let filter: FilterColortemp =
+            serde_json::from_str(r#"{"is_active":true,"temperature":4000}"#).unwrap();
+        assert_eq!(filter.mix, 1.);
+        assert_eq!(filter.tint, 0.);
+    }
+
+    #[test]
+    fn filter_actions() {
+        let ctx = egui::Context::default();
+        let mut filter_option = FilterOption {
+            filters: vec![
+                Box::new(FilterScale {
+                    is_active: true,
+                    width: 640,
+                    height: 360,
+                }),
+                Box::<FilterLut>::default(),
+            ],
+            ..Default::default()
+        };
+        assert!(filter_option.apply_action(&ctx, 0, FilterAction::Duplicate));
+        assert_eq!(filter_option.filters[1].to_filter_string(), "scale=640:360");
+        assert!(filter_option.apply_action(&ctx, 1, FilterAction::Reset));
+        assert!(!filter_option.filters[1].is_active());
+        assert!(!filter_option.apply_action(&ctx, 0, FilterAction::Copy));
+        assert!(filter_option.apply_action(&ctx, 2, FilterAction::Paste));
+        assert_eq!(filter_option.filters[3].to_filter_string(), "scale=640:360");
+        assert!(filter_option.apply_action(&ctx, 2, FilterAction::MoveToTop));
+        assert_eq!(filter_option.filters[0].name(), "LUT");
+        assert!(filter_option.apply_action(&ctx, 0, FilterAction::Remove));
+        assert_eq!(filter_option.filters.len(), 3);
+    }
+
+    #[test]
+    fn tone_curve() {
+        let mut filter = FilterToneCurve::default();
+        assert_eq!(
+            filter.to_filter_string(),
+            "curves=all='0.000/0.000 0.125/0.125 0.375/0.375 0.625/0.625 0.875/0.875 1.000/1.000'"
+        );
+        filter.shadows = -1.;
+        filter.darks = -1.;
+        filter.highlights = 1.;
+        let points = filter.control_points();
+        assert!(points.windows(2).all(|w| w[0][1] <= w[1][1]));
+        assert_eq!(points[1][1], 0.);
+        assert_eq!(points[4][1], 1.);
+    }
+
+    #[test]
+    fn limiter() {
+        let filter = FilterLimiter {
+            is_active: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.to_filter_string(),
+            r"lutyuv=y=clip(val\,16\,235):u=clip(val\,16\,240):v=clip(val\,16\,240)"
+        );
+    }
+
+    #[test]
+    fn deshake() {
+        let filter = FilterDeshake {
+            is_active: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.to_filter_string(),
+            "deshake=x=-1:y=-1:w=-1:h=-1:rx=16:ry=16"
+        );
+    }
+
+    #[test]
+    fn limiter_lines() {
+        let limiter = FilterLimiter::default();
+        assert_eq!(limiter.luma_limits(false), [0., 100.]);
+        let [min, max] = limiter.luma_limits(true);
+        assert!((min - 6.27).abs() < 0.01 && (max - 92.16).abs() < 0.01);
+    }
+
+    #[test]
+    fn colorspace_from_probe() {
+        let detected = ColorMetadata::from_ffprobe_json(
+            r#"{"programs": [], "streams": [{"color_space": "bt2020nc", "color_transfer": "unknown", "color_primaries": "bt2020"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(detected.color_transfer, None);
+        assert_eq!(detected.color_range, None);
+        assert!(!InputRange::Auto.is_full(detected.color_range.as_deref()));
+        assert!(InputRange::Auto.is_full(Some("pc")));
+        assert!(!InputRange::Limited.is_full(Some("pc")));
+
+        let mut filter = FilterColorspace {
+            is_active: true,
+            detected: Some(detected),
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.to_filter_string(),
+            "colorspace=space=bt709:trc=bt709:primaries=bt709"
+        );
+        filter.use_detected();
+        assert_eq!(
+            filter.to_filter_string(),
+            "colorspace=space=bt709:trc=bt709:primaries=bt709:ispace=bt2020ncl:iprimaries=bt2020"
+        );
+    }
+
+    #[test]
+    fn video_streams() {
+        let streams = VideoStream::list_from_ffprobe_json(
+            r#"{"streams": [
+                {"index": 0, "codec_name": "hevc", "width": 3840, "height": 2160, "disposition": {"attached_pic": 0}},
+                {"index": 3, "codec_name": "mjpeg", "width": 320, "height": 240, "disposition": {"attached_pic": 1}}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(streams[1].to_string(), "#3 mjpeg 320x240 (cover art)");
+
+        let mut input_file = InputFile::default();
+        assert!(input_file.map_args(true).is_empty());
+        input_file.set_video_streams(streams.clone());
+        assert_eq!(input_file.map_args(false), vec!["-map", "0:v:0"]);
+        input_file.video_stream = Some(1);
+        assert_eq!(
+            input_file.map_args(true),
+            vec!["-map", "0:v:1", "-map", "0:a?"]
+        );
+        assert_eq!(input_file.ffplay_stream_args(), vec!["-vst", "v:1"]);
+
+        // A single stream file after a file with several
+        input_file.set_video_streams(streams[..1].to_vec());
+        assert!(input_file.map_args(true).is_empty());
+        input_file.video_stream = Some(3);
+        input_file.set_video_streams(streams);
+        assert_eq!(input_file.map_args(false), vec!["-map", "0:v:0"]);
+    }
+
+    #[test]
+    fn ffprobe_audio_streams() {
+        let json = r#"{"streams": [
+                {"index": 0, "codec_type": "audio", "codec_name": "aac"},
+                {"index": 1, "codec_type": "video", "codec_name": "h264", "width": 1920, "height": 1080,
+                 "color_primaries": "bt709", "disposition": {"attached_pic": 0}}
+            ], "format": {"duration": "1.5"}}"#;
+        let streams = VideoStream::list_from_ffprobe_json(json).unwrap();
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].to_string(), "#1 h264 1920x1080");
+        let color = ColorMetadata::from_ffprobe_json(json).unwrap();
+        assert_eq!(color.color_primaries.as_deref(), Some("bt709"));
+    }
+
+    #[test]
+    fn failed_extraction_cleanup() {
+        let temp_dir = temp_dir::TempDir::new().unwrap();
+        let path = temp_dir.child("preview-7.bmp");
+        std::fs::write(&path, "half-written").unwrap();
+        let result = extract_frame(
+            vec!["-no-such-option".to_string()],
+            FrameOutput::File(path.clone()),
+        );
+        assert!(result.is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn external_player() {
+        let path = Path::new("/tmp/proxy-3.mp4");
+        let command = player_command("mpv --loop-file=inf", path);
+        assert_eq!(command.get_program(), "mpv");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            vec!["--loop-file=inf", "/tmp/proxy-3.mp4"]
+        );
+        let command = player_command("  ", path);
+        assert_eq!(command.get_args().last(), Some(path.as_os_str()));
+        let command = player_command(r#""/opt/My Player/player" -fs"#, path);
+        assert_eq!(command.get_program(), "/opt/My Player/player");
+
+        let temp_dir = temp_dir::TempDir::new().unwrap();
+        let player = temp_dir.child("my player");
+        std::fs::write(&player, "").unwrap();
+        let command = player_command(&player.to_string_lossy(), path);
+        assert_eq!(command.get_program(), player.as_os_str());
+    }
+
+    #[test]
+    fn split_command_line() {
+        assert_eq!(command_words(r#"a\ b "" "c\"d""#), ["a b", "", r#"c"d"#]);
+        assert_eq!(
+            command_words(r"C:\mpv\mpv.exe 'it''s'"),
+            [r"C:\mpv\mpv.exe", "its"]
+        );
+    }
+
+    #[test]
+    fn ffmpeg_log_levels() {
+        assert_eq!(
+            FfmpegLogLevel::Warning.to_option_args(),
+            vec!["-loglevel", "level+warning"]
+        );
+        assert_eq!(
+            ffmpeg_line_level("[h264 @ 0x5581] [error] non-existing PPS 0 referenced"),
+            log::Level::Error
+        );
+        assert_eq!(
+            ffmpeg_line_level("[warning] deprecated pixel format"),
+            log::Level::Warn
+        );
+        assert_eq!(ffmpeg_line_level("frame=    1 fps=0.0"), log::Level::Info);
+    }
+
+    #[test]
+    fn mask() {
+        let mut filter_option = FilterOption {
+            filters: vec![Box::new(FilterCustom {
+                is_active: true,
+                expression: "hue=s=0".to_string(),
+                single_filter: false,
+            })],
+            ..Default::default()
+        };
+        assert_eq!(filter_option.to_option_args(), vec!["-vf", "hue=s=0"]);
+        filter_option.mask = FilterMask {
+            is_active: true,
+            shape: MaskShape::Rectangle,
+            center: [0.5, 0.25],
+            size: [1., 0.5],
+            feather: 0.,
+            invert: true,
+        };
+        assert_eq!(
+            filter_option.to_option_args()[1],
+            r"split[base][fg];[fg]hue=s=0,format=rgba,geq=r=r(X\,Y):g=g(X\,Y):b=b(X\,Y):a=255-255*lte(max(abs((X-0.5*W)/(0.5*W))\,abs((Y-0.25*H)/(0.25*H)))\,1)[graded];[base]format=rgba[ungraded];[ungraded][graded]overlay=format=auto"
+        );
+        filter_option.input_format = Some("yuv420p10le".to_string());
+        assert!(
+            filter_option.to_option_args()[1].ends_with("overlay=format=auto,format=yuv420p10le")
+        );
+        assert!(filter_option.chain_warnings().is_empty());
+        filter_option.filters.push(Box::new(FilterScale {
+            is_active: true,
+            width: 1280,
+            height: 720,
+        }));
+        assert_eq!(
+            filter_option.chain_warnings(),
+            ["The mask only works if no active filter scales the frame"]
+        );
+    }
+
+    #[test]
+    fn custom_filter_with_commas() {
+        let filter_option = FilterOption {
+            filters: vec![
+                Box::new(FilterCustom {
+                    is_active: true,
+                    expression: "select=gt(scene,0.4);drawtext=text='a, b'".to_string(),
+                    single_filter: true,
+                }),
+                Box::new(FilterCustom {
+                    is_active: true,
+                    expression: r"hflip,select=gt(scene\,0.4)".to_string(),
+                    single_filter: false,
+                }),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            filter_option.to_option_args()[1],
+            r"select=gt(scene\,0.4)\;drawtext=text='a, b',hflip,select=gt(scene\,0.4)"
+        );
+    }
+
+    #[test]
+    fn registry_names_match() {
+        for (name, new) in FILTER_REGISTRY {
+            assert_eq!(new().name(), *name);
+        }
+    }
+
+    #[test]
+    fn clean_params() {
+        assert_eq!(format_param(1.000_000_1), "1");
+        assert_eq!(format_param(-0.), "0");
+        assert_eq!(format_param(-0.000_01), "0");
+        assert_eq!(format_param(0.123_456), "0.1235");
+        assert_eq!(format_param(-1.5), "-1.5");
+        assert_eq!(format_param(2.), "2");
+
+        let eq = FilterEq {
+            is_active: true,
+            contrast: 1.000_000_1,
+            brightness: -0.,
+            saturation: 0.999_999_9,
+            gamma: 1.234_567,
+            gamma_r: 1.,
+            gamma_g: 1.,
+            gamma_b: 1.,
+        };
+        assert_eq!(
+            eq.to_filter_string(),
+            "eq=contrast=1:brightness=0:saturation=1:gamma=1.2346:gamma_r=1:gamma_g=1:gamma_b=1"
+        );
+        let exposure = FilterExposure {
+            exposure: 0.7 - 0.4,
+            black: -0.,
+            ..Default::default()
+        };
+        assert_eq!(exposure.to_filter_string(), "exposure=exposure=0.3:black=0");
+    }
+
+    #[test]
+    fn modified_filters() {
+        let mut exposure = FilterExposure {
+            is_active: true,
+            relative: true,
+            ..Default::default()
+        };
+        assert!(!is_modified(&exposure));
+        exposure.exposure = 0.5;
+        assert!(is_modified(&exposure));
+
+        let scale = FILTER_REGISTRY[0].1();
+        assert!(is_modified(scale.as_ref()));
+        assert!(!is_modified(scale.boxed_default().as_ref()));
+    }
+
+    #[test]
+    fn exposure_shoulder() {
+        let mut exposure = FilterExposure {
+            is_active: true,
+            exposure: 1.,
+            ..Default::default()
+        };
+        assert_eq!(exposure.to_filter_string(), "exposure=exposure=1:black=0");
+
+        exposure.knee = 0.5;
+        let filter_string = exposure.to_filter_string();
+        let (curve, rest) = filter_string.split_once(',').unwrap();
+        assert_eq!(rest, "exposure=exposure=1:black=0");
+        let points: Vec<(f32, f32)> = curve
+            .strip_prefix("curves=master='")
+            .and_then(|c| c.strip_suffix('\''))
+            .unwrap()
+            .split(' ')
+            .map(|p| {
+                let (x, y) = p.split_once('/').unwrap();
+                (x.parse().unwrap(), y.parse().unwrap())
+            })
+            .collect();
+        assert_eq!(points.len(), 17);
+        // Below the knee the curve is the identity, above it the doubled values stay below 1
+        assert_eq!(points[4], (0.25, 0.25));
+        assert!(points.windows(2).all(|w| w[1].1 > w[0].1));
+        assert!(points[16].1 * 2. < 1.);
+        assert!(points[16].1 * 2. > 0.9);
+
+        exposure.exposure = -1.;
+        assert_eq!(exposure.to_filter_string(), "exposure=exposure=-1:black=0");
+
+        let json = serde_json::json!({"is_active": true, "exposure": 1.0, "black": 0.0});
+        let exposure: FilterExposure = serde_json::from_value(json).unwrap();
+        assert_eq!(exposure.knee, 0.);
+    }
+
+    #[test]
+    fn exposure_nudge() {
+        let mut exposure = FilterExposure {
+            exposure: 0.5,
+            relative: true,
+            ..Default::default()
+        };
+        exposure.nudge(1. / 3.);
+        assert!((exposure.exposure - 0.8333).abs() < 1e-3);
+        exposure.nudge(5.);
+        assert_eq!(exposure.exposure, 3.);
+
+        let json = serde_json::to_string(&exposure).unwrap();
+        assert!(!json.contains("relative"));
+    }
+
+    #[test]
+    fn filter_groups() {
+        let ctx = egui::Context::default();
+        let custom = |expression: &str| -> Box<dyn Filter> {
+            Box::new(FilterCustom {
+                is_active: true,
+                expression: expression.to_string(),
+                single_filter: false,
+            })
+        };
+        let mut filter_option: FilterOption = serde_json::from_value(serde_json::json!({
+            "filters": [
+                {"type": "FilterCustom", "is_active": true, "expression": "a"},
+                {"type": "FilterCustom", "is_active": true, "expression": "b"},
+            ]
+        }))
+        .unwrap();
+        assert!(filter_option.groups.is_empty());
+        filter_option.filters.push(custom("c"));
+        filter_option.groups = vec![
+            FilterGroup {
+                name: "Geometry".to_string(),
+                len: 1,
+            },
+            FilterGroup {
+                name: "Look".to_string(),
+                len: 1,
+            },
+        ];
+        filter_option.fix_groups();
+        assert_eq!(filter_option.group_ranges(), vec![0..1, 1..3]);
+
+        assert!(filter_option.apply_group_action(0, GroupAction::MoveDown));
+        assert_eq!(filter_option.to_option_args()[1], "b,c,a");
+        assert_eq!(filter_option.groups[0].name, "Look");
+
+        assert!(filter_option.apply_action(&ctx, 1, FilterAction::MoveToNextGroup));
+        assert_eq!(filter_option.to_option_args()[1], "b,c,a");
+        assert_eq!(filter_option.group_ranges(), vec![0..1, 1..3]);
+        assert!(filter_option.apply_action(&ctx, 2, FilterAction::MoveToTop));
+        assert_eq!(filter_option.to_option_args()[1], "b,a,c");
+        assert!(!filter_option.apply_action(&ctx, 0, FilterAction::MoveToPreviousGroup));
+
+        filter_option.apply_group_action(1, GroupAction::Ungroup);
+        assert_eq!(filter_option.group_ranges(), vec![0..3]);
+    }
+
+    #[test]
+    fn color_balance_as_curves() {
+        let mut color_balance = FilterColorBalance {
+            is_active: true,
+            export_as_curves: true,
+            ..Default::default()
+        };
+        let identity = "0/0 0.0625/0.063 0.125/0.125 0.1875/0.188 0.25/0.25 0.3125/0.313 \
+                        0.375/0.375 0.4375/0.438 0.5/0.5 0.5625/0.563 0.625/0.625 \
+                        0.6875/0.688 0.75/0.75 0.8125/0.813 0.875/0.875 0.9375/0.938 1/1";
+        assert_eq!(
+            color_balance.to_filter_string(),
+            format!("curves=r='{identity}':g='{identity}':b='{identity}'")
+        );
+
+        assert_eq!(FilterColorBalance::transfer(0., 1., 0., 0.), 0.7);
+        assert_eq!(FilterColorBalance::transfer(1., 1., 0., 0.), 1.);
+        assert_eq!(FilterColorBalance::transfer(0.5, 0., -0.5, 0.), 0.15);
+        assert_eq!(FilterColorBalance::transfer(1., 0., 0., -1.), 0.3);
+
+        color_balance.shadows_red = 0.5;
+        let curves = color_balance.to_filter_string();
+        assert!(curves.starts_with("curves=r='0/0.35 "));
+        assert!(curves.ends_with(&format!(":b='{identity}'")));
+
+        color_balance.preserve_lightness = true;
+        let curves = color_balance.to_filter_string();
+        assert!(curves.starts_with("curves=r='0/0 "));
+        assert!(!curves.ends_with(&format!(":b='{identity}'")));
+        assert_eq!(
+            FilterColorBalance::with_lightness([0.7, 0.5, 0.5], 0.5).map(|v| (v * 1000.).round()),
+            [625., 375., 375.]
+        );
+        assert_eq!(
+            FilterColorBalance::with_lightness([0.35, 0., 0.], 0.),
+            [0.; 3]
+        );
+
+        color_balance.export_as_curves = false;
+        assert!(color_balance
+            .to_filter_string()
+            .starts_with("colorbalance=rs=0.5:"));
+        assert!(color_balance.to_filter_string().ends_with(":pl=1"));
+    }
+
+    #[test]
+    fn chain_warnings() {
+        let custom = |expression: &str| -> Box<dyn Filter> {
+            Box::new(FilterCustom {
+                is_active: true,
+                expression: expression.to_string(),
+                single_filter: false,
+            })
+        };
+        let mut filter_option = FilterOption {
+            filters: vec![
+                Box::new(FilterScale {
+                    is_active: true,
+                    width: 1280,
+                    height: 720,
+                }),
+                custom("crop=1920:1080:0:0"),
+                Box::new(FilterLut {
+                    is_active: true,
+                    file: "/does/not/exist.cube".to_string(),
+                    ..Default::default()
+                }),
+                custom("scale=640:-2,crop=iw/2:ih"),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            filter_option.chain_warnings(),
+            vec![
+                "2 scale filters are active",
+                "Crop to 1920x1080 is larger than the scaled frame of 1280x720",
+                "LUT file /does/not/exist.cube does not exist",
+            ]
+        );
+
+        filter_option.filters.truncate(2);
+        filter_option.filters[1] = custom("crop=w=640:h=360");
+        assert!(filter_option.chain_warnings().is_empty());
+    }
+
+    #[test]
+    fn lut_order() {
+        let lut = || -> Box<dyn Filter> {
+            Box::new(FilterLut {
+                is_active: true,
+                file: "look.cube".to_string(),
+                ..Default::default()
+            })
+        };
+        let eq = || -> Box<dyn Filter> {
+            Box::new(FilterEq {
+                is_active: true,
+                ..Default::default()
+            })
+        };
+        let scale = || -> Box<dyn Filter> {
+            Box::new(FilterScale {
+                is_active: true,
+                width: 1280,
+                height: 720,
+            })
+        };
+        let mut filter_option = FilterOption {
+            filters: vec![lut(), scale(), eq(), scale()],
+            ..Default::default()
+        };
+        assert_eq!(filter_option.grading_after_lut(), vec!["Eq"]);
+        assert!(filter_option.move_luts_last());
+        let names = |f: &FilterOption| f.filters.iter().map(|f| f.name()).collect::<Vec<_>>();
+        assert_eq!(names(&filter_option), ["Scale", "Eq", "LUT", "Scale"]);
+        assert!(filter_option.grading_after_lut().is_empty());
+        assert!(!filter_option.move_luts_last());
+
+        // LUTs stay in their group
+        filter_option.filters = vec![lut(), eq(), eq()];
+        filter_option.groups = vec![
+            FilterGroup {
+                name: "Look".to_string(),
+                len: 2,
+            },
+            FilterGroup {
+                name: "Finish".to_string(),
+                len: 1,
+            },
+        ];
+        assert!(filter_option.move_luts_last());
+        assert_eq!(names(&filter_option), ["Eq", "LUT", "Eq"]);
+        assert_eq!(filter_option.grading_after_lut(), vec!["Eq"]);
+    }
+
+    #[test]
+    fn filter_presets() {
+        let eq = FilterEq {
+            is_active: true,
+            contrast: 1.2,
+            ..Default::default()
+        };
+        let mut presets = FilterPresets::new();
+        PresetChange::Save {
+            filter: eq.name().to_string(),
+            name: "Punch".to_string(),
+            preset: serde_json::to_string(&eq as &dyn Filter).unwrap(),
+        }
+        .apply(&mut presets);
+        let preset: Box<dyn Filter> = serde_json::from_str(&presets["Eq"]["Punch"]).unwrap();
+        assert_eq!(preset.to_filter_string(), eq.to_filter_string());
+
+        PresetChange::Delete {
+            filter: "Eq".to_string(),
+            name: "Other".to_string(),
+        }
+        .apply(&mut presets);
+        assert_eq!(presets["Eq"].len(), 1);
+        PresetChange::Delete {
+            filter: "Eq".to_string(),
+            name: "Punch".to_string(),
+        }
+        .apply(&mut presets);
+        assert!(presets.is_empty());
+    }
+
+    #[test]
+    fn pinned_filters() {
+        let ctx = egui::Context::default();
+        let mut filter_option = FilterOption {
+            filters: ["a", "b", "c", "d"]
+                .into_iter()
+                .map(|expression| -> Box<dyn Filter> {
+                    Box::new(FilterCustom {
+                        is_active: true,
+                        expression: expression.to_string(),
+                        single_filter: false,
+                    })
+                })
+                .collect(),
+            groups: ["Geometry", "Look"]
+                .into_iter()
+                .map(|name| FilterGroup {
+                    name: name.to_string(),
+                    len: 2,
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        assert!(filter_option.apply_action(&ctx, 2, FilterAction::Pin));
+        assert_eq!(filter_option.to_option_args()[1], "c,a,b,d");
+        assert_eq!(filter_option.group_ranges(), vec![0..3, 3..4]);
+        assert!(filter_option.is_pinned(0));
+
+        assert!(filter_option.apply_action(&ctx, 2, FilterAction::MoveToTop));
+        assert_eq!(filter_option.to_option_args()[1], "c,b,a,d");
+
+        assert!(filter_option.apply_group_action(0, GroupAction::MoveDown));
+        assert_eq!(filter_option.to_option_args()[1], "c,d,b,a");
+        assert_eq!(filter_option.groups[0].name, "Look");
+        assert_eq!(filter_option.group_ranges(), vec![0..2, 2..4]);
+
+        assert!(filter_option.apply_action(&ctx, 0, FilterAction::Duplicate));
+        assert_eq!(filter_option.pinned, 2);
+        assert!(filter_option.apply_action(&ctx, 0, FilterAction::Unpin));
+        assert_eq!(filter_option.pinned, 1);
+        assert_eq!(filter_option.to_option_args()[1], "c,c,d,b,a");
+    }
+
+    #[test]
+    fn solo_filter() {
+        let ctx = egui::Context::default();
+        let mut filter_option = FilterOption {
+            filters: vec![
+                Box::new(FilterScale {
+                    is_active: true,
+                    width: 640,
+                    height: 360,
+                }),
+                Box::<FilterLut>::default(),
+            ],
+            solo: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            filter_option.preview_option_args(),
+            vec!["-vf", "lut3d=file=:interp=tetrahedral"]
+        );
+        assert_eq!(filter_option.to_option_args(), vec!["-vf", "scale=640:360"]);
+        filter_option.apply_action(&ctx, 0, FilterAction::Remove);
+        assert_eq!(filter_option.solo, None);
+    }
+
+    #[test]
+    fn find_filter() {
+        let mut filter_option = FilterOption {
+            filters: vec![Box::<FilterScale>::default(), Box::<FilterLut>::default()],
+            ..Default::default()
+        };
+        filter_option.find_mut::<FilterLut>().unwrap().file = "a.cube".to_string();
+        assert_eq!(
+            filter_option.filters[1].to_filter_string(),
+            "lut3d=file=a.cube:interp=tetrahedral"
+        );
+        assert!(filter_option.find_mut::<FilterEq>().is_none());
+    }
+
+    #[test]
+    fn decode_16_bit_tiff() {
+        let img = image::ImageBuffer::<image::Rgb<u16>, _>::from_pixel(
+            2,
+            1,
+            image::Rgb([65535, 32896, 0]),
+        );
+        let mut tiff = Cursor::new(Vec::new());
+        img.write_to(&mut tiff, image::ImageFormat::Tiff).unwrap();
+        tiff.set_position(0);
+
+        let reader = ImageReader::new(tiff).with_guessed_format().unwrap();
+        let decoded = decode_frame(reader).and_then(into_rgba8).unwrap();
+        assert_eq!(decoded.dimensions(), (2, 1));
+        assert_eq!(decoded.get_pixel(0, 0).0, [255, 128, 0, 255]);
+
+        let reader = ImageReader::new(Cursor::new(b"not an image".to_vec()))
+            .with_guessed_format()
+            .unwrap();
+        assert!(decode_frame(reader).is_err());
+    }
+
+    #[test]
+    fn precise_frames() {
+        assert_eq!(
+            FrameOutput::Pipe(FrameFormat::Png16).to_option_args(),
+            vec![
+                "-pix_fmt",
+                "rgba64be",
+                "-f",
+                "image2pipe",
+                "-vcodec",
+                "png",
+                "pipe:1"
+            ]
+        );
+        assert_eq!(
+            FrameOutput::File(PathBuf::from("preview-2.png")).to_option_args(),
+            vec!["-pix_fmt", "rgba64be", "preview-2.png"]
+        );
+        assert_eq!(
+            FrameOutput::File(PathBuf::from("preview-2.bmp")).to_option_args(),
+            vec!["preview-2.bmp"]
+        );
+
+        let img = Rgba16Image::from_pixel(1, 1, image::Rgba([4112, 4096, 0, 65535]));
+        let mut png = Cursor::new(Vec::new());
+        img.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        png.set_position(0);
+        let reader = ImageReader::new(png).with_guessed_format().unwrap();
+        let (image, precise) = decode_frame(reader).and_then(split_precise).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [16, 16, 0, 255]);
+        assert_eq!(precise, Some(img));
+
+        let (_, precise) = split_precise(DynamicImage::new_rgba8(1, 1)).unwrap();
+        assert_eq!(precise, None);
+    }
+
+    #[test]
+    fn filter_templates() {
+        let definitions = parse_filter_definitions(
+            r#"[{
+                "name": "House grain",
+                "template": "noise=alls={strength}:allf={flags},eq=gamma={gamma}:eval={eval}",
+                "params": [
+                    {"name": "strength", "type": "int", "min": 0, "max": 100, "default": 12},
+                    {"name": "flags", "type": "text", "default": "t+u"},
+                    {"name": "gamma", "type": "float", "min": 0.1, "max": 3, "default": 1.1},
+                    {"name": "eval", "type": "bool", "default": false}
+                ]
+            }]"#,
+        )
+        .unwrap();
+        let mut filter = FilterTemplated::new(definitions[0].clone());
+        assert_eq!(filter.name(), "House grain");
+        assert_eq!(
+            filter.to_filter_string(),
+            "noise=alls=12:allf=t+u,eq=gamma=1.1:eval=0"
+        );
+        assert_eq!(filter.ffmpeg_filters(), vec!["noise", "eq"]);
+
+        filter.values[0] = TemplateValue::Int(30);
+        let filter: Box<dyn Filter> = Box::new(filter);
+        let json = serde_json::to_string(&filter).unwrap();
+        let filter: Box<dyn Filter> = serde_json::from_str(&json).unwrap();
+        assert_eq!(filter.name(), "House grain");
+        assert!(filter.to_filter_string().starts_with("noise=alls=30:"));
+
+        // Saved values from an older definition
+        let mut filter = FilterTemplated {
+            values: vec![TemplateValue::Text("x".to_string())],
+            ..FilterTemplated::new(definitions[0].clone())
+        };
+        filter.fit_values();
+        assert_eq!(
+            filter.values,
+            FilterTemplated::new(definitions[0].clone()).values
+        );
+
+        assert_eq!(
+            parse_filter_definitions(r#"[{"name": "A", "template": "eq=gamma={g}"}]"#).unwrap_err(),
+            "Template of \"A\" uses {g}, which is not a parameter"
+        );
+        assert_eq!(
+            parse_filter_definitions(r#"[{"name": "Eq", "template": "eq"}]"#).unwrap_err(),
+            "Filter \"Eq\" is the name of a built-in filter"
+        );
+        assert_eq!(
+            parse_filter_definitions(
+                r#"[{"name": "A", "template": "eq"}, {"name": "A", "template": "hue"}]"#
+            )
+            .unwrap_err(),
+            "Filter \"A\" is defined more than once"
+        );
+
+        // Values are not searched for placeholders
+        let mut filter = FilterTemplated::new(definitions[0].clone());
+        filter.values[1] = TemplateValue::Text("{gamma}".to_string());
+        assert_eq!(
+            filter.to_filter_string(),
+            "noise=alls=12:allf={gamma},eq=gamma=1.1:eval=0"
+        );
+
+        // Values beyond the range of an older definition survive a draw
+        filter.values[0] = TemplateValue::Int(150);
+        filter.values[2] = TemplateValue::Float(5.);
+        let ctx = egui::Context::default();
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                filter.draw(ctx, ui);
+            });
+        });
+        assert_eq!(filter.values[0], TemplateValue::Int(150));
+        assert_eq!(filter.values[2], TemplateValue::Float(5.));
+    }
+
+    #[test]
+    fn serial_requests() {
+        let convert = Request::Convert {
+            input: PathBuf::from("a.png"),
+            args: vec![],
+        };
+        assert!(convert.is_serial());
+        assert!(!convert.is_low_priority());
+        let thumbnail = Request::Thumbnail {
+            key: String::new(),
+            args: vec![],
+            output: FrameOutput::Pipe(FrameFormat::Bmp),
+        };
+        assert!(!thumbnail.is_serial());
+        assert!(thumbnail.is_low_priority());
+        let proxy = Request::PlayProxy {
+            args: vec![],
+            output: PathBuf::from("proxy-1.mp4"),
+            player: String::new(),
+        };
+        assert!(proxy.is_serial());
+    }
+
+    #[test]
+    fn range_locks() {
+        let locks = [
+            RangeLock {
+                filter: "Exposure".to_string(),
+                param: "Exposure".to_string(),
+                min: -1.,
+                max: 1.,
+            },
+            RangeLock {
+                filter: "Deshake".to_string(),
+                param: "Search x".to_string(),
+                min: 8.,
+                max: 100.,
+            },
+            RangeLock {
+                filter: "Eq".to_string(),
+                param: "Gamma".to_string(),
+                min: 20.,
+                max: 30.,
+            },
+        ];
+        assert_eq!(
+            locked_range(&locks, "Exposure", "Exposure", -3.0f32..=3.0),
+            -1.0..=1.0
+        );
+        assert_eq!(
+            locked_range(&locks, "Exposure", "Black level", -1.0f32..=1.0),
+            -1.0..=1.0
+        );
+        assert_eq!(locked_range(&locks, "Deshake", "Search x", 0..=64), 8..=64);
+        assert_eq!(
+            locked_range(&locks, "Eq", "Gamma", 0.1f32..=10.0),
+            0.1..=10.0
+        );
+    }
+
+    #[test]
+    fn locked_slider_keeps_value() {
+        let ctx = egui::Context::default();
+        let locks = Arc::new(vec![RangeLock {
+            filter: "Exposure".to_string(),
+            param: "Exposure".to_string(),
+            min: -1.,
+            max: 1.,
+        }]);
+        ctx.data_mut(|d| d.insert_temp(range_locks_id(), locks));
+        let mut exposure = FilterExposure {
+            exposure: 2.5,
+            ..Default::default()
+        };
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                exposure.draw(ctx, ui);
+            });
+        });
+        assert_eq!(exposure.exposure, 2.5);
+    }
+
+    #[test]
+    fn cdl_slider_keeps_value() {
+        let ctx = egui::Context::default();
+        let mut cdl = FilterCdl {
+            slope: [6., 1., 1.],
+            saturation: 5.,
+            ..Default::default()
+        };
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                cdl.draw(ctx, ui);
+            });
+        });
+        assert_eq!(cdl.slope, [6., 1., 1.]);
+        assert_eq!(cdl.saturation, 5.);
+    }
+
+    #[test]
+    fn ffmpeg_capabilities() {
+        let version = "ffmpeg version 4.4.2-0ubuntu0.22.04.1 Copyright (c) 2000-2021 the FFmpeg \
+                       developers\nbuilt with gcc 11 (Ubuntu 11.2.0-19ubuntu1)\n";
+        assert_eq!(
+            FfmpegCapabilities::parse_version(version).as_deref(),
+            Some("4.4.2-0ubuntu0.22.04.1")
+        );
+        assert_eq!(FfmpegCapabilities::parse_version(""), None);
+
+        let filters = "Filters:\n  T.. = Timeline support\n  A = Audio input/output\n  \
+                       | = Source or sink filter\n \
+                       TSC colorbalance      V->V       Adjust the color balance.\n \
+                       ... curves            V->V       Adjust components curves.\n \
+                       ..C scale             V->V       Scale the input video size.\n";
+        let capabilities = FfmpegCapabilities {
+            version: Some("4.4".to_string()),
+            filters: Some(FfmpegCapabilities::parse_filters(filters)),
+        };
+        assert_eq!(
+            capabilities.filters,
+            Some(HashSet::from(
+                ["colorbalance", "curves", "scale"].map(ToString::to_string)
+            ))
+        );
+        let mut exposure = FilterExposure {
+            exposure: 1.,
+            knee: FilterExposure::DEFAULT_KNEE,
+            ..Default::default()
+        };
+        assert_eq!(exposure.ffmpeg_filters(), ["curves", "exposure"]);
+        assert_eq!(capabilities.missing_filters(&exposure), ["exposure"]);
+        exposure.knee = 0.;
+        assert_eq!(exposure.ffmpeg_filters(), ["exposure"]);
+        assert!(capabilities
+            .missing_filters(&FilterColorBalance::default())
+            .is_empty());
+        assert!(FfmpegCapabilities::default()
+            .missing_filters(&exposure)
+            .is_empty());
+    }
+
+    #[test]
+    fn conversion_progress() {
+        assert_eq!(progress_seconds("out_time_us=2500000"), Some(2.5));
+        assert_eq!(progress_seconds("out_time_us=N/A"), None);
+        assert_eq!(progress_seconds("progress=continue"), None);
+
+        let json = r#"{"streams": [], "format": {"duration": "12.480000"}}"#;
+        assert_eq!(duration_from_ffprobe_json(json), Some(12.48));
+        assert_eq!(duration_from_ffprobe_json(r#"{"streams": []}"#), None);
+    }
+
+    #[test]
+    fn extract_failure() {
+        let (response_tx, response_rx) = flume::unbounded();
+        handle_request_catching(
+            Request::ExtractFrame {
+                id: 7,
+                args: vec!["-no-such-option".to_string()],
+                output: FrameOutput::Pipe(FrameFormat::Bmp),
+            },
+            &response_tx,
+        );
+        assert!(matches!(
+            response_rx.try_recv(),
+            Ok(Response::Error { id: Some(7), .. })
+        ));
+    }
+
+    #[test]
+    fn play_failure() {
+        let (response_tx, response_rx) = flume::unbounded();
+        handle_request(
+            Request::Play {
+                args: vec!["-no-such-option".to_string()],
+            },
+            &response_tx,
+        );
+        assert!(matches!(
+            response_rx.recv_timeout(Duration::from_secs(10)),
+            Ok(Response::Error { id: None, error: e }) if e.starts_with("ffplay failed") || e.starts_with("Could not run ffplay")
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn convert_after_play() {
+        let (response_tx, response_rx) = flume::unbounded();
+        let start = std::time::Instant::now();
+        // Stands in for a player window that stays open
+        let mut player = Command::new("sleep");
+        player.arg("5");
+        watch_ffplay(player, response_tx.clone());
+        handle_request(
+            Request::Convert {
+                input: PathBuf::from("a.png"),
+                args: vec!["-no-such-option".to_string()],
+            },
+            &response_tx,
         );
+        assert!(response_rx
+            .try_iter()
+            .any(|response| matches!(response, Response::Converted { .. })));
+        assert!(start.elapsed() < Duration::from_secs(4));
     }
 }
@@ -0,0 +1,373 @@
+use std::str::FromStr;
+
+use crate::ffmpeg::{
+    Filter, FilterColorBalance, FilterColorKey, FilterColortemp, FilterCustom, FilterEq,
+    FilterExposure, FilterFormat, FilterLut, FilterNormalize, FilterRgbGain, FilterScale,
+};
+
+#[derive(Debug, PartialEq)]
+pub struct FilterSegment {
+    pub name: String,
+    pub params: Vec<(Option<String>, String)>,
+    pub raw: String,
+}
+
+impl FilterSegment {
+    pub fn parse(raw: &str) -> Self {
+        let raw = raw.trim();
+        let (name, args) = match split_top_level(raw, '=').as_slice() {
+            [name] => (name.clone(), String::new()),
+            [name, ..] => (name.clone(), raw[name.len() + 1..].to_string()),
+            [] => (String::new(), String::new()),
+        };
+        let params = if args.is_empty() {
+            vec![]
+        } else {
+            split_top_level(&args, ':')
+                .into_iter()
+                .map(|param| match split_top_level(&param, '=').as_slice() {
+                    [key, ..] if key.len() < param.len() => {
+                        (Some(key.clone()), unquote(&param[key.len() + 1..]))
+                    }
+                    _ => (None, unquote(&param)),
+                })
+                .collect()
+        };
+
+        Self {
+            name: name.trim().to_string(),
+            params,
+            raw: raw.to_string(),
+        }
+    }
+
+    pub fn has_only_params(&self, names: &[&str]) -> bool {
+        self.params.iter().all(|(key, _)| match key {
+            Some(key) => names.contains(&key.as_str()),
+            None => true,
+        }) && self.params.len() <= names.len()
+    }
+}
+
+/// Looks up a parameter by name or, if it was given without a key, by its position.
+pub fn get_param_value<T: FromStr>(
+    segment: &FilterSegment,
+    name: &str,
+    position: usize,
+) -> Option<T> {
+    segment
+        .params
+        .iter()
+        .find(|(key, _)| key.as_deref() == Some(name))
+        .or_else(|| {
+            segment
+                .params
+                .get(position)
+                .filter(|(key, _)| key.is_none())
+        })
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+/// Like [`get_param_value`] but falls back to `default` if the parameter is missing.
+/// A parameter that is present but cannot be parsed is an error.
+pub(crate) fn get_param_value_or<T: FromStr>(
+    segment: &FilterSegment,
+    name: &str,
+    position: usize,
+    default: T,
+) -> Result<T, ()> {
+    let is_present = segment
+        .params
+        .iter()
+        .enumerate()
+        .any(|(i, (key, _))| key.as_deref() == Some(name) || (key.is_none() && i == position));
+    if is_present {
+        get_param_value(segment, name, position).ok_or(())
+    } else {
+        Ok(default)
+    }
+}
+
+/// Splits at `separator` while respecting single quotes, backslash escapes and brackets.
+pub fn split_top_level(s: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut bracket_depth = 0usize;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                current.push(c);
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            }
+            '\'' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            '[' if !in_quotes => {
+                bracket_depth += 1;
+                current.push(c);
+            }
+            ']' if !in_quotes => {
+                bracket_depth = bracket_depth.saturating_sub(1);
+                current.push(c);
+            }
+            c if c == separator && !in_quotes && bracket_depth == 0 => {
+                parts.push(std::mem::take(&mut current));
+            }
+            c => current.push(c),
+        }
+    }
+    parts.push(current);
+    parts
+}
+
+/// Escapes commas and semicolons outside of single quotes so `filter` stays one filter
+/// when it is joined into a chain. Already escaped characters are kept as they are.
+pub fn escape_separators(filter: &str) -> String {
+    let mut escaped = String::with_capacity(filter.len());
+    let mut in_quotes = false;
+    let mut chars = filter.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                escaped.push(c);
+                if let Some(c) = chars.next() {
+                    escaped.push(c);
+                }
+            }
+            '\'' => {
+                in_quotes = !in_quotes;
+                escaped.push(c);
+            }
+            ',' | ';' if !in_quotes => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unquote(value: &str) -> String {
+    let mut unquoted = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    unquoted.push(escaped);
+                }
+            }
+            '\'' => {}
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+/// The filters of a chain, empty ones are skipped.
+fn parse_segments(filter_chain: &str) -> impl Iterator<Item = FilterSegment> {
+    split_top_level(filter_chain, ',')
+        .into_iter()
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| FilterSegment::parse(&s))
+}
+
+/// Names of the filters in a filtergraph of one or more chains, without link labels.
+pub fn filter_names(filtergraph: &str) -> Vec<String> {
+    split_top_level(filtergraph, ';')
+        .iter()
+        .flat_map(|chain| parse_segments(chain))
+        .map(|segment| {
+            let mut name = segment.name.as_str();
+            while let Some(rest) = name.strip_prefix('[') {
+                name = rest.split_once(']').map_or("", |(_, rest)| rest);
+            }
+            name.split('[')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        })
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+pub fn parse_filters(filter_string: &str) -> Vec<Box<dyn Filter>> {
+    parse_segments(filter_string)
+        .map(|segment| {
+            let filter: Box<dyn Filter> =
+                if let Ok(filter) = TryInto::<FilterScale>::try_into(&segment) {
+                    Box::new(filter)
+                } else if let Ok(filter) = TryInto::<FilterExposure>::try_into(&segment) {
+                    Box::new(filter)
+                } else if let Ok(filter) = TryInto::<FilterColortemp>::try_into(&segment) {
+                    Box::new(filter)
+                } else if let Ok(filter) = TryInto::<FilterLut>::try_into(&segment) {
+                    Box::new(filter)
+                } else if let Ok(filter) = TryInto::<FilterEq>::try_into(&segment) {
+                    Box::new(filter)
+                } else if let Ok(filter) = TryInto::<FilterColorBalance>::try_into(&segment) {
+                    Box::new(filter)
+                } else if let Ok(filter) = TryInto::<FilterColorKey>::try_into(&segment) {
+                    Box::new(filter)
+                } else if let Ok(filter) = TryInto::<FilterNormalize>::try_into(&segment) {
+                    Box::new(filter)
+                } else if let Ok(filter) = TryInto::<FilterRgbGain>::try_into(&segment) {
+                    Box::new(filter)
+                } else if let Ok(filter) = TryInto::<FilterFormat>::try_into(&segment) {
+                    Box::new(filter)
+                } else {
+                    Box::new(FilterCustom {
+                        is_active: true,
+                        expression: segment.raw,
+                        single_filter: false,
+                    })
+                };
+            filter
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split() {
+        assert_eq!(
+            split_top_level(
+                r"eq=contrast=1.1,drawtext=text='a, b':x=1,select=gt(scene\,0.4)",
+                ','
+            ),
+            vec![
+                "eq=contrast=1.1",
+                "drawtext=text='a, b':x=1",
+                r"select=gt(scene\,0.4)"
+            ]
+        );
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            filter_names("split[a][b];[a]lutrgb=r=0[x];[b][x]overlay[out],format=yuv420p"),
+            ["split", "lutrgb", "overlay", "format"]
+        );
+        assert_eq!(
+            filter_names(r"select=gt(scene\,0.4),,null"),
+            ["select", "null"]
+        );
+        assert!(filter_names("").is_empty());
+    }
+
+    #[test]
+    fn segment() {
+        let segment = FilterSegment::parse("scale=1280:h=720");
+        assert_eq!(segment.name, "scale");
+        assert_eq!(get_param_value(&segment, "w", 0), Some(1280));
+        assert_eq!(get_param_value(&segment, "h", 1), Some(720));
+        assert_eq!(get_param_value::<u64>(&segment, "x", 2), None);
+    }
+
+    #[test]
+    fn parse() {
+        let filters = parse_filters(
+            "scale=640:360,eq=contrast=1.2:gamma=0.9,lut3d=file='/luts/a b.cube',curves=preset=vintage",
+        );
+        let names: Vec<_> = filters.iter().map(|f| f.name()).collect();
+        assert_eq!(names, vec!["Scale", "Eq", "LUT", "Custom filter(s)"]);
+        assert!(filters.iter().all(|f| f.is_active()));
+        assert_eq!(filters[0].to_filter_string(), "scale=640:360");
+        assert_eq!(
+            filters[1].to_filter_string(),
+            "eq=contrast=1.2:brightness=0:saturation=1:gamma=0.9:gamma_r=1:gamma_g=1:gamma_b=1"
+        );
+        assert_eq!(
+            filters[2].to_filter_string(),
+            "lut3d=file=/luts/a b.cube:interp=tetrahedral"
+        );
+        assert_eq!(filters[3].to_filter_string(), "curves=preset=vintage");
+    }
+
+    #[test]
+    fn normalize() {
+        let filters = parse_filters("normalize=blackpt=0x101010:smoothing=10");
+        assert_eq!(filters[0].name(), "Normalize");
+        assert_eq!(
+            filters[0].to_filter_string(),
+            "normalize=blackpt=0x101010:whitept=0xFFFFFF:smoothing=10:independence=1"
+        );
+    }
+
+    #[test]
+    fn escape() {
+        let escaped = escape_separators(r"select=gt(scene,0.4)+eq(n\,1);drawtext=text='a, b'");
+        assert_eq!(
+            escaped,
+            r"select=gt(scene\,0.4)+eq(n\,1)\;drawtext=text='a, b'"
+        );
+        assert_eq!(split_top_level(&escaped, ',').len(), 1);
+    }
+
+    #[test]
+    fn unsupported_values_become_custom() {
+        let filters = parse_filters(
+            "colortemperature=5600:pl=1,colortemperature=5600:pl=0.5,lut3d=a.cube:all,lut3d=file=a.cube:clut=first",
+        );
+        let names: Vec<_> = filters.iter().map(|f| f.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "Color temperature",
+                "Custom filter(s)",
+                "LUT",
+                "Custom filter(s)"
+            ]
+        );
+        assert_eq!(
+            filters[1].to_filter_string(),
+            "colortemperature=5600:pl=0.5"
+        );
+    }
+
+    #[test]
+    fn unknown_params_become_custom() {
+        let filters = parse_filters("eq=contrast=1.2:eval=frame");
+        assert_eq!(filters[0].name(), "Custom filter(s)");
+        assert_eq!(filters[0].to_filter_string(), "eq=contrast=1.2:eval=frame");
+    }
+
+    #[test]
+    fn rgb_gain() {
+        let filters =
+            parse_filters("colorchannelmixer=rr=1.1:bb=0.85,colorchannelmixer=rr=1:rg=0.2");
+        assert_eq!(filters[0].name(), "RGB gain");
+        assert_eq!(
+            filters[0].to_filter_string(),
+            "colorchannelmixer=rr=1.1:gg=1:bb=0.85"
+        );
+        assert_eq!(filters[1].name(), "Custom filter(s)");
+        assert_eq!(
+            FilterRgbGain::default().to_filter_string(),
+            "colorchannelmixer=rr=1:gg=1:bb=1"
+        );
+    }
+
+    #[test]
+    fn pixel_format() {
+        let filters =
+            parse_filters("format=gbrp16le,format=pix_fmts=yuv444p,format=yuv420p|yuv444p");
+        assert_eq!(filters[0].name(), "Pixel format");
+        assert_eq!(filters[0].to_filter_string(), "format=gbrp16le");
+        assert_eq!(filters[1].name(), "Pixel format");
+        assert_eq!(filters[1].to_filter_string(), "format=yuv444p");
+        assert_eq!(filters[2].name(), "Custom filter(s)");
+    }
+}
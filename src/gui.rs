@@ -1,34 +1,168 @@
 use eframe::App;
 use egui::{
-    CollapsingHeader, Color32, ColorImage, ComboBox, RichText, ScrollArea, SidePanel, Slider,
-    SliderClamping, TextEdit, TextureHandle, TopBottomPanel, Vec2,
+    load::SizedTexture, Align2, Button, CollapsingHeader, Color32, ColorImage, ComboBox, DragValue,
+    Key, PointerButton, Pos2, Rect, RichText, ScrollArea, Sense, SidePanel, Slider, SliderClamping,
+    TextEdit, TextureHandle, TopBottomPanel, Vec2,
 };
-use egui_plot::{MarkerShape, Plot, PlotPoints, Points};
+use egui_file::FileDialog;
+use egui_plot::{HLine, Line, MarkerShape, Plot, PlotPoint, PlotPoints, Points, Text};
 use flume::{Receiver, Sender};
-use image::{Pixel, Rgba, RgbaImage};
+use image::{DynamicImage, ImageBuffer, Pixel, Rgba, Rgba32FImage, RgbaImage};
+use regex::Regex;
 use std::{
-    collections::{HashMap, HashSet},
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::{Display, Write},
-    path::PathBuf,
-    time::Duration,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant},
 };
 use temp_dir::TempDir;
 
-use crate::ffmpeg::{
-    CliOption, Encoder, FilterColorBalance, FilterColortemp, FilterCustom, FilterEq,
-    FilterExposure, FilterLut, FilterOption, FilterScale, InputFile, NumberOfFramesOption,
-    OutputFile, Request, Response, SkipOption,
+use crate::{
+    cdl::read_cdl,
+    ffmpeg::is_image_file,
+    ffmpeg::{
+        command_words, filter_presets_id, preset_changes_id, range_locks_id,
+        read_filter_definitions, CliOption, ColorMetadata, Encoder, FfmpegCapabilities,
+        FfmpegLogLevel, FilterColorBalance, FilterColorKey, FilterColorspace, FilterColortemp,
+        FilterCustom, FilterDefinition, FilterDeshake, FilterEq, FilterExposure, FilterLimiter,
+        FilterLut, FilterMetadata, FilterNormalize, FilterOption, FilterPresets, FilterScale,
+        FilterSetDar, FilterSetSar, FilterTemplated, FilterToneCurve, FrameFormat, FrameOutput,
+        InputFile, JobId, MaskShape, NumberOfFramesOption, OptionPlacement, OutputFile,
+        PresetChange, RangeLock, Request, Response, Rgba16Image, SkipOption, Slate,
+        FILTER_REGISTRY, INPUT_OPTION_REGISTRY,
+    },
+    filtergraph::parse_filters,
+    logging::LOG_BUFFER,
+    mlt::filtergraph_property,
 };
 
+pub const APP_NAME: &str = "Colorust";
+const AUTO_PREVIEW_DEBOUNCE: Duration = Duration::from_millis(300);
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+const FFMPEG_WAVEFORM_FILTER: &str =
+    "waveform=display=parade:components=7:graticule=green:flags=numbers+dots";
+const THUMBNAIL_WIDTH: u32 = 64;
+const FAST_SCOPES_WIDTH: u32 = 256;
+const THUMBNAIL_CACHE_CAPACITY: usize = 256;
+
 pub struct ColorustApp {
     state: ColorustState,
+    recovered_state: Option<ColorustState>,
+    last_autosave: Instant,
     image_texture: Option<TextureHandle>,
     request_tx: Sender<Request>,
     response_rx: Receiver<Response>,
     temp_dir: TempDir,
     waiting_for_image: bool,
     waveform: Option<Waveform>,
+    waveform_texture: Option<TextureHandle>,
+    saturation_scope: Option<SaturationScope>,
     error: Option<String>,
+    last_change: Option<Instant>,
+    filter_import: String,
+    look_name: String,
+    prefetch_cache: HashMap<PathBuf, RgbaImage>,
+    prefetch_pending: HashSet<PathBuf>,
+    displayed_input: Option<PathBuf>,
+    next_job_id: JobId,
+    latest_preview_id: JobId,
+    latest_waveform_id: JobId,
+    waiting_for_waveform: bool,
+    live_base: Option<(RgbaImage, ToneApproximation)>,
+    requested_tone: ToneApproximation,
+    scopes_are_approximate: bool,
+    displayed_frame: Option<RgbaImage>,
+    /// The displayed frame as [`precise_levels`], extracted with precise scopes.
+    precise_frame: Option<Rgba32FImage>,
+    save_frame_dialog: Option<FileDialog>,
+    script_dialog: Option<FileDialog>,
+    cdl_dialog: Option<FileDialog>,
+    /// User defined filters, loaded from [`filter_definitions_path`] at startup.
+    filter_definitions: Vec<FilterDefinition>,
+    /// Scope window whose image is saved, until the path is picked.
+    scope_image_dialog: Option<(&'static str, FileDialog)>,
+    /// Screen area of the scope windows in the last frame.
+    scope_window_rects: HashMap<&'static str, Rect>,
+    filter_palette: Option<FilterPalette>,
+    look_diff: (String, String),
+    preview_view: PreviewView,
+    hovered_pixel: Option<[u32; 2]>,
+    drawing_mask: bool,
+    mask_drag_start: Option<Vec2>,
+    thumbnails: ThumbnailCache,
+    chain_warnings: Vec<String>,
+    ffmpeg_capabilities: Arc<FfmpegCapabilities>,
+    /// The displayed frame has transparent pixels and is drawn over a checkerboard.
+    image_has_alpha: bool,
+    /// Maximum texture side the displayed frame was downscaled to, if it exceeded it.
+    preview_downscaled: Option<usize>,
+    /// Ungraded frame for comparisons and the arguments it was extracted with, without output.
+    original_frame: Option<RgbaImage>,
+    original_args: Vec<String>,
+    latest_original_id: JobId,
+    /// Seek of the latest requested original, the latest requested preview and the displayed
+    /// frame. Frames are only compared if they were extracted at the same position.
+    original_seek: Option<FrameSeek>,
+    requested_seek: Option<FrameSeek>,
+    displayed_seek: Option<FrameSeek>,
+    /// Duration of the input in seconds, if ffprobe reported it.
+    input_duration: Option<f64>,
+    /// Complete ffprobe output for the input.
+    probe_json: Option<String>,
+    /// Color properties of the input as reported by ffprobe.
+    probed_color: ColorMetadata,
+    conversion: Option<Conversion>,
+    overwrite_output: bool,
+    scope_throttle: ScopeThrottle,
+}
+
+/// Limits how often the scopes are computed. Frames offered in between wait in `pending`,
+/// only the latest one is kept.
+#[derive(Default)]
+struct ScopeThrottle {
+    last_update: Option<Instant>,
+    pending: Option<DynamicImage>,
+}
+
+impl ScopeThrottle {
+    /// Time left until the scopes may be computed again.
+    fn remaining(&self, interval: Duration, now: Instant) -> Duration {
+        self.last_update
+            .map(|last| interval.saturating_sub(now.duration_since(last)))
+            .unwrap_or_default()
+    }
+}
+
+/// A conversion started with "Convert now".
+struct Conversion {
+    input: PathBuf,
+    output: PathBuf,
+    duration: Option<f64>,
+    seconds: f64,
+    result: Option<Result<(), String>>,
+}
+
+/// Filter thumbnails by the ffmpeg arguments that produced them, so any change of the filter,
+/// input or frame position misses the cache.
+#[derive(Default)]
+struct ThumbnailCache {
+    textures: HashMap<String, TextureHandle>,
+    pending: HashSet<String>,
+}
+
+impl ThumbnailCache {
+    fn insert(&mut self, key: String, texture: TextureHandle) {
+        if self.textures.len() >= THUMBNAIL_CACHE_CAPACITY {
+            self.textures.clear();
+        }
+        self.textures.insert(key, texture);
+    }
+
+    fn get(&self, key: &str) -> Option<SizedTexture> {
+        self.textures.get(key).map(SizedTexture::from_handle)
+    }
 }
 
 #[derive(Debug, Copy, Clone, serde::Deserialize, serde::Serialize, PartialEq, Eq)]
@@ -44,12 +178,114 @@ impl Display for PreviewManipulationType {
     }
 }
 
+/// How the preview manipulations compute the luma of a pixel, in percent.
+#[derive(Debug, Copy, Clone, Default, serde::Deserialize, serde::Serialize, PartialEq, Eq)]
+pub enum LumaMode {
+    /// Rec. 709 weights applied to the sRGB encoded values, as video luma is computed.
+    #[default]
+    Srgb,
+    /// Relative luminance, i.e. the weights applied to the linearized values, encoded with the
+    /// sRGB transfer function again so the percentages stay on the familiar scale.
+    LinearEncoded,
+    /// Relative luminance itself, so 18% is middle gray.
+    Linear,
+}
+
+impl LumaMode {
+    fn luma(self, pixel: &Rgba<u8>) -> f64 {
+        let luma = match self {
+            Self::Srgb => pixel.to_luma()[0] as f32 / 255.,
+            Self::LinearEncoded | Self::Linear => {
+                let [r, g, b, _] = pixel.0.map(|v| srgb_eotf(v as f32 / 255.));
+                let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+                if self == Self::Linear {
+                    luminance
+                } else {
+                    srgb_oetf(luminance)
+                }
+            }
+        };
+        luma as f64 * 100.
+    }
+}
+
+impl Display for LumaMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Srgb => write!(f, "sRGB luma"),
+            Self::LinearEncoded => write!(f, "Linear light, sRGB encoded"),
+            Self::Linear => write!(f, "Linear light"),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, serde::Deserialize, serde::Serialize)]
 pub struct PreviewManipulation {
     is_active: bool,
     manip_type: PreviewManipulationType,
     zebra_value: u8,
     zebra_range: u8,
+    #[serde(default)]
+    luma_mode: LumaMode,
+}
+
+impl Default for PreviewManipulation {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            manip_type: PreviewManipulationType::Zebra,
+            zebra_value: 52,
+            zebra_range: 2,
+            luma_mode: LumaMode::Srgb,
+        }
+    }
+}
+
+/// Older states have a single manipulation instead of a list.
+fn deserialize_preview_manipulations<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<PreviewManipulation>, D::Error> {
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(PreviewManipulation),
+        Many(Vec<PreviewManipulation>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(manipulation) => vec![manipulation],
+        OneOrMany::Many(manipulations) => manipulations,
+    })
+}
+
+fn draw_preview_manipulations(
+    ctx: &egui::Context,
+    ui: &mut egui::Ui,
+    manipulations: &mut Vec<PreviewManipulation>,
+) {
+    let mut remove = None;
+    for (i, manipulation) in manipulations.iter_mut().enumerate() {
+        let marker = if manipulation.is_active { "●" } else { "○" };
+        CollapsingHeader::new(format!("{marker} {}", manipulation.manip_type))
+            .id_salt(("preview_manipulation", i))
+            .show(ui, |ui| {
+                manipulation.draw(ctx, ui);
+                if ui.small_button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+    }
+    if let Some(i) = remove {
+        manipulations.remove(i);
+    }
+    if ui.small_button("Add").clicked() {
+        manipulations.push(PreviewManipulation {
+            is_active: true,
+            ..Default::default()
+        });
+    }
 }
 
 impl PreviewManipulation {
@@ -58,13 +294,13 @@ impl PreviewManipulation {
             log::info!("{:?}", self);
             match self.manip_type {
                 PreviewManipulationType::Zebra => {
-                    Self::apply_zebra(img, self.zebra_value, self.zebra_range)
+                    Self::apply_zebra(img, self.zebra_value, self.zebra_range, self.luma_mode)
                 }
             }
         };
     }
 
-    fn apply_zebra(img: &mut RgbaImage, value: u8, range: u8) {
+    fn apply_zebra(img: &mut RgbaImage, value: u8, range: u8, luma_mode: LumaMode) {
         // let pattern = RgbaImage::from_pixel(img.width(), img.height(), Rgba([255, 255, 255, 255]));
         let pattern = RgbaImage::from_fn(img.width(), img.height(), |x, y| {
             let is_white = (x + y) % 10 < 5;
@@ -76,7 +312,7 @@ impl PreviewManipulation {
         });
 
         for (x, y, pixel) in img.enumerate_pixels_mut() {
-            let luma = pixel.to_luma()[0] as f64 * 100. / 255.;
+            let luma = luma_mode.luma(pixel);
             if (value.saturating_sub(range) as f64..=value.saturating_add(range) as f64)
                 .contains(&luma)
             {
@@ -108,11 +344,311 @@ impl PreviewManipulation {
                         .clamping(SliderClamping::Always)
                         .text("Range"),
                 );
+                ComboBox::from_label("Luma")
+                    .selected_text(self.luma_mode.to_string())
+                    .show_ui(ui, |ui| {
+                        for mode in [LumaMode::Srgb, LumaMode::LinearEncoded, LumaMode::Linear] {
+                            ui.selectable_value(&mut self.luma_mode, mode, mode.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "sRGB luma weights the encoded values like video luma does. Linear \
+                         light weights the linearized values, which is the actual luminance, \
+                         optionally encoded with the sRGB curve again.",
+                    );
             }
         };
     }
 }
 
+/// Transform applied to the preview right before it is uploaded as a texture.
+///
+/// The extracted frame is treated as display-referred 8-bit RGB as ffmpeg wrote it. Scopes
+/// always use the untransformed frame. egui itself does no color management, so without a
+/// transform the values are shown as sRGB on whatever the monitor's native gamut is.
+#[derive(Debug, Copy, Clone, Default, serde::Deserialize, serde::Serialize, PartialEq, Eq)]
+pub enum DisplayTransform {
+    #[default]
+    None,
+    /// Decodes with the BT.1886 (gamma 2.4) EOTF and re-encodes with the sRGB transfer function.
+    Rec709ToSrgb,
+    /// Keeps the sRGB transfer function but maps Rec.709/sRGB primaries into Display P3 so
+    /// colors are not oversaturated on wide-gamut (P3) monitors.
+    SrgbToDisplayP3,
+}
+
+impl Display for DisplayTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "None (sRGB pass-through)"),
+            Self::Rec709ToSrgb => write!(f, "Rec.709 to sRGB"),
+            Self::SrgbToDisplayP3 => write!(f, "sRGB to Display P3"),
+        }
+    }
+}
+
+/// Kind of script the conversion commands are exported as.
+#[derive(Debug, Copy, Clone, serde::Deserialize, serde::Serialize, PartialEq, Eq)]
+pub enum ScriptKind {
+    Shell,
+    Batch,
+}
+
+impl Default for ScriptKind {
+    fn default() -> Self {
+        if cfg!(windows) {
+            Self::Batch
+        } else {
+            Self::Shell
+        }
+    }
+}
+
+impl Display for ScriptKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Shell => write!(f, "Shell script (.sh)"),
+            Self::Batch => write!(f, "Batch file (.bat)"),
+        }
+    }
+}
+
+impl ScriptKind {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Shell => "sh",
+            Self::Batch => "bat",
+        }
+    }
+
+    /// A script that runs the non-empty lines of `commands` in order and stops at the first
+    /// one that fails.
+    fn script(self, commands: &str) -> String {
+        let commands = commands.lines().map(str::trim).filter(|c| !c.is_empty());
+        match self {
+            Self::Shell => {
+                let mut script = "#!/bin/sh\nset -e\n\n".to_string();
+                for command in commands {
+                    writeln!(&mut script, "{command}").unwrap();
+                }
+                script
+            }
+            Self::Batch => {
+                let mut script = "@echo off\r\n\r\n".to_string();
+                for command in commands {
+                    // A batch file expands `%` even in quotes, `%%` is a literal one
+                    let command = command.replace('%', "%%");
+                    write!(&mut script, "{command}\r\nif errorlevel 1 exit /b 1\r\n").unwrap();
+                }
+                script
+            }
+        }
+    }
+}
+
+/// Quotes `arg` for the shell that runs `kind` if it contains anything but safe characters.
+fn shell_quote(arg: &str, kind: ScriptKind) -> Cow<'_, str> {
+    match kind {
+        ScriptKind::Shell => quote_posix(arg),
+        ScriptKind::Batch => quote_cmd(arg),
+    }
+}
+
+fn needs_quotes(arg: &str, safe: &str) -> bool {
+    arg.is_empty()
+        || !arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || safe.contains(c))
+}
+
+fn quote_posix(arg: &str) -> Cow<'_, str> {
+    if needs_quotes(arg, "-_./:=,+@%") {
+        format!("'{}'", arg.replace('\'', r"'\''")).into()
+    } else {
+        arg.into()
+    }
+}
+
+fn quote_cmd(arg: &str) -> Cow<'_, str> {
+    // `%` starts a variable for cmd
+    if needs_quotes(arg, "-_./:=,+@") {
+        format!("\"{}\"", arg.replace('"', "\"\"")).into()
+    } else {
+        arg.into()
+    }
+}
+
+/// Writes `script` to `path` and makes it executable for everyone who may read it.
+fn write_script(path: &Path, script: &str) -> std::io::Result<()> {
+    std::fs::write(path, script)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut permissions = std::fs::metadata(path)?.permissions();
+        permissions.set_mode(permissions.mode() | (permissions.mode() & 0o444) >> 2);
+        std::fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
+}
+
+/// ffmpeg scopes that are drawn onto the picture when playing back with ffplay.
+#[derive(Debug, Copy, Clone, Default, serde::Deserialize, serde::Serialize, PartialEq, Eq)]
+pub enum PlaybackScopes {
+    #[default]
+    None,
+    Waveform,
+    Vectorscope,
+    Both,
+}
+
+impl Display for PlaybackScopes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "No scopes"),
+            Self::Waveform => write!(f, "Waveform"),
+            Self::Vectorscope => write!(f, "Vectorscope"),
+            Self::Both => write!(f, "Waveform + vectorscope"),
+        }
+    }
+}
+
+impl PlaybackScopes {
+    /// Filtergraph that is appended to the user's filter chain. The waveform is placed at the
+    /// bottom and the vectorscope in the top right corner of the picture.
+    fn overlay_filter(self) -> Option<String> {
+        const WAVEFORM: &str = "waveform=components=7:graticule=green:flags=numbers";
+        const VECTORSCOPE: &str = "vectorscope=mode=color3:graticule=green";
+        match self {
+            Self::None => None,
+            Self::Waveform => Some(format!(
+                "split[main][scope];[scope]{WAVEFORM}[wf];[main][wf]overlay=0:H-h"
+            )),
+            Self::Vectorscope => Some(format!(
+                "split[main][scope];[scope]{VECTORSCOPE}[vs];[main][vs]overlay=W-w:0"
+            )),
+            Self::Both => Some(format!(
+                "split=3[main][wfin][vsin];[wfin]{WAVEFORM}[wf];[vsin]{VECTORSCOPE}[vs];\
+                 [main][wf]overlay=0:H-h[tmp];[tmp][vs]overlay=W-w:0"
+            )),
+        }
+    }
+}
+
+const SRGB_TO_DISPLAY_P3: [[f32; 3]; 3] = [
+    [0.8225, 0.1774, 0.0000],
+    [0.0332, 0.9669, 0.0000],
+    [0.0171, 0.0724, 0.9108],
+];
+
+fn srgb_eotf(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn srgb_oetf(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1. / 2.4) - 0.055
+    }
+}
+
+impl DisplayTransform {
+    pub fn apply(&self, img: &mut RgbaImage) {
+        let decode: Vec<f32> = (0..=255u8)
+            .map(|v| {
+                let v = v as f32 / 255.;
+                match self {
+                    Self::None => v,
+                    Self::Rec709ToSrgb => v.powf(2.4),
+                    Self::SrgbToDisplayP3 => srgb_eotf(v),
+                }
+            })
+            .collect();
+        let encode = |v: f32| (srgb_oetf(v.clamp(0., 1.)) * 255.).round() as u8;
+
+        match self {
+            Self::None => {}
+            Self::Rec709ToSrgb => {
+                let lut: Vec<u8> = decode.iter().map(|v| encode(*v)).collect();
+                for pixel in img.pixels_mut() {
+                    for c in pixel.0.iter_mut().take(3) {
+                        *c = lut[*c as usize];
+                    }
+                }
+            }
+            Self::SrgbToDisplayP3 => {
+                for pixel in img.pixels_mut() {
+                    let rgb = [0, 1, 2].map(|c| decode[pixel.0[c] as usize]);
+                    for (c, row) in SRGB_TO_DISPLAY_P3.iter().enumerate() {
+                        pixel.0[c] = encode(row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) {
+        ComboBox::from_label("Display transform")
+            .selected_text(self.to_string())
+            .show_ui(ui, |ui| {
+                for transform in [Self::None, Self::Rec709ToSrgb, Self::SrgbToDisplayP3] {
+                    ui.selectable_value(self, transform, transform.to_string());
+                }
+            })
+            .response
+            .on_hover_text(
+                "Applied to the preview only, after scopes are computed and before display. \
+                 Takes effect with the next preview.",
+            );
+    }
+}
+
+pub enum ArgPurpose<'a> {
+    /// Extract a single frame, `extra_filter` is appended to the end of the filter chain.
+    Preview {
+        output: &'a FrameOutput,
+        fast_preview: &'a FastPreview,
+        extra_filter: Option<&'a str>,
+        loglevel: FfmpegLogLevel,
+    },
+    /// Arguments for ffplay, optionally with ffmpeg's scopes overlaid on the picture.
+    Play { scopes: PlaybackScopes },
+    /// Full conversion as with the default conversion template.
+    Convert,
+    /// Grades a still image. The encoder follows the output file's extension.
+    Still,
+    /// A short clip from the current position for an external player, graded as the
+    /// conversion would but with [`PROXY_ENCODER`].
+    Proxy { output: &'a Path, seconds: f64 },
+}
+
+const PROXY_ENCODER: [&str; 10] = [
+    "-c:v", "libx264", "-preset", "veryfast", "-crf", "18", "-pix_fmt", "yuv420p", "-c:a", "aac",
+];
+
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+struct ExternalPlayer {
+    /// Command line the proxy path is appended to, empty for the system's default player.
+    command: String,
+    seconds: f64,
+}
+
+impl Default for ExternalPlayer {
+    fn default() -> Self {
+        Self {
+            command: String::new(),
+            seconds: 30.,
+        }
+    }
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct FileState {
     input_file: InputFile,
@@ -121,420 +657,3654 @@ pub struct FileState {
     skip_seconds: SkipOption,
     cli_options: Vec<Box<dyn CliOption>>,
     filter_options: FilterOption,
+    #[serde(default)]
+    filter_metadata: FilterMetadata,
+    #[serde(default)]
+    slate: Slate,
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
 pub struct ColorustState {
     active_file_state: FileState,
+    /// The other inputs of the project. In project order they are placed around the active
+    /// one, which is at `project_active`.
+    project_files: Vec<FileState>,
+    project_active: usize,
     waveform_multiplier: f64,
     conversion_commands: String,
+    script_kind: ScriptKind,
     file_history: HashMap<PathBuf, String>,
+    /// Least recently used first.
+    file_history_order: Vec<PathBuf>,
+    file_history_limit: usize,
     conversion_template: String,
-    preview_manipulation: PreviewManipulation,
+    /// Conversion templates that replace `conversion_template` for single inputs.
+    input_templates: HashMap<PathBuf, String>,
+    /// Applied one after another to the displayed frame.
+    #[serde(
+        alias = "preview_manipulation",
+        deserialize_with = "deserialize_preview_manipulations"
+    )]
+    preview_manipulations: Vec<PreviewManipulation>,
+    auto_preview: bool,
+    log_level: log::Level,
+    preview_via_pipe: bool,
+    lut_comparison: LutComparison,
+    fast_preview: FastPreview,
+    default_filters: FilterOption,
+    display_transform: DisplayTransform,
+    waveform_zoom: WaveformZoom,
+    waveform_graticule: Graticule,
+    /// Compute the scopes from a downscaled frame.
+    fast_scopes: bool,
+    /// Minimum time between two scope updates in milliseconds, 0 for no limit.
+    scope_interval_ms: u64,
+    /// Extract previews with 16 bits per channel and compute the scopes from those.
+    precise_scopes: bool,
+    ffmpeg_waveform: bool,
+    save_frame: SaveFrameOptions,
+    playback_scopes: PlaybackScopes,
+    external_player: ExternalPlayer,
+    ffmpeg_loglevel: FfmpegLogLevel,
+    batch_grade: BatchGrade,
+    filter_thumbnails: bool,
+    /// Start directories for the input and output file dialogs, empty for none.
+    default_input_dir: PathBuf,
+    default_output_dir: PathBuf,
+    /// Lets eframe persist egui's memory, which holds the window and panel layout.
+    remember_layout: bool,
+    /// Narrower slider ranges, applied while `lock_ranges` is set.
+    range_locks: Vec<RangeLock>,
+    lock_ranges: bool,
+    preview_zoom: PreviewZoom,
+    compare: Compare,
+    /// Keeping LUTs behind the grading filters.
+    lut_order: LutOrder,
+    filter_presets: FilterPresets,
+    /// Named file states per input file.
+    looks: HashMap<PathBuf, BTreeMap<String, String>>,
 }
 
-impl ColorustState {}
+fn recovery_path() -> Option<PathBuf> {
+    let dir = eframe::storage_dir(APP_NAME)?;
+    std::fs::create_dir_all(&dir).ok()?;
+    Some(dir.join("recovery.json"))
+}
 
-impl Default for ColorustState {
-    fn default() -> Self {
-        ColorustState {
-            active_file_state: FileState {
-                input_file: Default::default(),
-                output_file: Default::default(),
-                encoder: Default::default(),
-                cli_options: vec![],
-                filter_options: FilterOption {
-                    filters: vec![
-                        Box::new(FilterScale {
-                            is_active: true,
-                            width: 1280,
-                            height: 720,
-                        }),
-                        Box::<FilterExposure>::default(),
-                        Box::<FilterColortemp>::default(),
-                        Box::<FilterLut>::default(),
-                        Box::<FilterEq>::default(),
-                        Box::<FilterColorBalance>::default(),
-                        Box::<FilterCustom>::default(),
-                    ],
-                },
-                skip_seconds: Default::default(),
-            },
-            waveform_multiplier: 25.,
-            conversion_commands: Default::default(),
-            file_history: Default::default(),
-            conversion_template: "ffmpeg ##input## ##cli## ##filter## ##encoder## ##output##"
-                .to_string(),
-            preview_manipulation: PreviewManipulation {
-                is_active: false,
-                manip_type: PreviewManipulationType::Zebra,
-                zebra_value: 52,
-                zebra_range: 2,
-            },
+fn filter_definitions_path() -> Option<PathBuf> {
+    Some(eframe::storage_dir(APP_NAME)?.join("filters.json"))
+}
+
+fn load_filter_definitions() -> Vec<FilterDefinition> {
+    let Some(path) = filter_definitions_path().filter(|path| path.is_file()) else {
+        return vec![];
+    };
+    match read_filter_definitions(&path) {
+        Ok(definitions) => {
+            log::info!(
+                "Loaded {} filter definitions from {path:?}",
+                definitions.len()
+            );
+            definitions
+        }
+        Err(e) => {
+            log::error!("Could not load filter definitions from {path:?}: {e}");
+            vec![]
         }
     }
 }
 
-impl ColorustApp {
-    pub fn new(
-        cc: &eframe::CreationContext<'_>,
-        request_tx: Sender<Request>,
-        response_rx: Receiver<Response>,
-    ) -> Self {
-        let state = if let Some(storage) = cc.storage {
-            eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default()
-        } else {
-            Default::default()
-        };
-        Self {
-            state,
-            image_texture: None,
-            request_tx,
-            response_rx,
-            temp_dir: TempDir::new().unwrap(),
-            waiting_for_image: false,
-            waveform: None,
-            error: None,
+/// Returns the autosaved state if it is newer than what eframe persisted on the last exit.
+fn read_recovery_file() -> Option<ColorustState> {
+    let path = recovery_path()?;
+    let recovery_modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+    let storage_modified = eframe::storage_dir(APP_NAME)
+        .and_then(|dir| std::fs::metadata(dir.join("app.ron")).ok())
+        .and_then(|m| m.modified().ok());
+    if storage_modified.is_some_and(|storage_modified| storage_modified >= recovery_modified) {
+        return None;
+    }
+    match serde_json::from_str(&std::fs::read_to_string(&path).ok()?) {
+        Ok(state) => Some(state),
+        Err(e) => {
+            log::warn!("Could not parse recovery file {path:?}: {e}");
+            None
         }
     }
+}
 
-    fn draw_side_panel(&mut self, ctx: &egui::Context) {
-        SidePanel::left("Parameters").show(ctx, |ui| {
-            CollapsingHeader::new(self.state.active_file_state.input_file.name()).show(ui, |ui| {
-                self.state.active_file_state.input_file.draw(ctx, ui);
-            });
-            CollapsingHeader::new(self.state.active_file_state.output_file.name()).show(ui, |ui| {
-                self.state.active_file_state.output_file.draw(ctx, ui);
-            });
-            CollapsingHeader::new(self.state.active_file_state.encoder.name()).show(ui, |ui| {
-                self.state.active_file_state.encoder.draw(ctx, ui);
-            });
-            CollapsingHeader::new(self.state.active_file_state.skip_seconds.name()).show(
-                ui,
-                |ui| {
-                    self.state.active_file_state.skip_seconds.draw(ctx, ui);
-                },
-            );
-            ui.separator();
-            for opt in self.state.active_file_state.cli_options.iter_mut() {
-                CollapsingHeader::new(opt.name()).show(ui, |ui| {
-                    opt.draw(ctx, ui);
-                });
-            }
-            CollapsingHeader::new("Filters").show(ui, |ui| {
-                self.state.active_file_state.filter_options.draw(ctx, ui);
-            });
-            ui.separator();
-            CollapsingHeader::new("Preview Manipulation").show(ui, |ui| {
-                self.state.preview_manipulation.draw(ctx, ui);
-            });
-            ui.horizontal(|ui| {
-                if ui.button("Create preview").clicked() {
-                    let preview_file = self.temp_dir.child("preview.bmp");
-                    let mut args = vec![
-                        "-y".to_string(),
-                        "-loglevel".to_string(),
-                        "warning".to_string(),
-                    ];
-                    args.append(&mut self.state.active_file_state.skip_seconds.to_option_args());
-                    args.append(&mut self.state.active_file_state.input_file.to_option_args());
-                    args.append(&mut NumberOfFramesOption { frames: 1 }.to_option_args());
-                    args.append(
-                        &mut self
-                            .state
-                            .active_file_state
-                            .cli_options
-                            .iter()
-                            .filter_map(|o| {
-                                if o.is_active() {
-                                    Some(o.to_option_args())
-                                } else {
-                                    None
-                                }
-                            })
-                            .flatten()
-                            .collect(),
-                    );
-                    args.append(&mut self.state.active_file_state.filter_options.to_option_args());
-                    args.append(
-                        &mut OutputFile {
-                            path: preview_file.clone(),
-                            dialog: None,
-                        }
-                        .to_option_args(),
-                    );
+const PLACEHOLDERS: [&str; 5] = [
+    "##input##",
+    "##cli##",
+    "##filter##",
+    "##encoder##",
+    "##output##",
+];
+const REQUIRED_PLACEHOLDERS: [&str; 2] = ["##input##", "##output##"];
+static PLACEHOLDER_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"##[^#\s]*##").unwrap());
 
-                    self.request_tx
-                        .send(Request::ExtractFrame {
-                            args,
-                            output: preview_file,
-                        })
-                        .unwrap();
-                    self.waiting_for_image = true;
-                }
-                if ui.button("Play preview").clicked() {
-                    let mut args = vec![];
-                    args.append(&mut self.state.active_file_state.skip_seconds.to_option_args());
-                    args.append(&mut self.state.active_file_state.input_file.to_option_args());
-                    args.append(
-                        &mut self
-                            .state
-                            .active_file_state
-                            .cli_options
-                            .iter()
-                            .filter_map(|o| {
-                                if o.is_active() {
-                                    Some(o.to_option_args())
-                                } else {
-                                    None
-                                }
-                            })
-                            .flatten()
-                            .collect(),
-                    );
-                    args.append(&mut self.state.active_file_state.filter_options.to_option_args());
+#[derive(Debug, PartialEq)]
+enum TemplateIssue {
+    Unknown {
+        token: String,
+        suggestion: Option<&'static str>,
+    },
+    Missing(&'static str),
+}
 
-                    self.request_tx.send(Request::Play { args }).unwrap();
+impl Display for TemplateIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown {
+                token,
+                suggestion: Some(suggestion),
+            } => write!(f, "unknown placeholder {token}, did you mean {suggestion}?"),
+            Self::Unknown {
+                token,
+                suggestion: None,
+            } => write!(f, "unknown placeholder {token}"),
+            Self::Missing(placeholder) => write!(f, "missing placeholder {placeholder}"),
+        }
+    }
+}
+
+fn template_issues(template: &str) -> Vec<TemplateIssue> {
+    let mut issues: Vec<_> = PLACEHOLDER_REGEX
+        .find_iter(template)
+        .map(|token| token.as_str())
+        .filter(|token| !PLACEHOLDERS.contains(token))
+        .map(|token| TemplateIssue::Unknown {
+            token: token.to_string(),
+            suggestion: PLACEHOLDERS
+                .into_iter()
+                .map(|placeholder| (edit_distance(token, placeholder), placeholder))
+                .filter(|(distance, _)| *distance <= 2)
+                .min()
+                .map(|(_, placeholder)| placeholder),
+        })
+        .collect();
+    issues.extend(
+        REQUIRED_PLACEHOLDERS
+            .into_iter()
+            .filter(|placeholder| !template.contains(placeholder))
+            .map(TemplateIssue::Missing),
+    );
+    issues
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Forgets window positions and sizes as well as panel sizes.
+fn reset_layout(ctx: &egui::Context) {
+    ctx.memory_mut(|memory| {
+        memory.reset_areas();
+        memory.data.clear();
+    });
+}
+
+/// Appends `_suffix` to the file stem, e.g. `out.mp4` becomes `out_suffix.mp4`.
+fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut file_name = format!("{stem}_{suffix}");
+    if let Some(extension) = path.extension() {
+        file_name = format!("{file_name}.{}", extension.to_string_lossy());
+    }
+    path.with_file_name(file_name)
+}
+
+/// Leaf-level differences between two JSON trees as `(path, old, new)`.
+/// Array elements with a `type` tag (filters, CLI options) are labeled with it.
+fn json_diff(old: &serde_json::Value, new: &serde_json::Value) -> Vec<(String, String, String)> {
+    fn walk(
+        path: String,
+        old: Option<&serde_json::Value>,
+        new: Option<&serde_json::Value>,
+        diff: &mut Vec<(String, String, String)>,
+    ) {
+        use serde_json::Value;
+
+        let join = |key: String| {
+            if path.is_empty() {
+                key
+            } else {
+                format!("{path}.{key}")
+            }
+        };
+        match (old, new) {
+            (Some(Value::Object(old)), Some(Value::Object(new))) => {
+                let keys: std::collections::BTreeSet<_> = old.keys().chain(new.keys()).collect();
+                for key in keys {
+                    walk(join(key.clone()), old.get(key), new.get(key), diff);
                 }
-            });
-            ui.separator();
-            CollapsingHeader::new("Conversion template").show(ui, |ui| {
-                ui.text_edit_singleline(&mut self.state.conversion_template);
-            });
-            if ui.button("Generate conversion command").clicked() {
-                let mut template = self.state.conversion_template.clone();
-                template = template.replace(
-                    "##input##",
-                    &self
-                        .state
-                        .active_file_state
-                        .input_file
-                        .to_option_args()
-                        .join(" "),
+            }
+            (Some(Value::Array(old)), Some(Value::Array(new))) => {
+                for i in 0..old.len().max(new.len()) {
+                    let (old, new) = (old.get(i), new.get(i));
+                    let tag = new
+                        .or(old)
+                        .and_then(|v| v.get("type"))
+                        .and_then(Value::as_str)
+                        .map(|tag| format!(" {tag}"))
+                        .unwrap_or_default();
+                    walk(join(format!("{i}{tag}")), old, new, diff);
+                }
+            }
+            (old, new) if old != new => {
+                let show = |v: Option<&Value>| v.map_or("—".to_string(), Value::to_string);
+                diff.push((path, show(old), show(new)));
+            }
+            _ => {}
+        }
+    }
+
+    let mut diff = vec![];
+    walk(String::new(), Some(old), Some(new), &mut diff);
+    diff
+}
+
+/// The built-in default chain. Filters added to [`FILTER_REGISTRY`] are only offered in the
+/// Add filter menu and the palette.
+fn default_filters() -> FilterOption {
+    FilterOption {
+        filters: vec![
+            Box::new(FilterScale {
+                is_active: false,
+                width: 1280,
+                height: 720,
+            }),
+            Box::<FilterExposure>::default(),
+            Box::<FilterColortemp>::default(),
+            Box::<FilterLut>::default(),
+            Box::<FilterEq>::default(),
+            Box::<FilterColorBalance>::default(),
+            Box::<FilterColorKey>::default(),
+            Box::<FilterNormalize>::default(),
+            Box::<FilterLimiter>::default(),
+            Box::<FilterColorspace>::default(),
+            Box::<FilterToneCurve>::default(),
+            Box::<FilterSetSar>::default(),
+            Box::<FilterSetDar>::default(),
+            Box::<FilterDeshake>::default(),
+            Box::<FilterCustom>::default(),
+        ],
+        ..Default::default()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+enum CompareMode {
+    #[default]
+    Off,
+    /// Graded frame left of the wipe, original frame right of it.
+    Wipe,
+    Crossfade,
+}
+
+impl Display for CompareMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Self::Off => "Off",
+            Self::Wipe => "Wipe",
+            Self::Crossfade => "Crossfade",
+        };
+        write!(f, "{text}")
+    }
+}
+
+/// How grading filters behind a LUT are handled.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+enum LutOrder {
+    /// Filters may be in any order.
+    Off,
+    #[default]
+    Warn,
+    /// Move LUTs behind the grading filters of their group whenever the chain changes.
+    KeepLast,
+}
+
+impl Display for LutOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Self::Off => "Any order",
+            Self::Warn => "Warn",
+            Self::KeepLast => "Keep last",
+        };
+        write!(f, "{text}")
+    }
+}
+
+/// Shows the graded preview together with the ungraded frame, composited client-side.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+struct Compare {
+    mode: CompareMode,
+    /// How much of the graded frame shows, from 0 to 1.
+    position: f32,
+}
+
+impl Default for Compare {
+    fn default() -> Self {
+        Self {
+            mode: CompareMode::Off,
+            position: 0.5,
+        }
+    }
+}
+
+impl Compare {
+    /// Composites `original` into `graded`. The original is resized first if the filters
+    /// changed the frame size.
+    fn apply(&self, graded: &mut RgbaImage, original: &RgbaImage) {
+        if self.mode == CompareMode::Off {
+            return;
+        }
+        let original = if original.dimensions() == graded.dimensions() {
+            Cow::Borrowed(original)
+        } else {
+            Cow::Owned(image::imageops::resize(
+                original,
+                graded.width(),
+                graded.height(),
+                image::imageops::FilterType::Triangle,
+            ))
+        };
+        let position = self.position.clamp(0., 1.);
+        match self.mode {
+            CompareMode::Off => unreachable!(),
+            CompareMode::Wipe => {
+                let wipe = (graded.width() as f32 * position).round() as u32;
+                for (x, y, pixel) in graded.enumerate_pixels_mut() {
+                    if x >= wipe {
+                        *pixel = *original.get_pixel(x, y);
+                    }
+                }
+            }
+            CompareMode::Crossfade => {
+                for (pixel, original) in graded.pixels_mut().zip(original.pixels()) {
+                    for (c, o) in pixel.0.iter_mut().zip(original.0) {
+                        *c = (*c as f32 * position + o as f32 * (1. - position)).round() as u8;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+enum PreviewZoom {
+    /// Scale the frame to the central panel, keeping the aspect ratio.
+    #[default]
+    Fit,
+    /// One frame pixel per physical screen pixel, pan by scrolling.
+    ActualPixels,
+}
+
+impl PreviewZoom {
+    /// Display size in points of a frame that is `image` pixels large.
+    fn image_size(self, image: Vec2, available: Vec2, pixels_per_point: f32) -> Vec2 {
+        match self {
+            Self::Fit => {
+                let scale = (available.x / image.x).min(available.y / image.y);
+                if scale.is_finite() && scale > 0. {
+                    image * scale
+                } else {
+                    image / pixels_per_point
+                }
+            }
+            Self::ActualPixels => image / pixels_per_point,
+        }
+    }
+}
+
+/// Interactive zoom and pan on top of [`PreviewZoom`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct PreviewView {
+    zoom: f32,
+    /// Offset of the image's top left corner from the panel's, in points.
+    offset: Vec2,
+}
+
+impl Default for PreviewView {
+    fn default() -> Self {
+        Self {
+            zoom: 1.,
+            offset: Vec2::ZERO,
+        }
+    }
+}
+
+impl PreviewView {
+    /// Zooms by `factor` keeping the image point under `cursor` (relative to the panel) fixed.
+    fn zoom_around(&mut self, factor: f32, cursor: Vec2) {
+        let zoom = (self.zoom * factor).clamp(0.1, 32.);
+        let factor = zoom / self.zoom;
+        self.offset = cursor - (cursor - self.offset) * factor;
+        self.zoom = zoom;
+    }
+
+    fn image_rect(&self, panel: Rect, size: Vec2) -> Rect {
+        Rect::from_min_size(panel.min + self.offset, size * self.zoom)
+    }
+
+    /// Position relative to `rect`, clamped to 0 to 1.
+    fn to_relative(rect: Rect, pos: Pos2) -> Vec2 {
+        ((pos - rect.min) / rect.size()).clamp(Vec2::ZERO, Vec2::splat(1.))
+    }
+
+    /// Maps a screen position to the pixel of a `pixels` large frame shown in `rect`.
+    fn to_image_pixel(rect: Rect, pos: Pos2, pixels: [u32; 2]) -> Option<[u32; 2]> {
+        if !rect.contains(pos) {
+            return None;
+        }
+        let relative = (pos - rect.min) / rect.size();
+        Some([
+            ((relative.x * pixels[0] as f32) as u32).min(pixels[0] - 1),
+            ((relative.y * pixels[1] as f32) as u32).min(pixels[1] - 1),
+        ])
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+struct BatchGrade {
+    input_dir: String,
+    output_dir: String,
+    /// `None` while the image is still being graded.
+    #[serde(skip)]
+    status: BTreeMap<PathBuf, Option<Result<(), String>>>,
+}
+
+/// Images directly inside `dir`, sorted by name.
+fn batch_inputs(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut inputs = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_image_file(path))
+        .collect::<Vec<_>>();
+    inputs.sort();
+    Ok(inputs)
+}
+
+/// The output directory of a batch grade. It has to differ from the input directory, the
+/// graded images keep their file names and would overwrite the originals.
+fn batch_output_dir(input_dir: &Path, output_dir: &Path) -> Result<PathBuf, String> {
+    if output_dir.as_os_str().is_empty() {
+        return Err("No output directory".to_string());
+    }
+    let canonical = |dir: &Path| std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    if canonical(input_dir) == canonical(output_dir) {
+        return Err("The output directory is the input directory".to_string());
+    }
+    Ok(output_dir.to_path_buf())
+}
+
+#[derive(Debug, Copy, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+struct SaveFrameOptions {
+    with_manipulation: bool,
+    tiff_bit_depth: u8,
+}
+
+impl Default for SaveFrameOptions {
+    fn default() -> Self {
+        Self {
+            with_manipulation: false,
+            tiff_bit_depth: 16,
+        }
+    }
+}
+
+/// Downscaled JPEG previews for slow sources. Not suitable for judging the grade.
+#[derive(Debug, Copy, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct FastPreview {
+    is_active: bool,
+    width: u32,
+    quality: u8,
+}
+
+impl Default for FastPreview {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            width: 640,
+            quality: 5,
+        }
+    }
+}
+
+impl FastPreview {
+    fn draw(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.is_active, "Fast preview (not grade-accurate)");
+        ui.add(
+            DragValue::new(&mut self.width)
+                .range(16..=7680)
+                .prefix("Width: "),
+        );
+        ui.add(Slider::new(&mut self.quality, 2..=31).text("JPEG quantizer (lower is better)"));
+    }
+}
+
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+struct LutComparison {
+    luts: Vec<String>,
+    current: usize,
+    new_lut: String,
+}
+
+impl FileState {
+    fn with_filters(filter_options: FilterOption) -> Self {
+        Self {
+            input_file: Default::default(),
+            output_file: Default::default(),
+            encoder: Default::default(),
+            cli_options: INPUT_OPTION_REGISTRY.iter().map(|(_, new)| new()).collect(),
+            filter_options,
+            skip_seconds: Default::default(),
+            filter_metadata: Default::default(),
+            slate: Default::default(),
+        }
+    }
+
+    /// Adds the input options that file states saved before they existed lack.
+    fn add_missing_input_options(&mut self) {
+        for (name, new) in INPUT_OPTION_REGISTRY {
+            if !self.cli_options.iter().any(|o| o.name() == *name) {
+                self.cli_options.push(new());
+            }
+        }
+    }
+
+    fn active_cli_args(&self, placement: OptionPlacement) -> Vec<String> {
+        self.cli_options
+            .iter()
+            .filter_map(|o| {
+                if o.is_active() && o.placement() == placement {
+                    Some(o.to_option_args())
+                } else {
+                    None
+                }
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Arguments for each placeholder of the conversion template, in default template order.
+    fn conversion_arg_groups(&self) -> [(&'static str, Vec<String>); 5] {
+        let mut cli_args = self.active_cli_args(OptionPlacement::AfterInput);
+        cli_args.append(&mut self.filter_metadata.to_option_args(&self.filter_options));
+        [
+            (
+                "##input##",
+                [
+                    self.active_cli_args(OptionPlacement::BeforeInput),
+                    self.input_file.to_option_args(),
+                    self.input_file.map_args(true),
+                ]
+                .concat(),
+            ),
+            ("##cli##", cli_args),
+            ("##filter##", self.conversion_filter_args()),
+            ("##encoder##", self.encoder.to_option_args()),
+            ("##output##", self.output_file.to_option_args()),
+        ]
+    }
+
+    /// `filter_args` with the forced source range tagged in front of the chain.
+    fn range_tagged(&self, mut filter_args: Vec<String>) -> Vec<String> {
+        if let Some(range) = self.input_file.range.to_filter_string() {
+            match filter_args.last_mut() {
+                Some(filter_string) => *filter_string = format!("{range},{filter_string}"),
+                None => filter_args = vec!["-vf".to_string(), range],
+            }
+        }
+        filter_args
+    }
+
+    /// The filters with the slate appended.
+    fn conversion_filter_args(&self) -> Vec<String> {
+        let mut filter_args = self.range_tagged(self.filter_options.to_option_args());
+        let clip_name = self
+            .input_file
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        if let Some(slate) = self
+            .slate
+            .filter_string(&clip_name, self.skip_seconds.seconds)
+        {
+            append_filter(&mut filter_args, &slate);
+        }
+        filter_args
+    }
+
+    pub fn to_ffmpeg_args(&self, purpose: ArgPurpose) -> Vec<String> {
+        match purpose {
+            ArgPurpose::Preview {
+                output,
+                fast_preview,
+                extra_filter,
+                loglevel,
+            } => self.preview_args(output, fast_preview, extra_filter, loglevel),
+            ArgPurpose::Play { scopes } => {
+                let mut args = vec![];
+                args.append(&mut self.skip_seconds.to_option_args());
+                args.append(&mut self.input_file.to_option_args());
+                args.append(&mut self.input_file.ffplay_stream_args());
+                // ffplay lacks input options like -hwaccel
+                args.append(&mut self.active_cli_args(OptionPlacement::AfterInput));
+                let mut filter_args = self.range_tagged(self.filter_options.to_option_args());
+                if let Some(overlay) = scopes.overlay_filter() {
+                    append_filter(&mut filter_args, &overlay);
+                }
+                args.append(&mut filter_args);
+                args
+            }
+            ArgPurpose::Convert => self
+                .conversion_arg_groups()
+                .into_iter()
+                .flat_map(|(_, args)| args)
+                .collect(),
+            ArgPurpose::Still => {
+                let mut args = vec!["-y".to_string()];
+                args.append(&mut FfmpegLogLevel::Error.to_option_args());
+                args.append(&mut self.input_file.to_option_args());
+                args.append(&mut self.range_tagged(self.filter_options.to_option_args()));
+                args.append(&mut NumberOfFramesOption { frames: 1 }.to_option_args());
+                args.append(&mut self.output_file.to_option_args());
+                args
+            }
+            ArgPurpose::Proxy { output, seconds } => {
+                let mut args = vec!["-y".to_string()];
+                args.append(&mut FfmpegLogLevel::Error.to_option_args());
+                args.append(&mut self.skip_seconds.args_before_input());
+                for (placeholder, mut group) in self.conversion_arg_groups() {
+                    match placeholder {
+                        "##input##" => {
+                            args.append(&mut group);
+                            args.append(&mut self.skip_seconds.args_after_input());
+                        }
+                        "##encoder##" => args.extend(PROXY_ENCODER.map(String::from)),
+                        "##output##" => {
+                            args.extend(["-t".to_string(), seconds.to_string()]);
+                            args.push(output.to_string_lossy().to_string());
+                        }
+                        _ => args.append(&mut group),
+                    }
+                }
+                args
+            }
+        }
+    }
+
+    pub fn set_output(&mut self, path: PathBuf) {
+        self.output_file.path = path;
+    }
+
+    /// The arguments of the conversion command built from `template`, without the program.
+    /// The template is split like a shell would, so quoted arguments may contain spaces.
+    fn conversion_args(&self, template: &str) -> Vec<String> {
+        let groups = self.conversion_arg_groups();
+        let mut words = command_words(template).into_iter().peekable();
+        // The program, ffmpeg is always run from the settings
+        words.next_if(|word| !word.starts_with('-') && !PLACEHOLDERS.contains(&word.as_str()));
+        let mut args = vec![];
+        for word in words {
+            match groups.iter().find(|(placeholder, _)| *placeholder == word) {
+                Some((_, group)) => args.extend(group.iter().cloned()),
+                None => args.push(word),
+            }
+        }
+        args
+    }
+
+    /// The conversion command for a shell. Arguments are quoted where needed, unlike those
+    /// passed to [`Command`](std::process::Command).
+    fn conversion_command(&self, template: &str, kind: ScriptKind) -> String {
+        let mut template = template.to_string();
+        for (placeholder, args) in self.conversion_arg_groups() {
+            let args: Vec<_> = args.iter().map(|arg| shell_quote(arg, kind)).collect();
+            template = template.replace(placeholder, &args.join(" "));
+        }
+        template
+    }
+
+    fn preview_args(
+        &self,
+        output: &FrameOutput,
+        fast_preview: &FastPreview,
+        extra_filter: Option<&str>,
+        loglevel: FfmpegLogLevel,
+    ) -> Vec<String> {
+        self.frame_args(
+            self.filter_options.preview_option_args(),
+            output,
+            fast_preview,
+            extra_filter,
+            loglevel,
+        )
+    }
+
+    /// Arguments for the current frame as the preview would show it without any filter.
+    fn original_args(
+        &self,
+        output: &FrameOutput,
+        fast_preview: &FastPreview,
+        loglevel: FfmpegLogLevel,
+    ) -> Vec<String> {
+        self.frame_args(vec![], output, fast_preview, None, loglevel)
+    }
+
+    fn frame_args(
+        &self,
+        filter_args: Vec<String>,
+        output: &FrameOutput,
+        fast_preview: &FastPreview,
+        extra_filter: Option<&str>,
+        loglevel: FfmpegLogLevel,
+    ) -> Vec<String> {
+        let mut args = self.single_frame_args(loglevel);
+        let mut filter_args = self.range_tagged(filter_args);
+        let scale = fast_preview
+            .is_active
+            .then(|| format!("scale={}:-2", fast_preview.width));
+        for filter in scale.iter().map(String::as_str).chain(extra_filter) {
+            append_filter(&mut filter_args, filter);
+        }
+        if fast_preview.is_active {
+            filter_args.push("-q:v".to_string());
+            filter_args.push(fast_preview.quality.to_string());
+        }
+        args.append(&mut filter_args);
+        args.append(&mut output.to_option_args());
+        args
+    }
+
+    /// Where [`Self::single_frame_args`] seeks to.
+    fn frame_seek(&self) -> FrameSeek {
+        FrameSeek {
+            seconds: self.skip_seconds.seconds,
+            accurate: self.skip_seconds.accurate,
+        }
+    }
+
+    /// Arguments up to the filters for extracting the frame at [`Self::frame_seek`]. Previews,
+    /// originals for comparisons and thumbnails all start with these, so they show the same
+    /// frame: `-ss SECONDS` comes before `-i` to seek in the input, or right after it for frame
+    /// accurate seeking, followed by `-frames:v 1`.
+    fn single_frame_args(&self, loglevel: FfmpegLogLevel) -> Vec<String> {
+        let mut args = vec!["-y".to_string()];
+        args.append(&mut loglevel.to_option_args());
+        args.append(&mut self.skip_seconds.args_before_input());
+        args.append(&mut self.active_cli_args(OptionPlacement::BeforeInput));
+        args.append(&mut self.input_file.to_option_args());
+        args.append(&mut self.skip_seconds.args_after_input());
+        args.append(&mut self.input_file.map_args(false));
+        args.append(&mut NumberOfFramesOption { frames: 1 }.to_option_args());
+        args.append(&mut self.active_cli_args(OptionPlacement::AfterInput));
+        args
+    }
+
+    /// Arguments for a thumbnail of the current frame with only the filter at `filter` applied,
+    /// or without any filter. The output is left to the caller so the arguments can serve as
+    /// the cache key.
+    fn thumbnail_args(&self, filter: Option<usize>, loglevel: FfmpegLogLevel) -> Vec<String> {
+        let mut args = self.single_frame_args(loglevel);
+        let mut filter_args = self.range_tagged(match filter {
+            Some(i) => self.filter_options.solo_option_args(i),
+            None => vec![],
+        });
+        append_filter(&mut filter_args, &format!("scale={THUMBNAIL_WIDTH}:-2"));
+        args.append(&mut filter_args);
+        args
+    }
+}
+
+/// Position a single frame is extracted at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FrameSeek {
+    seconds: u64,
+    accurate: bool,
+}
+
+impl Display for FrameSeek {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let method = if self.accurate {
+            "frame accurate"
+        } else {
+            "input seek"
+        };
+        write!(f, "-ss {} ({method})", self.seconds)
+    }
+}
+
+/// Appends `filter` to the chain in `filter_args` (`["-vf", chain]` or empty).
+fn append_filter(filter_args: &mut Vec<String>, filter: &str) {
+    match filter_args.last_mut() {
+        Some(filter_string) => *filter_string = format!("{filter_string},{filter}"),
+        None => *filter_args = vec!["-vf".to_string(), filter.to_string()],
+    }
+}
+
+impl ColorustState {
+    fn project_len(&self) -> usize {
+        self.project_files.len() + 1
+    }
+
+    /// The file state at position `i` of the project.
+    fn project_file(&self, i: usize) -> &FileState {
+        match i.cmp(&self.project_active) {
+            std::cmp::Ordering::Less => &self.project_files[i],
+            std::cmp::Ordering::Equal => &self.active_file_state,
+            std::cmp::Ordering::Greater => &self.project_files[i - 1],
+        }
+    }
+
+    /// Makes the file state at position `i` of the project the active one.
+    fn switch_project_file(&mut self, i: usize) {
+        let active = self.project_active;
+        if i == active || i >= self.project_len() {
+            return;
+        }
+        let next = self
+            .project_files
+            .remove(if i < active { i } else { i - 1 });
+        let previous = std::mem::replace(&mut self.active_file_state, next);
+        self.project_files
+            .insert(if i < active { active - 1 } else { active }, previous);
+        self.project_active = i;
+    }
+
+    fn add_project_file(&mut self, file_state: FileState) {
+        self.project_files.push(file_state);
+        self.switch_project_file(self.project_len() - 1);
+    }
+
+    /// Removes the active file state from the project, the next one becomes active.
+    fn remove_active_project_file(&mut self) {
+        if self.project_files.is_empty() {
+            return;
+        }
+        if self.project_active == self.project_files.len() {
+            self.project_active -= 1;
+        }
+        self.active_file_state = self.project_files.remove(self.project_active);
+    }
+
+    /// The conversion template for `input`, which is the global one unless it is overridden.
+    fn conversion_template(&self, input: &Path) -> &str {
+        self.input_templates
+            .get(input)
+            .unwrap_or(&self.conversion_template)
+    }
+
+    /// Marks `input` as most recently used.
+    fn touch_history(&mut self, input: &Path) {
+        self.file_history_order.retain(|p| p != input);
+        self.file_history_order.push(input.to_path_buf());
+    }
+
+    /// Drops the least recently used entries beyond `file_history_limit`. Entries without a
+    /// recorded access are considered the oldest.
+    fn evict_history(&mut self) {
+        self.file_history_order
+            .retain(|p| self.file_history.contains_key(p));
+        while self.file_history.len() > self.file_history_limit {
+            let oldest = self
+                .file_history
+                .keys()
+                .find(|p| !self.file_history_order.contains(p))
+                .cloned()
+                .unwrap_or_else(|| self.file_history_order.remove(0));
+            log::info!("Evicting {oldest:?} from the file history");
+            self.file_history.remove(&oldest);
+        }
+    }
+}
+
+impl Default for ColorustState {
+    fn default() -> Self {
+        ColorustState {
+            active_file_state: FileState::with_filters(default_filters()),
+            project_files: vec![],
+            project_active: 0,
+            waveform_multiplier: 25.,
+            conversion_commands: Default::default(),
+            script_kind: Default::default(),
+            file_history: Default::default(),
+            file_history_order: Default::default(),
+            file_history_limit: 100,
+            conversion_template: "ffmpeg ##input## ##cli## ##filter## ##encoder## ##output##"
+                .to_string(),
+            input_templates: Default::default(),
+            preview_manipulations: vec![Default::default()],
+            auto_preview: false,
+            log_level: log::Level::Info,
+            preview_via_pipe: true,
+            lut_comparison: Default::default(),
+            fast_preview: Default::default(),
+            default_filters: default_filters(),
+            display_transform: Default::default(),
+            waveform_zoom: Default::default(),
+            waveform_graticule: Default::default(),
+            fast_scopes: false,
+            scope_interval_ms: 0,
+            precise_scopes: false,
+            ffmpeg_waveform: false,
+            save_frame: Default::default(),
+            playback_scopes: Default::default(),
+            external_player: Default::default(),
+            ffmpeg_loglevel: Default::default(),
+            batch_grade: Default::default(),
+            filter_thumbnails: false,
+            default_input_dir: Default::default(),
+            default_output_dir: Default::default(),
+            remember_layout: true,
+            range_locks: Vec::new(),
+            lock_ranges: false,
+            preview_zoom: Default::default(),
+            compare: Default::default(),
+            lut_order: Default::default(),
+            filter_presets: Default::default(),
+            looks: Default::default(),
+        }
+    }
+}
+
+impl ColorustApp {
+    pub fn new(
+        cc: &eframe::CreationContext<'_>,
+        request_tx: Sender<Request>,
+        response_rx: Receiver<Response>,
+    ) -> Self {
+        let mut state: ColorustState = if let Some(storage) = cc.storage {
+            eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default()
+        } else {
+            Default::default()
+        };
+        let recovered_state = read_recovery_file();
+        state.project_active = state.project_active.min(state.project_files.len());
+        for file_state in
+            std::iter::once(&mut state.active_file_state).chain(&mut state.project_files)
+        {
+            file_state.add_missing_input_options();
+        }
+        if !state.remember_layout {
+            // eframe restores the memory of the last session that persisted it
+            reset_layout(&cc.egui_ctx);
+        }
+        request_tx.send(Request::DetectCapabilities).unwrap();
+        Self {
+            state,
+            recovered_state,
+            last_autosave: Instant::now(),
+            image_texture: None,
+            request_tx,
+            response_rx,
+            temp_dir: TempDir::new().unwrap(),
+            waiting_for_image: false,
+            waveform: None,
+            waveform_texture: None,
+            saturation_scope: None,
+            error: None,
+            last_change: None,
+            filter_import: String::new(),
+            look_name: String::new(),
+            prefetch_cache: HashMap::new(),
+            prefetch_pending: HashSet::new(),
+            displayed_input: None,
+            next_job_id: 0,
+            latest_preview_id: 0,
+            latest_waveform_id: 0,
+            waiting_for_waveform: false,
+            live_base: None,
+            requested_tone: Default::default(),
+            scopes_are_approximate: false,
+            displayed_frame: None,
+            precise_frame: None,
+            save_frame_dialog: None,
+            script_dialog: None,
+            cdl_dialog: None,
+            filter_definitions: load_filter_definitions(),
+            scope_image_dialog: None,
+            scope_window_rects: HashMap::new(),
+            filter_palette: None,
+            look_diff: Default::default(),
+            preview_view: Default::default(),
+            hovered_pixel: None,
+            drawing_mask: false,
+            mask_drag_start: None,
+            thumbnails: Default::default(),
+            chain_warnings: vec![],
+            ffmpeg_capabilities: Default::default(),
+            image_has_alpha: false,
+            preview_downscaled: None,
+            original_frame: None,
+            original_args: vec![],
+            latest_original_id: 0,
+            original_seek: None,
+            requested_seek: None,
+            displayed_seek: None,
+            input_duration: None,
+            probe_json: None,
+            probed_color: Default::default(),
+            conversion: None,
+            overwrite_output: false,
+            scope_throttle: Default::default(),
+        }
+    }
+
+    fn autosave(&mut self) {
+        if self.last_autosave.elapsed() < AUTOSAVE_INTERVAL || self.recovered_state.is_some() {
+            return;
+        }
+        self.last_autosave = Instant::now();
+        let Some(path) = recovery_path() else {
+            return;
+        };
+        match serde_json::to_string(&self.state) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(&path, json) {
+                    log::warn!("Could not write recovery file {path:?}: {e}");
+                }
+            }
+            Err(e) => log::warn!("Could not serialize state: {e}"),
+        }
+    }
+
+    fn draw_recovery_window(&mut self, ctx: &egui::Context) {
+        if self.recovered_state.is_none() {
+            return;
+        }
+        egui::Window::new("Restore session")
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label("The last session did not exit cleanly. Restore its state?");
+                ui.horizontal(|ui| {
+                    if ui.button("Restore").clicked() {
+                        self.state = self.recovered_state.take().unwrap();
+                    }
+                    if ui.button("Discard").clicked() {
+                        self.recovered_state = None;
+                    }
+                });
+            });
+    }
+
+    fn next_job_id(&mut self) -> JobId {
+        self.next_job_id += 1;
+        self.next_job_id
+    }
+
+    /// Extracts the ungraded frame for comparisons, unless the current one is still valid.
+    fn request_original(&mut self) {
+        if self.state.compare.mode == CompareMode::Off {
+            return;
+        }
+        let id = self.next_job_id();
+        let output = self.frame_output("original", id);
+        let args = self.state.active_file_state.original_args(
+            &output,
+            &self.state.fast_preview,
+            self.state.ffmpeg_loglevel,
+        );
+        let key = args[..args.len() - output.to_option_args().len()].to_vec();
+        if key == self.original_args {
+            return;
+        }
+        self.original_args = key;
+        self.original_frame = None;
+        self.original_seek = Some(self.state.active_file_state.frame_seek());
+        self.latest_original_id = id;
+        self.request_tx
+            .send(Request::ExtractFrame { id, args, output })
+            .unwrap();
+    }
+
+    fn frame_output(&self, file_stem: &str, id: JobId) -> FrameOutput {
+        let format = if self.state.fast_preview.is_active {
+            FrameFormat::Jpeg
+        } else {
+            FrameFormat::Bmp
+        };
+        self.output_as(file_stem, id, format)
+    }
+
+    fn output_as(&self, file_stem: &str, id: JobId, format: FrameFormat) -> FrameOutput {
+        if self.state.preview_via_pipe {
+            FrameOutput::Pipe(format)
+        } else {
+            FrameOutput::File(
+                self.temp_dir
+                    .child(format!("{file_stem}-{id}.{}", format.extension())),
+            )
+        }
+    }
+
+    fn play_proxy(&mut self) {
+        let id = self.next_job_id();
+        let output = self.temp_dir.child(format!("proxy-{id}.mp4"));
+        let args = self
+            .state
+            .active_file_state
+            .to_ffmpeg_args(ArgPurpose::Proxy {
+                output: &output,
+                seconds: self.state.external_player.seconds,
+            });
+        self.request_tx
+            .send(Request::PlayProxy {
+                args,
+                output,
+                player: self.state.external_player.command.clone(),
+            })
+            .unwrap();
+    }
+
+    fn request_preview(&mut self) {
+        self.update_chain_warnings();
+        let id = self.next_job_id();
+        self.latest_preview_id = id;
+        self.requested_seek = Some(self.state.active_file_state.frame_seek());
+        self.requested_tone =
+            ToneApproximation::from_filters(&self.state.active_file_state.filter_options);
+        let precise = self.state.precise_scopes && !self.state.fast_preview.is_active;
+        let output = if precise {
+            self.output_as("preview", id, FrameFormat::Png16)
+        } else {
+            self.frame_output("preview", id)
+        };
+        let args = self
+            .state
+            .active_file_state
+            .to_ffmpeg_args(ArgPurpose::Preview {
+                output: &output,
+                fast_preview: &self.state.fast_preview,
+                extra_filter: precise.then_some(PRECISE_FRAME_FILTERS),
+                loglevel: self.state.ffmpeg_loglevel,
+            });
+
+        self.request_tx
+            .send(Request::ExtractFrame { id, args, output })
+            .unwrap();
+        self.waiting_for_image = true;
+        self.request_original();
+
+        if self.state.ffmpeg_waveform {
+            let id = self.next_job_id();
+            self.latest_waveform_id = id;
+            self.waiting_for_waveform = true;
+            let output = self.frame_output("waveform", id);
+            let args = self
+                .state
+                .active_file_state
+                .to_ffmpeg_args(ArgPurpose::Preview {
+                    output: &output,
+                    fast_preview: &self.state.fast_preview,
+                    extra_filter: Some(FFMPEG_WAVEFORM_FILTER),
+                    loglevel: self.state.ffmpeg_loglevel,
+                });
+            self.request_tx
+                .send(Request::ExtractFrame { id, args, output })
+                .unwrap();
+        }
+
+        if self.state.filter_thumbnails {
+            self.request_thumbnails();
+        }
+    }
+
+    fn thumbnail_keys(&self) -> impl Iterator<Item = (Option<usize>, String)> + '_ {
+        let file_state = &self.state.active_file_state;
+        std::iter::once(None)
+            .chain((0..file_state.filter_options.filters.len()).map(Some))
+            .map(|filter| {
+                let args = file_state.thumbnail_args(filter, self.state.ffmpeg_loglevel);
+                (filter, args.join(" "))
+            })
+    }
+
+    fn request_thumbnails(&mut self) {
+        let missing: Vec<_> = self
+            .thumbnail_keys()
+            .filter(|(_, key)| {
+                !self.thumbnails.textures.contains_key(key)
+                    && !self.thumbnails.pending.contains(key)
+            })
+            .collect();
+        for (filter, key) in missing {
+            let id = self.next_job_id();
+            let output = self.frame_output("thumbnail", id);
+            let mut args = self
+                .state
+                .active_file_state
+                .thumbnail_args(filter, self.state.ffmpeg_loglevel);
+            args.append(&mut output.to_option_args());
+            self.request_tx
+                .send(Request::Thumbnail {
+                    key: key.clone(),
+                    args,
+                    output,
+                })
+                .unwrap();
+            self.thumbnails.pending.insert(key);
+        }
+    }
+
+    /// Hands the cached thumbnails to the filter list.
+    fn update_filter_thumbnails(&mut self) {
+        let thumbnails = if self.state.filter_thumbnails {
+            let mut textures = self
+                .thumbnail_keys()
+                .map(|(_, key)| self.thumbnails.get(&key));
+            match textures.next().flatten() {
+                Some(before) => textures
+                    .map(|after| after.map(|after| [before, after]))
+                    .collect(),
+                None => vec![],
+            }
+        } else {
+            vec![]
+        };
+        self.state.active_file_state.filter_options.thumbnails = thumbnails;
+    }
+
+    fn prefetch_saved_inputs(&mut self) {
+        let saved_inputs: Vec<_> = self
+            .state
+            .file_history
+            .iter()
+            .filter(|(input, _)| {
+                !self.prefetch_cache.contains_key(*input) && !self.prefetch_pending.contains(*input)
+            })
+            .map(|(input, file_state_string)| (input.clone(), file_state_string.clone()))
+            .collect();
+        for (input, file_state_string) in saved_inputs.iter() {
+            let Ok(file_state) = serde_json::from_str::<FileState>(file_state_string) else {
+                log::warn!("Could not parse saved state for {input:?}");
+                continue;
+            };
+            let id = self.next_job_id();
+            let output = self.frame_output("prefetch", id);
+            let args = file_state.to_ffmpeg_args(ArgPurpose::Preview {
+                output: &output,
+                fast_preview: &self.state.fast_preview,
+                extra_filter: None,
+                loglevel: self.state.ffmpeg_loglevel,
+            });
+            self.request_tx
+                .send(Request::Prefetch {
+                    input: input.clone(),
+                    args,
+                    output,
+                })
+                .unwrap();
+            self.prefetch_pending.insert(input.clone());
+        }
+    }
+
+    fn handle_input_change(&mut self, ctx: &egui::Context) {
+        let input = &self.state.active_file_state.input_file.path;
+        if self.displayed_input.as_ref() == Some(input) {
+            return;
+        }
+        let input = input.clone();
+        self.clear_preview();
+        self.displayed_input = Some(input.clone());
+        self.input_duration = None;
+        self.probe_json = None;
+        self.probed_color = Default::default();
+        if input.is_file() {
+            self.request_tx
+                .send(Request::Probe {
+                    input: input.clone(),
+                })
+                .unwrap();
+        }
+        if let Some(img) = self.prefetch_cache.get(&input) {
+            // The saved state it was extracted with may be outdated
+            self.displayed_seek = None;
+            self.show_image(ctx, img.clone(), None);
+            self.request_preview();
+        }
+    }
+
+    /// Forgets everything cached about the input, e.g. while another process is rewriting it.
+    fn reload_input(&mut self, ctx: &egui::Context) {
+        let input = self.state.active_file_state.input_file.path.clone();
+        self.prefetch_cache.remove(&input);
+        self.thumbnails = Default::default();
+        self.displayed_input = None;
+        if !input.is_file() {
+            self.clear_preview();
+            self.displayed_input = Some(input.clone());
+            log::error!("Input file {input:?} does not exist");
+            self.error = Some(format!("Input file {} does not exist", input.display()));
+            return;
+        }
+        self.handle_input_change(ctx);
+        self.request_preview();
+    }
+
+    /// Logs warnings about the filter chain that were not shown before.
+    fn update_chain_warnings(&mut self) {
+        let filter_options = &self.state.active_file_state.filter_options;
+        let mut warnings = filter_options.chain_warnings();
+        if self.state.lut_order != LutOrder::Off {
+            warnings.extend(
+                filter_options
+                    .grading_after_lut()
+                    .into_iter()
+                    .map(|name| format!("{name} is applied after a LUT")),
+            );
+        }
+        for warning in warnings.iter().filter(|w| !self.chain_warnings.contains(w)) {
+            log::warn!("{warning}");
+        }
+        self.chain_warnings = warnings;
+    }
+
+    /// Drops the displayed frame and everything derived from it. Previews that are still being
+    /// extracted are ignored when they arrive.
+    fn clear_preview(&mut self) {
+        self.latest_preview_id = self.next_job_id();
+        self.image_texture = None;
+        self.scope_throttle.pending = None;
+        self.image_has_alpha = false;
+        self.preview_downscaled = None;
+        self.original_frame = None;
+        self.original_args.clear();
+        self.original_seek = None;
+        self.requested_seek = None;
+        self.displayed_seek = None;
+        self.waveform = None;
+        self.waveform_texture = None;
+        self.saturation_scope = None;
+        self.live_base = None;
+        self.displayed_frame = None;
+        self.precise_frame = None;
+        self.hovered_pixel = None;
+        self.scopes_are_approximate = false;
+        self.waiting_for_image = false;
+        self.waiting_for_waveform = false;
+    }
+
+    /// Re-derives the scopes from the last frame with the approximated tone change applied.
+    fn update_live_scopes(&mut self) {
+        let Some((base, base_tone)) = self.live_base.as_ref() else {
+            return;
+        };
+        let tone = ToneApproximation::from_filters(&self.state.active_file_state.filter_options);
+        let lut = base_tone.lut_to(&tone);
+        let mut img = base.clone();
+        for pixel in img.pixels_mut() {
+            for c in pixel.0.iter_mut().take(3) {
+                *c = lut[*c as usize];
+            }
+        }
+        self.update_scopes(DynamicImage::ImageRgba8(img));
+        self.scopes_are_approximate = true;
+    }
+
+    /// Computes the scopes of `img`, or keeps it for later if they were updated too recently.
+    fn update_scopes(&mut self, img: DynamicImage) {
+        let interval = Duration::from_millis(self.state.scope_interval_ms);
+        if self.scope_throttle.remaining(interval, Instant::now()) > Duration::ZERO {
+            self.scope_throttle.pending = Some(img);
+            return;
+        }
+        self.compute_scopes(&img);
+    }
+
+    fn compute_scopes(&mut self, img: &DynamicImage) {
+        self.scope_throttle.last_update = Some(Instant::now());
+        self.scope_throttle.pending = None;
+        let fast = self.state.fast_scopes;
+        let (waveform, saturation_scope) = match img {
+            DynamicImage::ImageRgba8(img) => scopes_of(&scope_frame(img, fast), level_8bit),
+            DynamicImage::ImageRgba32F(img) => scopes_of(&scope_frame(img, fast), |v| v as f64),
+            img => scopes_of(&scope_frame(&img.to_rgba8(), fast), level_8bit),
+        };
+        self.waveform = Some(waveform);
+        self.saturation_scope = Some(saturation_scope);
+    }
+
+    /// The displayed frame at the highest precision available.
+    fn scope_source(&self) -> Option<DynamicImage> {
+        match &self.precise_frame {
+            Some(frame) => Some(DynamicImage::ImageRgba32F(frame.clone())),
+            None => self.displayed_frame.clone().map(DynamicImage::ImageRgba8),
+        }
+    }
+
+    /// Computes the scopes of a frame held back by the throttle once it is due.
+    fn update_pending_scopes(&mut self, ctx: &egui::Context) {
+        if self.scope_throttle.pending.is_none() {
+            return;
+        }
+        let interval = Duration::from_millis(self.state.scope_interval_ms);
+        let remaining = self.scope_throttle.remaining(interval, Instant::now());
+        if remaining > Duration::ZERO {
+            ctx.request_repaint_after(remaining);
+        } else if let Some(img) = self.scope_throttle.pending.take() {
+            self.compute_scopes(&img);
+        }
+    }
+
+    fn show_image(&mut self, ctx: &egui::Context, img: RgbaImage, precise: Option<Rgba32FImage>) {
+        self.image_has_alpha = has_transparency(&img);
+        self.scopes_are_approximate = false;
+        self.precise_frame = precise;
+        self.displayed_frame = Some(img);
+        if let Some(source) = self.scope_source() {
+            self.update_scopes(source);
+        }
+        self.upload_preview(ctx);
+    }
+
+    /// The original frame, if it was extracted at the same position as the displayed frame.
+    fn aligned_original(&self) -> Option<&RgbaImage> {
+        self.original_frame
+            .as_ref()
+            .filter(|_| self.displayed_seek.is_some() && self.displayed_seek == self.original_seek)
+    }
+
+    /// Uploads the displayed frame with the comparison and the preview manipulations applied.
+    /// Frames larger than the GPU allows are downscaled for display only.
+    fn upload_preview(&mut self, ctx: &egui::Context) {
+        let Some(img) = self.displayed_frame.clone() else {
+            return;
+        };
+        let max_texture_side = ctx.input(|i| i.max_texture_side);
+        let mut img = texture_frame(img, max_texture_side);
+        self.preview_downscaled = self
+            .displayed_frame
+            .as_ref()
+            .filter(|frame| frame.dimensions() != img.dimensions())
+            .map(|_| max_texture_side);
+        if let Some(original) = self.aligned_original() {
+            self.state.compare.apply(&mut img, original);
+        }
+        for manipulation in &self.state.preview_manipulations {
+            manipulation.apply(&mut img);
+        }
+        self.state.display_transform.apply(&mut img);
+        let pixels = img.as_flat_samples();
+        let img = ColorImage::from_rgba_unmultiplied(
+            [img.width() as _, img.height() as _],
+            pixels.as_slice(),
+        );
+        self.image_texture = Some(ctx.load_texture("img", img, Default::default()));
+    }
+
+    fn schedule_auto_preview(&mut self, ctx: &egui::Context) {
+        if !self.state.auto_preview {
+            self.last_change = None;
+            return;
+        }
+        if let Some(last_change) = self.last_change {
+            let elapsed = last_change.elapsed();
+            if elapsed >= AUTO_PREVIEW_DEBOUNCE {
+                self.last_change = None;
+                self.request_preview();
+            } else {
+                ctx.request_repaint_after(AUTO_PREVIEW_DEBOUNCE - elapsed);
+            }
+        }
+    }
+
+    fn draw_side_panel(&mut self, ctx: &egui::Context) {
+        SidePanel::left("Parameters").show(ctx, |ui| {
+            let mut changed = false;
+            let mut order_changed = false;
+            CollapsingHeader::new(format!("Project ({})", self.state.project_len())).show(
+                ui,
+                |ui| {
+                    self.draw_project(ui);
+                },
+            );
+            let default_dir = |dir: &PathBuf| (!dir.as_os_str().is_empty()).then(|| dir.clone());
+            self.state.active_file_state.input_file.default_dir =
+                default_dir(&self.state.default_input_dir);
+            self.state.active_file_state.output_file.default_dir =
+                default_dir(&self.state.default_output_dir);
+            self.state.active_file_state.filter_options.capabilities =
+                self.ffmpeg_capabilities.clone();
+            self.state.active_file_state.filter_options.input_format =
+                self.probed_color.pix_fmt.clone();
+            self.state.default_filters.capabilities = self.ffmpeg_capabilities.clone();
+            if self.state.lock_ranges {
+                let locks = Arc::new(self.state.range_locks.clone());
+                ctx.data_mut(|d| d.insert_temp(range_locks_id(), locks));
+            } else {
+                ctx.data_mut(|d| d.remove_temp::<Arc<Vec<RangeLock>>>(range_locks_id()));
+            }
+            let presets = Arc::new(self.state.filter_presets.clone());
+            ctx.data_mut(|d| d.insert_temp(filter_presets_id(), presets));
+            CollapsingHeader::new(self.state.active_file_state.input_file.name()).show(ui, |ui| {
+                changed |= self
+                    .state
+                    .active_file_state
+                    .input_file
+                    .draw(ctx, ui)
+                    .changed();
+                if ui
+                    .button("Reload")
+                    .on_hover_text("Probe the input again and extract the current frame from disk")
+                    .clicked()
+                {
+                    self.reload_input(ctx);
+                }
+                if let Some(probe_json) = &self.probe_json {
+                    CollapsingHeader::new("Show raw probe").show(ui, |ui| {
+                        if ui.small_button("Copy").clicked() {
+                            ctx.copy_text(probe_json.clone());
+                        }
+                        ScrollArea::both().max_height(300.).show(ui, |ui| {
+                            ui.add(
+                                TextEdit::multiline(&mut probe_json.as_str())
+                                    .code_editor()
+                                    .desired_width(f32::INFINITY),
+                            );
+                        });
+                    });
+                }
+            });
+            CollapsingHeader::new(self.state.active_file_state.output_file.name()).show(ui, |ui| {
+                self.state.active_file_state.output_file.draw(ctx, ui);
+            });
+            CollapsingHeader::new(self.state.active_file_state.encoder.name()).show(ui, |ui| {
+                self.state.active_file_state.encoder.draw(ctx, ui);
+            });
+            CollapsingHeader::new(self.state.active_file_state.filter_metadata.name()).show(
+                ui,
+                |ui| {
+                    self.state.active_file_state.filter_metadata.draw(ctx, ui);
+                },
+            );
+            CollapsingHeader::new(self.state.active_file_state.slate.name()).show(ui, |ui| {
+                self.state.active_file_state.slate.draw(ctx, ui);
+            });
+            CollapsingHeader::new(self.state.active_file_state.skip_seconds.name()).show(
+                ui,
+                |ui| {
+                    changed |= self
+                        .state
+                        .active_file_state
+                        .skip_seconds
+                        .draw(ctx, ui)
+                        .changed();
+                },
+            );
+            ui.separator();
+            for opt in self.state.active_file_state.cli_options.iter_mut() {
+                CollapsingHeader::new(opt.name()).show(ui, |ui| {
+                    changed |= opt.draw(ctx, ui).changed();
+                });
+            }
+            CollapsingHeader::new("Filters").show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        TextEdit::singleline(&mut self.filter_import)
+                            .hint_text("Paste filter string"),
+                    );
+                    if ui.button("Import").clicked() {
+                        self.state.active_file_state.filter_options.filters =
+                            parse_filters(&self.filter_import);
+                        self.state.active_file_state.filter_options.pinned = 0;
+                        self.filter_import.clear();
+                        changed = true;
+                    }
+                    if ui
+                        .button("Import CDL")
+                        .on_hover_text("Add the grade of an ASC CDL file (.cdl, .cc, .ccc)")
+                        .clicked()
+                    {
+                        let mut dialog = FileDialog::open_file(None).show_files_filter(Box::new(
+                            |path: &Path| {
+                                path.extension().is_some_and(|ext| {
+                                    ["cdl", "cc", "ccc"]
+                                        .iter()
+                                        .any(|cdl| ext.eq_ignore_ascii_case(cdl))
+                                })
+                            },
+                        ));
+                        dialog.open();
+                        self.cdl_dialog = Some(dialog);
+                    }
+                    ui.menu_button("Add filter", |ui| {
+                        for (name, new) in FILTER_REGISTRY {
+                            let missing = self.ffmpeg_capabilities.missing_filters(new().as_ref());
+                            if ui
+                                .add_enabled(missing.is_empty(), Button::new(*name))
+                                .on_disabled_hover_text(
+                                    self.ffmpeg_capabilities.missing_text(&missing),
+                                )
+                                .clicked()
+                            {
+                                self.state
+                                    .active_file_state
+                                    .filter_options
+                                    .filters
+                                    .push(new());
+                                changed = true;
+                                ui.close_menu();
+                            }
+                        }
+                        ui.separator();
+                        for definition in &self.filter_definitions {
+                            let filter = FilterTemplated::new(definition.clone());
+                            let missing = self.ffmpeg_capabilities.missing_filters(&filter);
+                            if ui
+                                .add_enabled(missing.is_empty(), Button::new(&definition.name))
+                                .on_disabled_hover_text(
+                                    self.ffmpeg_capabilities.missing_text(&missing),
+                                )
+                                .clicked()
+                            {
+                                self.state
+                                    .active_file_state
+                                    .filter_options
+                                    .filters
+                                    .push(Box::new(filter));
+                                changed = true;
+                                ui.close_menu();
+                            }
+                        }
+                        if self.filter_definitions.is_empty() {
+                            let path = filter_definitions_path().unwrap_or_default();
+                            ui.weak("No user filters").on_hover_text(format!(
+                                "Define filters in {} and restart",
+                                path.display()
+                            ));
+                        }
+                    });
+                });
+                if ui
+                    .checkbox(&mut self.state.filter_thumbnails, "Thumbnails")
+                    .on_hover_text(
+                        "Show each filter's effect on its own next to the unfiltered frame",
+                    )
+                    .changed()
+                    && self.state.filter_thumbnails
+                {
+                    self.request_thumbnails();
+                }
+                self.update_filter_thumbnails();
+                changed |= self
+                    .state
+                    .active_file_state
+                    .filter_options
+                    .draw(ctx, ui)
+                    .changed();
+                let preset_changes = ctx.data_mut(|d| {
+                    d.remove_temp::<Vec<PresetChange>>(preset_changes_id())
+                        .unwrap_or_default()
+                });
+                for preset_change in preset_changes {
+                    preset_change.apply(&mut self.state.filter_presets);
+                }
+                for warning in &self.chain_warnings {
+                    ui.colored_label(Color32::YELLOW, format!("⚠ {warning}"));
+                }
+                ComboBox::from_label("LUT order")
+                    .selected_text(self.state.lut_order.to_string())
+                    .show_ui(ui, |ui| {
+                        for order in [LutOrder::Off, LutOrder::Warn, LutOrder::KeepLast] {
+                            order_changed |= ui
+                                .selectable_value(
+                                    &mut self.state.lut_order,
+                                    order,
+                                    order.to_string(),
+                                )
+                                .changed();
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Creative LUTs are usually applied after the grade. \
+                         Warn about grading filters behind a LUT or move the LUT behind them.",
+                    );
+                ui.horizontal(|ui| {
+                    if ui.button("Save as default").clicked() {
+                        self.state.default_filters =
+                            self.state.active_file_state.filter_options.clone();
+                    }
+                    if ui.button("Reset to default").clicked() {
+                        self.state.active_file_state.filter_options =
+                            self.state.default_filters.clone();
+                        changed = true;
+                    }
+                    if ui.button("Restore built-in default").clicked() {
+                        self.state.default_filters = default_filters();
+                    }
+                });
+                if ui
+                    .button("Copy as kdenlive filtergraph")
+                    .on_hover_text("Paste into the producer of a clip in a kdenlive project")
+                    .clicked()
+                {
+                    match self.state.active_file_state.filter_options.filter_string() {
+                        Some(filter_string) => ctx.copy_text(filtergraph_property(&filter_string)),
+                        None => log::warn!("No active filters to copy"),
+                    }
+                }
+            });
+            changed |= self.import_cdl(ctx);
+            if (changed || order_changed)
+                && self.state.lut_order == LutOrder::KeepLast
+                && self.state.active_file_state.filter_options.move_luts_last()
+            {
+                log::info!("Moved the LUT behind the grading filters");
+                changed = true;
+            }
+            if changed || order_changed {
+                self.update_chain_warnings();
+            }
+            if changed && self.state.auto_preview {
+                self.last_change = Some(Instant::now());
+            }
+            if changed && !self.state.auto_preview {
+                self.update_live_scopes();
+            }
+            ui.separator();
+            CollapsingHeader::new("Preview Manipulation").show(ui, |ui| {
+                draw_preview_manipulations(ctx, ui, &mut self.state.preview_manipulations);
+            });
+            CollapsingHeader::new("Fast Preview").show(ui, |ui| {
+                self.state.fast_preview.draw(ctx, ui);
+            });
+            CollapsingHeader::new("Display").show(ui, |ui| {
+                self.state.display_transform.draw(ctx, ui);
+            });
+            CollapsingHeader::new("Save frame").show(ui, |ui| {
+                self.draw_save_frame(ctx, ui);
+            });
+            CollapsingHeader::new("Range locks").show(ui, |ui| {
+                self.draw_range_locks(ui);
+            });
+            CollapsingHeader::new("Default directories").show(ui, |ui| {
+                egui::Grid::new("default_directories").show(ui, |ui| {
+                    for (label, dir) in [
+                        ("Input", &mut self.state.default_input_dir),
+                        ("Output", &mut self.state.default_output_dir),
+                    ] {
+                        ui.label(label);
+                        let mut path = dir.to_string_lossy();
+                        if ui
+                            .add(TextEdit::singleline(path.to_mut()).hint_text("None"))
+                            .changed()
+                        {
+                            *dir = PathBuf::from(path.to_string());
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Create preview").clicked() {
+                    self.request_preview();
+                }
+                if ui
+                    .button("Clear")
+                    .on_hover_text("Remove the preview and scopes")
+                    .clicked()
+                {
+                    self.clear_preview();
+                }
+                ui.checkbox(&mut self.state.auto_preview, "Auto preview");
+                ui.checkbox(&mut self.state.preview_via_pipe, "Pipe")
+                    .on_hover_text(
+                        "Read the preview frame from ffmpeg's stdout instead of a temporary file",
+                    );
+                if ui.button("Play preview").clicked() {
+                    let args = self
+                        .state
+                        .active_file_state
+                        .to_ffmpeg_args(ArgPurpose::Play {
+                            scopes: self.state.playback_scopes,
+                        });
+                    self.request_tx.send(Request::Play { args }).unwrap();
+                }
+                ComboBox::from_id_salt("playback_scopes")
+                    .selected_text(self.state.playback_scopes.to_string())
+                    .show_ui(ui, |ui| {
+                        for scopes in [
+                            PlaybackScopes::None,
+                            PlaybackScopes::Waveform,
+                            PlaybackScopes::Vectorscope,
+                            PlaybackScopes::Both,
+                        ] {
+                            let text = scopes.to_string();
+                            ui.selectable_value(&mut self.state.playback_scopes, scopes, text);
+                        }
+                    })
+                    .response
+                    .on_hover_text("Scopes rendered by ffplay during playback");
+            });
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Open in player")
+                    .on_hover_text(
+                        "Encode a graded proxy from the current position and open it in an \
+                         external player",
+                    )
+                    .clicked()
+                {
+                    self.play_proxy();
+                }
+                ui.add(
+                    DragValue::new(&mut self.state.external_player.seconds)
+                        .range(1.0..=600.)
+                        .suffix(" s"),
+                );
+                ui.add(
+                    TextEdit::singleline(&mut self.state.external_player.command)
+                        .hint_text("System default player"),
+                );
+            });
+            ui.separator();
+            CollapsingHeader::new("Conversion template").show(ui, |ui| {
+                self.draw_conversion_template(ui);
+            });
+            if ui.button("Generate conversion command").clicked() {
+                let template = self
+                    .state
+                    .conversion_template(&self.state.active_file_state.input_file.path);
+                let issues = template_issues(template);
+                if !issues.is_empty() {
+                    let issues: Vec<_> = issues.iter().map(ToString::to_string).collect();
+                    self.error = Some(format!("Conversion template: {}", issues.join(", ")));
+                }
+                let command = self
+                    .state
+                    .active_file_state
+                    .conversion_command(template, self.state.script_kind);
+                writeln!(&mut self.state.conversion_commands, "{command}").unwrap();
+            }
+            self.draw_convert_now(ui);
+            ui.separator();
+            ui.horizontal(|ui| {
+                let input = self.state.active_file_state.input_file.path.clone();
+                if ui.button("Save file state").clicked() {
+                    self.state.file_history.insert(
+                        input.clone(),
+                        serde_json::to_string(&self.state.active_file_state).unwrap(),
+                    );
+                    self.state.touch_history(&input);
+                    self.state.evict_history();
+                }
+                if ui.button("Load file state").clicked() {
+                    if let Some(file_state_string) = self.state.file_history.get(&input) {
+                        if let Ok(file_state) = serde_json::from_str(file_state_string) {
+                            self.state.active_file_state = file_state;
+                            self.state.touch_history(&input);
+                        } else {
+                            log::error!("Could not parse state from JSON!");
+                        }
+                    } else {
+                        log::warn!("No state available!");
+                    }
+                }
+            });
+            ui.add(
+                DragValue::new(&mut self.state.file_history_limit)
+                    .range(1..=10000)
+                    .prefix("Saved file states limit: "),
+            );
+            CollapsingHeader::new("Looks").show(ui, |ui| {
+                self.draw_looks(ui);
+            });
+            CollapsingHeader::new("Batch grade images").show(ui, |ui| {
+                self.draw_batch_grade(ui);
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Prefetch saved inputs").clicked() {
+                    self.prefetch_saved_inputs();
+                }
+                ui.label(format!(
+                    "Prefetched: {}/{}",
+                    self.prefetch_cache.len(),
+                    self.prefetch_cache.len() + self.prefetch_pending.len()
+                ));
+            });
+        });
+    }
+
+    fn draw_range_locks(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.state.lock_ranges, "Lock ranges")
+            .on_hover_text("Limit the filter sliders to the ranges below");
+        let mut remove = None;
+        egui::Grid::new("range_locks").show(ui, |ui| {
+            for (i, lock) in self.state.range_locks.iter_mut().enumerate() {
+                ComboBox::from_id_salt(("range_lock_filter", i))
+                    .selected_text(lock.filter.as_str())
+                    .show_ui(ui, |ui| {
+                        for (name, _) in FILTER_REGISTRY {
+                            ui.selectable_value(&mut lock.filter, name.to_string(), *name);
+                        }
+                    });
+                ui.add(
+                    TextEdit::singleline(&mut lock.param)
+                        .hint_text("Slider")
+                        .desired_width(100.),
+                )
+                .on_hover_text("Text of the slider, e.g. \"Exposure\" or \"Shadows red\"");
+                ui.add(DragValue::new(&mut lock.min).speed(0.01).prefix("min: "));
+                ui.add(DragValue::new(&mut lock.max).speed(0.01).prefix("max: "));
+                if ui.button("🗑").clicked() {
+                    remove = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(i) = remove {
+            self.state.range_locks.remove(i);
+        }
+        if ui.button("Add lock").clicked() {
+            self.state.range_locks.push(RangeLock {
+                filter: "Exposure".to_string(),
+                param: "Exposure".to_string(),
+                min: -1.,
+                max: 1.,
+            });
+        }
+    }
+
+    fn draw_project(&mut self, ui: &mut egui::Ui) {
+        let mut selected = None;
+        for i in 0..self.state.project_len() {
+            let path = &self.state.project_file(i).input_file.path;
+            let name = match path.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => "(no input)".to_string(),
+            };
+            if ui
+                .selectable_label(i == self.state.project_active, name)
+                .on_hover_text(path.to_string_lossy())
+                .clicked()
+            {
+                selected = Some(i);
+            }
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Add input").clicked() {
+                self.stash_displayed_frame();
+                let file_state = FileState::with_filters(self.state.default_filters.clone());
+                self.state.add_project_file(file_state);
+            }
+            if ui
+                .add_enabled(
+                    self.state.project_len() > 1,
+                    egui::Button::new("Remove from project"),
+                )
+                .clicked()
+            {
+                self.state.remove_active_project_file();
+            }
+        });
+        if let Some(i) = selected {
+            self.stash_displayed_frame();
+            self.state.switch_project_file(i);
+        }
+    }
+
+    /// Keeps the current frame so it is shown right away when switching back to the input.
+    fn stash_displayed_frame(&mut self) {
+        if let Some(frame) = &self.displayed_frame {
+            let input = self.state.active_file_state.input_file.path.clone();
+            self.prefetch_cache.insert(input, frame.clone());
+        }
+    }
+
+    fn draw_looks(&mut self, ui: &mut egui::Ui) {
+        let input = self.state.active_file_state.input_file.path.clone();
+        ui.horizontal(|ui| {
+            ui.add(TextEdit::singleline(&mut self.look_name).hint_text("Look name"));
+            if ui.button("Save look").clicked() && !self.look_name.is_empty() {
+                self.state.looks.entry(input.clone()).or_default().insert(
+                    std::mem::take(&mut self.look_name),
+                    serde_json::to_string(&self.state.active_file_state).unwrap(),
                 );
-                template = template.replace(
-                    "##cli##",
-                    &self
-                        .state
-                        .active_file_state
-                        .cli_options
-                        .iter()
-                        .filter_map(|o| {
-                            if o.is_active() {
-                                Some(o.to_option_args())
-                            } else {
-                                None
-                            }
-                        })
-                        .flatten()
-                        .collect::<Vec<_>>()
-                        .join(" "),
+            }
+        });
+        let Some(looks) = self.state.looks.get_mut(&input) else {
+            return;
+        };
+        let mut remove = None;
+        for (name, file_state_string) in looks.iter() {
+            ui.horizontal(|ui| {
+                ui.label(name);
+                if ui.button("Load").clicked() {
+                    match serde_json::from_str(file_state_string) {
+                        Ok(file_state) => self.state.active_file_state = file_state,
+                        Err(e) => log::error!("Could not parse look {name}: {e}"),
+                    }
+                }
+                if ui.button("Delete").clicked() {
+                    remove = Some(name.clone());
+                }
+            });
+        }
+        if let Some(name) = remove {
+            looks.remove(&name);
+        }
+        if looks.len() >= 2 {
+            CollapsingHeader::new("Compare looks").show(ui, |ui| {
+                let (old_name, new_name) = &mut self.look_diff;
+                ui.horizontal(|ui| {
+                    for (id, selected) in [
+                        ("look_diff_old", &mut *old_name),
+                        ("look_diff_new", &mut *new_name),
+                    ] {
+                        ComboBox::from_id_salt(id)
+                            .selected_text(selected.clone())
+                            .show_ui(ui, |ui| {
+                                for name in looks.keys() {
+                                    ui.selectable_value(selected, name.clone(), name);
+                                }
+                            });
+                    }
+                });
+                let (Some(old), Some(new)) = (looks.get(old_name), looks.get(new_name)) else {
+                    return;
+                };
+                let (Ok(old), Ok(new)) = (
+                    serde_json::from_str::<serde_json::Value>(old),
+                    serde_json::from_str::<serde_json::Value>(new),
+                ) else {
+                    ui.label("Could not parse looks");
+                    return;
+                };
+                let diff = json_diff(&old, &new);
+                if diff.is_empty() {
+                    ui.label("No differences");
+                    return;
+                }
+                egui::Grid::new("look_diff").striped(true).show(ui, |ui| {
+                    for (path, old, new) in diff {
+                        ui.label(path);
+                        ui.label(format!("{old} → {new}"));
+                        ui.end_row();
+                    }
+                });
+            });
+        }
+        if ui.button("Generate conversions for all looks").clicked() {
+            // Same as `ColorustState::conversion_template` while `looks` is borrowed
+            let template = self
+                .state
+                .input_templates
+                .get(&input)
+                .unwrap_or(&self.state.conversion_template);
+            for (name, file_state_string) in looks.iter() {
+                let mut file_state: FileState = match serde_json::from_str(file_state_string) {
+                    Ok(file_state) => file_state,
+                    Err(e) => {
+                        log::error!("Could not parse look {name}: {e}");
+                        continue;
+                    }
+                };
+                file_state.output_file.path = path_with_suffix(&file_state.output_file.path, name);
+                let command = file_state.conversion_command(template, self.state.script_kind);
+                writeln!(&mut self.state.conversion_commands, "{command}").unwrap();
+            }
+        }
+    }
+
+    fn draw_convert_now(&mut self, ui: &mut egui::Ui) {
+        let running = self
+            .conversion
+            .as_ref()
+            .is_some_and(|conversion| conversion.result.is_none());
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!running, Button::new("Convert now"))
+                .on_hover_text("Run the conversion command in the background")
+                .clicked()
+            {
+                self.convert_now();
+            }
+            ui.checkbox(&mut self.overwrite_output, "Overwrite output");
+        });
+        let Some(conversion) = &self.conversion else {
+            return;
+        };
+        match &conversion.result {
+            None => {
+                let text = format!("{:.1} s", conversion.seconds);
+                let progress = match conversion.duration {
+                    Some(duration) if duration > 0. => {
+                        egui::ProgressBar::new((conversion.seconds / duration) as f32)
+                            .show_percentage()
+                    }
+                    _ => egui::ProgressBar::new(0.).animate(true),
+                };
+                ui.add(progress.text(text));
+            }
+            Some(Ok(())) => {
+                ui.label(format!("Converted to {}", conversion.output.display()));
+            }
+            Some(Err(e)) => {
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    format!("Conversion failed: {e}"),
                 );
-                template = template.replace(
-                    "##filter##",
-                    &self
-                        .state
-                        .active_file_state
-                        .filter_options
-                        .to_option_args()
-                        .join(" "),
+            }
+        }
+    }
+
+    fn convert_now(&mut self) {
+        let file_state = &self.state.active_file_state;
+        let template = self.state.conversion_template(&file_state.input_file.path);
+        let issues = template_issues(template);
+        if issues
+            .iter()
+            .any(|issue| matches!(issue, TemplateIssue::Missing(_)))
+        {
+            let issues: Vec<_> = issues.iter().map(ToString::to_string).collect();
+            self.error = Some(format!("Conversion template: {}", issues.join(", ")));
+            return;
+        }
+        let mut args = file_state.conversion_args(template);
+        // A template that asks to overwrite wins over the checkbox
+        if !args.iter().any(|arg| arg == "-y") {
+            let overwrite = if self.overwrite_output { "-y" } else { "-n" };
+            args.insert(0, overwrite.to_string());
+        }
+        let input = file_state.input_file.path.clone();
+        log::info!("Converting {input:?}");
+        self.conversion = Some(Conversion {
+            input: input.clone(),
+            output: file_state.output_file.path.clone(),
+            duration: self.input_duration,
+            seconds: 0.,
+            result: None,
+        });
+        self.request_tx
+            .send(Request::Convert { input, args })
+            .unwrap();
+    }
+
+    fn draw_conversion_template(&mut self, ui: &mut egui::Ui) {
+        let input = self.state.active_file_state.input_file.path.clone();
+        let mut input_only = self.state.input_templates.contains_key(&input);
+        if ui
+            .add_enabled(
+                !input.as_os_str().is_empty(),
+                egui::Checkbox::new(&mut input_only, "Use for this input only"),
+            )
+            .on_hover_text("Edit a template that replaces the global one for the current input")
+            .changed()
+        {
+            if input_only {
+                let template = self.state.conversion_template.clone();
+                self.state.input_templates.insert(input.clone(), template);
+            } else {
+                self.state.input_templates.remove(&input);
+            }
+        }
+        let template = self
+            .state
+            .input_templates
+            .get_mut(&input)
+            .unwrap_or(&mut self.state.conversion_template);
+        let mut layouter = |ui: &egui::Ui, text: &str, _wrap_width: f32| {
+            let mut job = egui::text::LayoutJob::default();
+            let font = egui::TextStyle::Monospace.resolve(ui.style());
+            let normal = ui.visuals().text_color();
+            let mut last = 0;
+            for token in PLACEHOLDER_REGEX.find_iter(text) {
+                job.append(
+                    &text[last..token.start()],
+                    0.,
+                    egui::TextFormat::simple(font.clone(), normal),
                 );
-                template = template.replace(
-                    "##encoder##",
-                    &self
-                        .state
-                        .active_file_state
-                        .encoder
-                        .to_option_args()
-                        .join(" "),
+                let color = if PLACEHOLDERS.contains(&token.as_str()) {
+                    Color32::LIGHT_BLUE
+                } else {
+                    Color32::RED
+                };
+                job.append(
+                    token.as_str(),
+                    0.,
+                    egui::TextFormat::simple(font.clone(), color),
                 );
-                template = template.replace(
-                    "##output##",
-                    &self
+                last = token.end();
+            }
+            job.append(&text[last..], 0., egui::TextFormat::simple(font, normal));
+            ui.fonts(|f| f.layout_job(job))
+        };
+        ui.add(TextEdit::singleline(template).layouter(&mut layouter));
+        let issues = template_issues(template);
+        for issue in issues.iter() {
+            ui.colored_label(Color32::YELLOW, issue.to_string());
+        }
+        if issues.iter().any(|i| {
+            matches!(
+                i,
+                TemplateIssue::Unknown {
+                    suggestion: Some(_),
+                    ..
+                }
+            )
+        }) && ui.button("Fix placeholders").clicked()
+        {
+            for issue in issues {
+                if let TemplateIssue::Unknown {
+                    token,
+                    suggestion: Some(suggestion),
+                } = issue
+                {
+                    *template = template.replace(&token, suggestion);
+                }
+            }
+        }
+    }
+
+    fn draw_bottom_panel(&mut self, ctx: &egui::Context) {
+        TopBottomPanel::bottom("conversion_commands")
+            .resizable(true)
+            .max_height(500.)
+            .show(ctx, |ui| {
+                ScrollArea::new([false, true]).show(ui, |ui| {
+                    let available_size = ui.available_size();
+                    ui.add_sized(
+                        Vec2::new(available_size.x, available_size.y - 40.),
+                        TextEdit::multiline(&mut self.state.conversion_commands),
+                    );
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        self.draw_script_export(ctx, ui);
+                        ui.separator();
+                        match &self.error {
+                            Some(error) => ui.label(
+                                RichText::new(format!("Error: {error}")).color(Color32::RED),
+                            ),
+                            None => ui.label(RichText::new("OK").color(Color32::GREEN)),
+                        };
+                    });
+                });
+            });
+    }
+
+    /// Adds the first correction of the CDL file chosen in the dialog as a filter.
+    fn import_cdl(&mut self, ctx: &egui::Context) -> bool {
+        let Some(dialog) = &mut self.cdl_dialog else {
+            return false;
+        };
+        if !dialog.show(ctx).selected() {
+            return false;
+        }
+        let path = dialog.path().map(Path::to_path_buf);
+        self.cdl_dialog = None;
+        let Some(path) = path else {
+            return false;
+        };
+        let corrections = match read_cdl(&path) {
+            Ok(corrections) => corrections,
+            Err(e) => {
+                log::error!("Could not import CDL {path:?}: {e}");
+                return false;
+            }
+        };
+        if corrections.len() > 1 {
+            log::warn!(
+                "{path:?} has {} color corrections, importing the first one",
+                corrections.len()
+            );
+        }
+        let correction = corrections.into_iter().next().unwrap();
+        log::info!(
+            "Imported CDL {}",
+            correction.id.as_deref().unwrap_or("without id")
+        );
+        self.state
+            .active_file_state
+            .filter_options
+            .filters
+            .push(Box::new(correction.filter));
+        true
+    }
+
+    fn draw_script_export(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        if ui
+            .add_enabled(
+                !self.state.conversion_commands.trim().is_empty(),
+                egui::Button::new("Export commands as script"),
+            )
+            .clicked()
+        {
+            let mut dialog = FileDialog::save_file(None)
+                .default_filename(format!("colorust.{}", self.state.script_kind.extension()));
+            dialog.open();
+            self.script_dialog = Some(dialog);
+        }
+        ComboBox::from_id_salt("script_kind")
+            .selected_text(self.state.script_kind.to_string())
+            .show_ui(ui, |ui| {
+                for kind in [ScriptKind::Shell, ScriptKind::Batch] {
+                    ui.selectable_value(&mut self.state.script_kind, kind, kind.to_string());
+                }
+            });
+        if let Some(dialog) = &mut self.script_dialog {
+            if dialog.show(ctx).selected() {
+                if let Some(path) = dialog.path() {
+                    let script = self
                         .state
-                        .active_file_state
-                        .output_file
-                        .to_option_args()
-                        .join(" "),
+                        .script_kind
+                        .script(&self.state.conversion_commands);
+                    match write_script(path, &script) {
+                        Ok(()) => log::info!("Exported commands to {path:?}"),
+                        Err(e) => log::error!("Could not write {path:?}: {e}"),
+                    }
+                }
+                self.script_dialog = None;
+            }
+        }
+    }
+
+    fn draw_windows(&mut self, ctx: &egui::Context) {
+        let waveforms = egui::Window::new("waveforms").show(ctx, |ui| {
+            self.draw_scope_notes(ui);
+            self.draw_save_scope_image_button(ui, "waveforms");
+            ui.add(Slider::new(&mut self.state.waveform_multiplier, 1.0..=100.).text("Multiplier"));
+            ui.checkbox(
+                &mut self.state.ffmpeg_waveform,
+                "Use ffmpeg's waveform filter",
+            )
+            .on_hover_text("Extracts a second frame with ffmpeg's waveform filter applied");
+            if self.state.ffmpeg_waveform {
+                if let Some(waveform_texture) = self.waveform_texture.as_ref() {
+                    ui.add(egui::Image::new(waveform_texture).max_width(1050.));
+                }
+                return;
+            }
+            if ui
+                .checkbox(&mut self.state.fast_scopes, "Fast scopes")
+                .on_hover_text(format!(
+                    "Compute the scopes from the frame downscaled to {FAST_SCOPES_WIDTH} columns. \
+                     Fine detail and single-pixel extremes are averaged out."
+                ))
+                .changed()
+            {
+                if let Some(source) = self.scope_source() {
+                    self.compute_scopes(&source);
+                }
+            }
+            ui.checkbox(&mut self.state.precise_scopes, "Precise scopes")
+                .on_hover_text(
+                    "Extract previews as 16-bit PNG so the scopes show detail finer than 8 bits. \
+                     The preview itself stays 8-bit. Not used with the fast preview.",
+                );
+            ui.horizontal(|ui| {
+                ui.label("Update at most every");
+                ui.add(
+                    DragValue::new(&mut self.state.scope_interval_ms)
+                        .range(0..=5000)
+                        .suffix(" ms"),
+                )
+                .on_hover_text(
+                    "Keep showing the last scopes in between, 0 updates with every frame",
                 );
-                writeln!(&mut self.state.conversion_commands, "{template}").unwrap();
+            });
+            self.state.waveform_zoom.draw(ui);
+            self.state.waveform_graticule.draw_settings(ui);
+            let zoom = self.state.waveform_zoom;
+            let graticule = self.state.waveform_graticule;
+            let file_state = &self.state.active_file_state;
+            let full_range = file_state
+                .input_file
+                .range
+                .is_full(self.probed_color.color_range.as_deref());
+            let limits = file_state
+                .filter_options
+                .find::<FilterLimiter>()
+                .filter(|limiter| limiter.is_active)
+                .map(|limiter| limiter.luma_limits(full_range));
+            ui.horizontal(|ui| {
+                if let Some(waveform) = self.waveform.as_ref() {
+                    Plot::new("waveform_r")
+                        .width(350.)
+                        .height(400.)
+                        .x_axis_label("Red (column)")
+                        .y_axis_label("Level")
+                        .include_y(zoom.min - 10.)
+                        .include_y(zoom.max + 10.)
+                        .show(ui, |plot_ui| {
+                            graticule.draw(plot_ui, &zoom);
+                            draw_limits(plot_ui, limits);
+                            for (points, value) in
+                                waveform.get_plot_points(RgbComponent::Red, &zoom)
+                            {
+                                plot_ui.points(
+                                    Points::new(points)
+                                        .color(Color32::from_rgb(
+                                            (value * 255. * self.state.waveform_multiplier) as u8,
+                                            0,
+                                            0,
+                                        ))
+                                        .shape(MarkerShape::Circle),
+                                )
+                            }
+                        });
+                    Plot::new("waveform_g")
+                        .width(350.)
+                        .height(400.)
+                        .x_axis_label("Green (column)")
+                        .y_axis_label("Level")
+                        .include_y(zoom.min - 10.)
+                        .include_y(zoom.max + 10.)
+                        .show(ui, |plot_ui| {
+                            graticule.draw(plot_ui, &zoom);
+                            draw_limits(plot_ui, limits);
+                            for (points, value) in
+                                waveform.get_plot_points(RgbComponent::Green, &zoom)
+                            {
+                                plot_ui.points(
+                                    Points::new(points)
+                                        .color(Color32::from_rgb(
+                                            0,
+                                            (value * 255. * self.state.waveform_multiplier) as u8,
+                                            0,
+                                        ))
+                                        .shape(MarkerShape::Circle),
+                                )
+                            }
+                        });
+                    Plot::new("waveform_b")
+                        .width(350.)
+                        .height(400.)
+                        .x_axis_label("Blue (column)")
+                        .y_axis_label("Level")
+                        .include_y(zoom.min - 10.)
+                        .include_y(zoom.max + 10.)
+                        .show(ui, |plot_ui| {
+                            graticule.draw(plot_ui, &zoom);
+                            draw_limits(plot_ui, limits);
+                            for (points, value) in
+                                waveform.get_plot_points(RgbComponent::Blue, &zoom)
+                            {
+                                plot_ui.points(
+                                    Points::new(points)
+                                        .color(Color32::from_rgb(
+                                            0,
+                                            0,
+                                            (value * 255. * self.state.waveform_multiplier) as u8,
+                                        ))
+                                        .shape(MarkerShape::Circle),
+                                )
+                            }
+                        });
+                }
+            });
+        });
+        let saturation = egui::Window::new("saturation").show(ctx, |ui| {
+            self.draw_scope_notes(ui);
+            self.draw_save_scope_image_button(ui, "saturation");
+            if let Some(saturation_scope) = self.saturation_scope.as_ref() {
+                Plot::new("saturation_scope")
+                    .width(350.)
+                    .height(400.)
+                    .include_x(0.)
+                    .include_x(60.)
+                    .include_y(-10.)
+                    .include_y(110.)
+                    .x_axis_label("Saturation")
+                    .y_axis_label("Luma")
+                    .show(ui, |plot_ui| {
+                        for (points, value) in saturation_scope.get_plot_points() {
+                            let intensity = (64. + value * 191.) as u8;
+                            plot_ui.points(
+                                Points::new(points)
+                                    .color(Color32::from_gray(intensity))
+                                    .shape(MarkerShape::Circle),
+                            )
+                        }
+                        plot_ui.line(
+                            Line::new(SaturationScope::legal_boundary())
+                                .color(Color32::RED)
+                                .name("Legal"),
+                        );
+                    });
             }
-            ui.separator();
+        });
+        for (window, response) in [("waveforms", waveforms), ("saturation", saturation)] {
+            match response {
+                Some(response) => self
+                    .scope_window_rects
+                    .insert(window, response.response.rect),
+                None => self.scope_window_rects.remove(window),
+            };
+        }
+        self.request_scope_image(ctx);
+    }
+
+    fn draw_save_scope_image_button(&mut self, ui: &mut egui::Ui, window: &'static str) {
+        if ui
+            .button("Save image")
+            .on_hover_text("Save the window as a PNG image")
+            .clicked()
+        {
+            let mut dialog = FileDialog::save_file(None).default_filename(format!("{window}.png"));
+            dialog.open();
+            self.scope_image_dialog = Some((window, dialog));
+        }
+    }
+
+    /// Asks for a screenshot once the path of a scope image is picked. The dialog is closed by
+    /// then so it does not cover the scope.
+    fn request_scope_image(&mut self, ctx: &egui::Context) {
+        let Some((window, dialog)) = &mut self.scope_image_dialog else {
+            return;
+        };
+        if !dialog.show(ctx).selected() {
+            if !dialog.visible() {
+                self.scope_image_dialog = None;
+            }
+            return;
+        }
+        if let (Some(path), Some(rect)) = (dialog.path(), self.scope_window_rects.get(window)) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot(egui::UserData::new(
+                ScopeScreenshot {
+                    path: path.to_path_buf(),
+                    rect: *rect,
+                },
+            )));
+        }
+        self.scope_image_dialog = None;
+    }
+
+    fn save_scope_screenshots(&self, ctx: &egui::Context) {
+        let screenshots: Vec<_> = ctx.input(|i| {
+            i.raw
+                .events
+                .iter()
+                .filter_map(|event| match event {
+                    egui::Event::Screenshot {
+                        user_data, image, ..
+                    } => Some((user_data.data.clone()?, image.clone())),
+                    _ => None,
+                })
+                .collect()
+        });
+        for (data, image) in screenshots {
+            let Some(screenshot) = data.downcast_ref::<ScopeScreenshot>() else {
+                continue;
+            };
+            let img = crop_screenshot(&image, screenshot.rect, ctx.pixels_per_point());
+            match img.save(&screenshot.path) {
+                Ok(()) => log::info!("Saved scope image to {:?}", screenshot.path),
+                Err(e) => log::error!("Could not save scope image {:?}: {e}", screenshot.path),
+            }
+        }
+    }
+
+    fn draw_log_window(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Log").show(ctx, |ui| {
             ui.horizontal(|ui| {
-                if ui.button("Save file state").clicked() {
-                    self.state.file_history.insert(
-                        self.state.active_file_state.input_file.path.clone(),
-                        serde_json::to_string(&self.state.active_file_state).unwrap(),
-                    );
+                ComboBox::from_label("Level")
+                    .selected_text(self.state.log_level.to_string())
+                    .show_ui(ui, |ui| {
+                        for level in [log::Level::Error, log::Level::Warn, log::Level::Info] {
+                            ui.selectable_value(
+                                &mut self.state.log_level,
+                                level,
+                                level.to_string(),
+                            );
+                        }
+                    });
+                if ui.button("Clear").clicked() {
+                    LOG_BUFFER.clear();
                 }
-                if ui.button("Load file state").clicked() {
-                    if let Some(file_state_string) = self
-                        .state
-                        .file_history
-                        .get(&self.state.active_file_state.input_file.path)
+                ComboBox::from_label("ffmpeg")
+                    .selected_text(self.state.ffmpeg_loglevel.as_str())
+                    .show_ui(ui, |ui| {
+                        for level in FfmpegLogLevel::ALL {
+                            ui.selectable_value(
+                                &mut self.state.ffmpeg_loglevel,
+                                level,
+                                level.as_str(),
+                            );
+                        }
+                    })
+                    .response
+                    .on_hover_text("Verbosity of ffmpeg's output when extracting frames");
+            });
+            ui.separator();
+            ScrollArea::vertical()
+                .max_height(300.)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for entry in LOG_BUFFER
+                        .entries()
+                        .iter()
+                        .filter(|e| e.level <= self.state.log_level)
                     {
-                        if let Ok(file_state) = serde_json::from_str(file_state_string) {
-                            self.state.active_file_state = file_state;
+                        let color = match entry.level {
+                            log::Level::Error => Color32::RED,
+                            log::Level::Warn => Color32::YELLOW,
+                            _ => ui.visuals().text_color(),
+                        };
+                        ui.label(
+                            RichText::new(format!(
+                                "[{}] {}: {}",
+                                entry.level, entry.target, entry.message
+                            ))
+                            .color(color)
+                            .monospace(),
+                        );
+                    }
+                });
+        });
+    }
+
+    fn draw_debug_window(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Debug")
+            .default_open(false)
+            .show(ctx, |ui| {
+                if ui.button("Copy file state as JSON").clicked() {
+                    match serde_json::to_string_pretty(&self.state.active_file_state) {
+                        Ok(json) => ctx.copy_text(json),
+                        Err(e) => log::error!("Could not serialize file state: {e}"),
+                    }
+                }
+                if ui.button("Copy state as JSON").clicked() {
+                    match serde_json::to_string_pretty(&self.state) {
+                        Ok(json) => ctx.copy_text(json),
+                        Err(e) => log::error!("Could not serialize state: {e}"),
+                    }
+                }
+                ui.separator();
+                ui.checkbox(&mut self.state.remember_layout, "Remember window layout")
+                    .on_hover_text(
+                        "Window positions and sizes, panel sizes and collapsed sections",
+                    );
+                if ui.button("Reset window layout").clicked() {
+                    reset_layout(ctx);
+                }
+                ui.separator();
+                ui.label(format!(
+                    "ffmpeg version: {}",
+                    self.ffmpeg_capabilities
+                        .version
+                        .as_deref()
+                        .unwrap_or("unknown")
+                ));
+            });
+    }
+
+    fn draw_batch_grade(&mut self, ui: &mut egui::Ui) {
+        let batch = &mut self.state.batch_grade;
+        ui.add(TextEdit::singleline(&mut batch.input_dir).hint_text("Input folder"));
+        ui.add(TextEdit::singleline(&mut batch.output_dir).hint_text("Output folder"));
+        let is_running = batch.status.values().any(Option::is_none);
+        if ui
+            .add_enabled(!is_running, egui::Button::new("Grade all images"))
+            .clicked()
+        {
+            self.start_batch_grade();
+        }
+        let batch = &self.state.batch_grade;
+        if batch.status.is_empty() {
+            return;
+        }
+        let done = batch.status.values().filter(|s| s.is_some()).count();
+        ui.add(
+            egui::ProgressBar::new(done as f32 / batch.status.len() as f32)
+                .text(format!("{done}/{}", batch.status.len())),
+        );
+        ScrollArea::vertical()
+            .id_salt("batch_grade_status")
+            .max_height(150.)
+            .show(ui, |ui| {
+                for (input, status) in batch.status.iter() {
+                    let name = input.file_name().unwrap_or_default().to_string_lossy();
+                    match status {
+                        None => ui.label(format!("… {name}")),
+                        Some(Ok(())) => ui.colored_label(Color32::GREEN, format!("✔ {name}")),
+                        Some(Err(e)) => ui.colored_label(Color32::RED, format!("✖ {name}: {e}")),
+                    };
+                }
+            });
+    }
+
+    fn start_batch_grade(&mut self) {
+        let batch = &mut self.state.batch_grade;
+        let output_dir =
+            match batch_output_dir(Path::new(&batch.input_dir), Path::new(&batch.output_dir)) {
+                Ok(output_dir) => output_dir,
+                Err(e) => {
+                    log::error!("Could not start batch grade: {e}");
+                    self.error = Some(format!("Batch grade: {e}"));
+                    return;
+                }
+            };
+        let inputs = match batch_inputs(Path::new(&batch.input_dir)) {
+            Ok(inputs) => inputs,
+            Err(e) => {
+                log::error!("Could not read {}: {e}", batch.input_dir);
+                return;
+            }
+        };
+        if let Err(e) = std::fs::create_dir_all(&output_dir) {
+            log::error!("Could not create {output_dir:?}: {e}");
+            return;
+        }
+        let file_state_string = serde_json::to_string(&self.state.active_file_state).unwrap();
+        batch.status.clear();
+        for input in inputs {
+            let mut file_state: FileState = serde_json::from_str(&file_state_string).unwrap();
+            file_state.input_file.path = input.clone();
+            file_state.input_file.input_changed();
+            file_state.set_output(output_dir.join(input.file_name().unwrap()));
+            let args = file_state.to_ffmpeg_args(ArgPurpose::Still);
+            batch.status.insert(input.clone(), None);
+            self.request_tx
+                .send(Request::Convert { input, args })
+                .unwrap();
+        }
+        log::info!("Grading {} images", batch.status.len());
+    }
+
+    fn draw_save_frame(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        ui.checkbox(
+            &mut self.state.save_frame.with_manipulation,
+            "Include preview manipulation",
+        );
+        ComboBox::from_label("TIFF bit depth")
+            .selected_text(format!("{} bit", self.state.save_frame.tiff_bit_depth))
+            .show_ui(ui, |ui| {
+                for bit_depth in [8, 16] {
+                    ui.selectable_value(
+                        &mut self.state.save_frame.tiff_bit_depth,
+                        bit_depth,
+                        format!("{bit_depth} bit"),
+                    );
+                }
+            });
+        if ui
+            .add_enabled(
+                self.displayed_frame.is_some(),
+                egui::Button::new("Save frame"),
+            )
+            .on_hover_text("PNG or TIFF, depending on the file extension")
+            .clicked()
+        {
+            let mut dialog = FileDialog::save_file(None);
+            dialog.open();
+            self.save_frame_dialog = Some(dialog);
+        }
+        if let Some(dialog) = &mut self.save_frame_dialog {
+            if dialog.show(ctx).selected() {
+                if let Some(path) = dialog.path().map(Path::to_path_buf) {
+                    self.save_frame(&path);
+                }
+                self.save_frame_dialog = None;
+            }
+        }
+    }
+
+    fn save_frame(&self, path: &Path) {
+        let Some(frame) = self.displayed_frame.as_ref() else {
+            return;
+        };
+        let mut frame = frame.clone();
+        if self.state.save_frame.with_manipulation {
+            for manipulation in &self.state.preview_manipulations {
+                manipulation.apply(&mut frame);
+            }
+        }
+        let is_tiff = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| ["tif", "tiff"].contains(&e.to_lowercase().as_str()));
+        let frame = DynamicImage::ImageRgba8(frame);
+        let result = if is_tiff && self.state.save_frame.tiff_bit_depth == 16 {
+            DynamicImage::ImageRgba16(frame.into_rgba16()).save(path)
+        } else {
+            frame.save(path)
+        };
+        match result {
+            Ok(()) => log::info!("Saved frame to {path:?}"),
+            Err(e) => log::error!("Could not save frame to {path:?}: {e}"),
+        }
+    }
+
+    fn draw_scope_notes(&self, ui: &mut egui::Ui) {
+        if self.scopes_are_approximate {
+            ui.colored_label(
+                Color32::LIGHT_BLUE,
+                "Live approximation of exposure/eq, create a preview for exact scopes",
+            );
+        }
+        if self.state.fast_preview.is_active {
+            ui.colored_label(
+                Color32::YELLOW,
+                "Fast preview: scopes are based on a lossy, downscaled frame",
+            );
+        }
+    }
+
+    fn draw_lut_comparison_window(&mut self, ctx: &egui::Context) {
+        egui::Window::new("LUT comparison")
+            .default_open(false)
+            .show(ctx, |ui| {
+                let mut remove = None;
+                for (i, lut) in self.state.lut_comparison.luts.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let marker = if i == self.state.lut_comparison.current {
+                            "▶"
                         } else {
-                            log::error!("Could not parse state from JSON!");
+                            " "
+                        };
+                        ui.label(format!("{marker} {lut}"));
+                        if ui.small_button("Remove").clicked() {
+                            remove = Some(i);
                         }
-                    } else {
-                        log::warn!("No state available!");
-                    }
+                    });
                 }
+                if let Some(i) = remove {
+                    self.state.lut_comparison.luts.remove(i);
+                }
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.state.lut_comparison.new_lut);
+                    if ui.button("Add").clicked() && !self.state.lut_comparison.new_lut.is_empty() {
+                        let lut = std::mem::take(&mut self.state.lut_comparison.new_lut);
+                        self.state.lut_comparison.luts.push(lut);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Previous").clicked() {
+                        self.cycle_lut(-1);
+                    }
+                    if ui.button("Next (L)").clicked() {
+                        self.cycle_lut(1);
+                    }
+                });
             });
-        });
+
+        let no_focus = ctx.memory(|m| m.focused().is_none());
+        if no_focus && ctx.input(|i| i.key_pressed(Key::L)) {
+            self.cycle_lut(if ctx.input(|i| i.modifiers.shift) {
+                -1
+            } else {
+                1
+            });
+        }
     }
 
-    fn draw_bottom_panel(&mut self, ctx: &egui::Context) {
-        TopBottomPanel::bottom("conversion_commands")
-            .resizable(true)
-            .max_height(500.)
+    fn cycle_lut(&mut self, step: isize) {
+        let comparison = &mut self.state.lut_comparison;
+        if comparison.luts.is_empty() {
+            return;
+        }
+        let len = comparison.luts.len() as isize;
+        comparison.current = (comparison.current as isize + step).rem_euclid(len) as usize;
+        let file = comparison.luts[comparison.current].clone();
+
+        let Some(lut) = self
+            .state
+            .active_file_state
+            .filter_options
+            .find_mut::<FilterLut>()
+        else {
+            log::warn!("No LUT filter in the filter chain");
+            return;
+        };
+        log::info!("Comparing LUT {file}");
+        lut.file = file;
+        lut.is_active = true;
+        self.request_preview();
+    }
+
+    fn draw_filter_palette(&mut self, ctx: &egui::Context) {
+        if ctx.input(|i| i.modifiers.command && i.key_pressed(Key::K)) {
+            self.filter_palette = Some(Default::default());
+        }
+        let Some(palette) = &mut self.filter_palette else {
+            return;
+        };
+
+        let matches = palette.matches();
+        palette.selected = palette.selected.min(matches.len().saturating_sub(1));
+        let mut close = ctx.input(|i| i.key_pressed(Key::Escape));
+        let mut chosen = None;
+        if ctx.input(|i| i.key_pressed(Key::ArrowDown)) {
+            palette.selected = (palette.selected + 1).min(matches.len().saturating_sub(1));
+        }
+        if ctx.input(|i| i.key_pressed(Key::ArrowUp)) {
+            palette.selected = palette.selected.saturating_sub(1);
+        }
+
+        egui::Window::new("Insert filter")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, Vec2::new(0., 50.))
             .show(ctx, |ui| {
-                ScrollArea::new([false, true]).show(ui, |ui| {
-                    let available_size = ui.available_size();
-                    ui.add_sized(
-                        Vec2::new(available_size.x, available_size.y - 40.),
-                        TextEdit::multiline(&mut self.state.conversion_commands),
+                let response =
+                    ui.add(TextEdit::singleline(&mut palette.query).hint_text("Search filters…"));
+                response.request_focus();
+                if response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+                    chosen = matches.get(palette.selected).copied();
+                }
+                for (i, &index) in matches.iter().enumerate() {
+                    let (name, _) = FILTER_REGISTRY[index];
+                    if ui.selectable_label(i == palette.selected, name).clicked() {
+                        chosen = Some(index);
+                    }
+                }
+            });
+
+        if let Some(index) = chosen {
+            let (name, new) = FILTER_REGISTRY[index];
+            log::info!("Inserting filter {name}");
+            self.state
+                .active_file_state
+                .filter_options
+                .filters
+                .push(new());
+            self.last_change = Some(Instant::now());
+            close = true;
+        }
+        if close {
+            self.filter_palette = None;
+        }
+    }
+
+    fn draw_central_panel(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let mut reset = ui
+                    .selectable_value(&mut self.state.preview_zoom, PreviewZoom::Fit, "Fit")
+                    .clicked();
+                reset |= ui
+                    .selectable_value(
+                        &mut self.state.preview_zoom,
+                        PreviewZoom::ActualPixels,
+                        "100%",
+                    )
+                    .clicked();
+                if reset {
+                    self.preview_view = Default::default();
+                }
+                ui.label(format!("Zoom: {:.0}%", self.preview_view.zoom * 100.))
+                    .on_hover_text(
+                        "Ctrl+wheel to zoom, wheel or middle-drag to pan, double-click to reset",
                     );
-                    ui.separator();
-                    match &self.error {
-                        Some(error) => {
-                            ui.label(RichText::new(format!("Error: {error}")).color(Color32::RED))
-                        }
-                        None => ui.label(RichText::new("OK").color(Color32::GREEN)),
-                    };
+                if let Some([x, y]) = self.hovered_pixel {
+                    ui.label(format!("x: {x}, y: {y}"));
+                }
+                ui.toggle_value(&mut self.drawing_mask, "Draw mask")
+                    .on_hover_text("Drag on the preview to place the mask");
+                ui.separator();
+                self.draw_compare(ctx, ui);
+                if let Some(max_side) = self.preview_downscaled {
+                    ui.colored_label(ui.visuals().warn_fg_color, "Downscaled")
+                        .on_hover_text(format!(
+                            "The frame is larger than the {max_side} px textures the GPU \
+                             supports, so it is shown downscaled. Scopes and pixel positions \
+                             use the full frame."
+                        ));
+                }
+            });
+            let Some(img) = self.image_texture.as_ref() else {
+                ui.centered_and_justified(|ui| {
+                    ui.weak("No preview");
                 });
+                return;
+            };
+            // The texture may be downscaled, positions refer to the frame
+            let [width, height] = self.displayed_frame.as_ref().map_or(img.size(), |frame| {
+                [frame.width() as usize, frame.height() as usize]
+            });
+            let sar = self
+                .state
+                .active_file_state
+                .filter_options
+                .sample_aspect_ratio(width as u32, height as u32);
+            let size = self.state.preview_zoom.image_size(
+                Vec2::new(width as f32 * sar, height as f32),
+                ui.available_size(),
+                ctx.pixels_per_point(),
+            );
+            let (panel, response) =
+                ui.allocate_exact_size(ui.available_size(), Sense::click_and_drag());
+            if response.double_clicked() {
+                self.preview_view = Default::default();
+            }
+            if response.dragged_by(PointerButton::Middle) {
+                self.preview_view.offset += response.drag_delta();
+            }
+            if let Some(pointer) = response.hover_pos() {
+                let (zoom, scroll) = ctx.input(|i| (i.zoom_delta(), i.smooth_scroll_delta));
+                if zoom != 1. {
+                    self.preview_view.zoom_around(zoom, pointer - panel.min);
+                } else {
+                    self.preview_view.offset += scroll;
+                }
+            }
+            let rect = self.preview_view.image_rect(panel, size);
+            if self.image_has_alpha {
+                paint_checkerboard(&ui.painter_at(panel), rect);
+            }
+            ui.painter_at(panel).image(
+                img.id(),
+                rect,
+                Rect::from_min_max(Pos2::ZERO, Pos2::new(1., 1.)),
+                Color32::WHITE,
+            );
+            if self.state.compare.mode == CompareMode::Wipe && self.aligned_original().is_some() {
+                let x = rect.left() + rect.width() * self.state.compare.position;
+                ui.painter_at(panel).vline(
+                    x,
+                    rect.y_range(),
+                    egui::Stroke::new(1., Color32::WHITE),
+                );
+            }
+            self.hovered_pixel = response.hover_pos().and_then(|pos| {
+                PreviewView::to_image_pixel(rect, pos, [width as u32, height as u32])
             });
+            self.draw_mask_overlay(ui, panel, rect, &response);
+        });
     }
 
-    fn draw_windows(&mut self, ctx: &egui::Context) {
-        egui::Window::new("waveforms").show(ctx, |ui| {
-            ui.add(Slider::new(&mut self.state.waveform_multiplier, 1.0..=100.).text("Multiplier"));
-            ui.horizontal(|ui| {
-                if let Some(waveform) = self.waveform.as_ref() {
-                    Plot::new("waveform_r")
-                        .width(350.)
-                        .height(400.)
-                        .include_y(-10.)
-                        .include_y(110.)
-                        .show(ui, |plot_ui| {
-                            for (points, value) in waveform.get_plot_points(RgbComponent::Red) {
-                                plot_ui.points(
-                                    Points::new(points)
-                                        .color(Color32::from_rgb(
-                                            (value * 255. * self.state.waveform_multiplier) as u8,
-                                            0,
-                                            0,
-                                        ))
-                                        .shape(MarkerShape::Circle),
-                                )
-                            }
-                        });
-                    Plot::new("waveform_g")
-                        .width(350.)
-                        .height(400.)
-                        .include_y(-10.)
-                        .include_y(110.)
-                        .show(ui, |plot_ui| {
-                            for (points, value) in waveform.get_plot_points(RgbComponent::Green) {
-                                plot_ui.points(
-                                    Points::new(points)
-                                        .color(Color32::from_rgb(
-                                            0,
-                                            (value * 255. * self.state.waveform_multiplier) as u8,
-                                            0,
-                                        ))
-                                        .shape(MarkerShape::Circle),
-                                )
+    fn draw_compare(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        let compare = self.state.compare;
+        ComboBox::from_label("Compare")
+            .selected_text(self.state.compare.mode.to_string())
+            .show_ui(ui, |ui| {
+                for mode in [CompareMode::Off, CompareMode::Wipe, CompareMode::Crossfade] {
+                    ui.selectable_value(&mut self.state.compare.mode, mode, mode.to_string());
+                }
+            })
+            .response
+            .on_hover_text("Show the frame without any filter next to or blended with the grade");
+        if self.state.compare.mode != CompareMode::Off {
+            ui.add(
+                Slider::new(&mut self.state.compare.position, 0.0..=1.0)
+                    .custom_formatter(|value, _| format!("{:.0}%", value * 100.))
+                    .text("Graded"),
+            );
+            let seek = |seek: Option<FrameSeek>| seek.map_or("-".to_string(), |s| s.to_string());
+            ui.label(format!("Graded frame: {}", seek(self.displayed_seek)));
+            ui.label(format!("Original frame: {}", seek(self.original_seek)));
+            if self.original_frame.is_some() && self.aligned_original().is_none() {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    "Waiting for frames from the same position",
+                );
+            }
+        }
+        if compare.mode == CompareMode::Off && self.state.compare.mode != CompareMode::Off {
+            self.request_original();
+        }
+        if compare != self.state.compare {
+            self.upload_preview(ctx);
+        }
+    }
+
+    fn draw_mask_overlay(
+        &mut self,
+        ui: &egui::Ui,
+        panel: Rect,
+        rect: Rect,
+        response: &egui::Response,
+    ) {
+        let mask = &mut self.state.active_file_state.filter_options.mask;
+        if self.drawing_mask {
+            let pointer = response
+                .interact_pointer_pos()
+                .map(|pos| PreviewView::to_relative(rect, pos));
+            if response.drag_started_by(PointerButton::Primary) {
+                self.mask_drag_start = pointer;
+            }
+            if let (Some(start), Some(end)) = (self.mask_drag_start, pointer) {
+                if response.dragged_by(PointerButton::Primary) {
+                    mask.is_active = true;
+                    mask.center = [(start.x + end.x) / 2., (start.y + end.y) / 2.];
+                    mask.size = [(end.x - start.x).abs(), (end.y - start.y).abs()];
+                }
+            }
+            if response.drag_stopped()
+                && self.mask_drag_start.take().is_some()
+                && self.state.auto_preview
+            {
+                self.last_change = Some(Instant::now());
+            }
+        }
+        if !mask.is_active {
+            return;
+        }
+        let center = rect.min + Vec2::from(mask.center) * rect.size();
+        let size = Vec2::from(mask.size) * rect.size();
+        let stroke = egui::Stroke::new(1.5, Color32::YELLOW);
+        let painter = ui.painter_at(panel);
+        match mask.shape {
+            MaskShape::Ellipse => {
+                painter.add(egui::Shape::ellipse_stroke(center, size / 2., stroke));
+            }
+            MaskShape::Rectangle => {
+                painter.rect_stroke(Rect::from_center_size(center, size), 0., stroke);
+            }
+        }
+    }
+
+    fn handle_events(&mut self, ctx: &egui::Context) {
+        while let Ok(response) = self.response_rx.try_recv() {
+            match response {
+                Response::Image { id, image, precise } => {
+                    if id == self.latest_original_id {
+                        self.original_frame = Some(image);
+                        self.upload_preview(ctx);
+                        continue;
+                    }
+                    if id < self.latest_preview_id {
+                        log::debug!("Ignoring stale preview {id}");
+                        continue;
+                    }
+                    if id == self.latest_waveform_id {
+                        let image = texture_frame(image, ctx.input(|i| i.max_texture_side));
+                        let pixels = image.as_flat_samples();
+                        let image = ColorImage::from_rgba_unmultiplied(
+                            [image.width() as _, image.height() as _],
+                            pixels.as_slice(),
+                        );
+                        self.waveform_texture =
+                            Some(ctx.load_texture("waveform", image, Default::default()));
+                        self.waiting_for_waveform = false;
+                        continue;
+                    }
+                    self.error = None;
+                    self.waiting_for_image = false;
+                    let (image, precise) = match precise {
+                        Some(precise) => {
+                            let levels = precise_levels(&precise);
+                            (levels_to_rgba8(&levels), Some(levels))
+                        }
+                        None => (image, None),
+                    };
+                    self.live_base = Some((image.clone(), self.requested_tone));
+                    self.displayed_seek = self.requested_seek;
+                    self.show_image(ctx, image, precise);
+                }
+                Response::Prefetched { input, result } => {
+                    self.prefetch_pending.remove(&input);
+                    match result {
+                        Ok(image) => {
+                            self.prefetch_cache.insert(input, image);
+                        }
+                        Err(e) => log::warn!("Could not prefetch {input:?}: {e}"),
+                    }
+                }
+                Response::Probed { input, result } => {
+                    if input != self.state.active_file_state.input_file.path {
+                        continue;
+                    }
+                    match result {
+                        Ok(info) => {
+                            log::info!(
+                                "Probed {input:?}: {:?}, {:?}, duration {:?}",
+                                info.color,
+                                info.video_streams,
+                                info.duration
+                            );
+                            self.probe_json = Some(info.raw);
+                            self.probed_color = info.color.clone();
+                            if let Some(colorspace) = self
+                                .state
+                                .active_file_state
+                                .filter_options
+                                .find_mut::<FilterColorspace>()
+                            {
+                                colorspace.detected = Some(info.color);
                             }
-                        });
-                    Plot::new("waveform_b")
-                        .width(350.)
-                        .height(400.)
-                        .include_y(-10.)
-                        .include_y(110.)
-                        .show(ui, |plot_ui| {
-                            for (points, value) in waveform.get_plot_points(RgbComponent::Blue) {
-                                plot_ui.points(
-                                    Points::new(points)
-                                        .color(Color32::from_rgb(
-                                            0,
-                                            0,
-                                            (value * 255. * self.state.waveform_multiplier) as u8,
-                                        ))
-                                        .shape(MarkerShape::Circle),
-                                )
+                            self.state
+                                .active_file_state
+                                .input_file
+                                .set_video_streams(info.video_streams);
+                            self.input_duration = info.duration;
+                        }
+                        Err(e) => log::warn!("Could not probe {input:?}: {e}"),
+                    }
+                }
+                Response::ConvertProgress { input, seconds } => {
+                    if let Some(conversion) = &mut self.conversion {
+                        if conversion.input == input {
+                            conversion.seconds = seconds;
+                        }
+                    }
+                }
+                Response::Converted { input, result } => {
+                    if let Err(e) = &result {
+                        log::error!("Could not convert {input:?}: {e}");
+                    }
+                    if let Some(conversion) = &mut self.conversion {
+                        if conversion.input == input && conversion.result.is_none() {
+                            if result.is_ok() {
+                                log::info!("Converted {input:?} to {:?}", conversion.output);
                             }
-                        });
+                            conversion.result = Some(result.clone());
+                        }
+                    }
+                    if let Some(status) = self.state.batch_grade.status.get_mut(&input) {
+                        *status = Some(result);
+                    }
                 }
-            });
-        });
+                Response::Thumbnail { key, result } => {
+                    self.thumbnails.pending.remove(&key);
+                    match result {
+                        Ok(image) => {
+                            let pixels = image.as_flat_samples();
+                            let image = ColorImage::from_rgba_unmultiplied(
+                                [image.width() as _, image.height() as _],
+                                pixels.as_slice(),
+                            );
+                            let texture = ctx.load_texture(&key, image, Default::default());
+                            self.thumbnails.insert(key, texture);
+                        }
+                        Err(e) => log::warn!("Could not extract thumbnail: {e}"),
+                    }
+                }
+                Response::Capabilities(capabilities) => {
+                    log::info!(
+                        "Detected ffmpeg version {}",
+                        capabilities.version.as_deref().unwrap_or("unknown")
+                    );
+                    self.ffmpeg_capabilities = Arc::new(capabilities);
+                    self.state.active_file_state.filter_options.capabilities =
+                        self.ffmpeg_capabilities.clone();
+                    self.update_chain_warnings();
+                }
+                Response::Error { id, error } => {
+                    if let Some(id) = id {
+                        if id == self.latest_waveform_id {
+                            self.waiting_for_waveform = false;
+                        } else if id == self.latest_preview_id {
+                            self.waiting_for_image = false;
+                        } else if id != self.latest_original_id {
+                            log::debug!("Ignoring error of stale job {id}: {error}");
+                            continue;
+                        }
+                    }
+                    self.error = Some(error);
+                }
+            }
+        }
+    }
+}
+
+impl App for ColorustApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.state.evict_history();
+        eframe::set_value(storage, eframe::APP_KEY, &self.state);
+    }
+
+    fn persist_egui_memory(&self) -> bool {
+        self.state.remember_layout
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if let Some(path) = recovery_path() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if self.waiting_for_image || self.waiting_for_waveform {
+            ctx.request_repaint_after(Duration::from_millis(50));
+        }
+
+        self.handle_events(ctx);
+        self.update_pending_scopes(ctx);
+        self.save_scope_screenshots(ctx);
+        self.handle_input_change(ctx);
+
+        self.draw_side_panel(ctx);
+        self.draw_bottom_panel(ctx);
+        self.draw_central_panel(ctx);
+        self.draw_windows(ctx);
+        self.draw_log_window(ctx);
+        self.draw_debug_window(ctx);
+        self.draw_lut_comparison_window(ctx);
+        self.draw_filter_palette(ctx);
+
+        self.draw_recovery_window(ctx);
+
+        self.schedule_auto_preview(ctx);
+        self.autosave();
+    }
+}
+
+#[typetag::serde(tag = "type")]
+pub trait GuiElement {
+    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> egui::Response;
+    fn name(&self) -> &'static str;
+    fn is_active(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug)]
+enum RgbComponent {
+    Red,
+    Green,
+    Blue,
+}
+
+#[derive(Debug, Default)]
+struct FilterPalette {
+    query: String,
+    selected: usize,
+}
+
+impl FilterPalette {
+    /// Indices into [`FILTER_REGISTRY`], best match first.
+    fn matches(&self) -> Vec<usize> {
+        let mut matches: Vec<_> = FILTER_REGISTRY
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (name, _))| fuzzy_score(&self.query, name).map(|score| (score, i)))
+            .collect();
+        matches.sort_by_key(|&(score, i)| (std::cmp::Reverse(score), i));
+        matches.into_iter().map(|(_, i)| i).collect()
+    }
+}
+
+/// Scores `candidate` if all characters of `query` appear in it in order, ignoring case.
+/// Consecutive characters and matches at word starts score higher.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let mut score = 0;
+    let mut candidate_chars = candidate
+        .chars()
+        .map(|c| c.to_ascii_lowercase())
+        .enumerate();
+    let mut last_match: Option<usize> = None;
+    let word_starts: Vec<_> = candidate
+        .char_indices()
+        .scan(' ', |prev, (_, c)| {
+            let is_start = !prev.is_alphanumeric();
+            *prev = c;
+            Some(is_start)
+        })
+        .collect();
+
+    for q in query.chars().filter(|c| !c.is_whitespace()) {
+        let q = q.to_ascii_lowercase();
+        let (i, _) = candidate_chars.find(|&(_, c)| c == q)?;
+        score += 1;
+        if last_match.is_some_and(|last| last + 1 == i) {
+            score += 2;
+        }
+        if word_starts[i] {
+            score += 3;
+        }
+        last_match = Some(i);
+    }
+    Some(score)
+}
+
+/// A scope window to save as `path` once the requested screenshot arrives.
+struct ScopeScreenshot {
+    path: PathBuf,
+    rect: Rect,
+}
+
+/// Cuts `rect`, in points, out of a screenshot. Parts outside of the screen are left out.
+fn crop_screenshot(image: &ColorImage, rect: Rect, pixels_per_point: f32) -> RgbaImage {
+    let screen = Rect::from_min_size(
+        Pos2::ZERO,
+        Vec2::new(image.width() as f32, image.height() as f32) / pixels_per_point,
+    );
+    let rect = rect.intersect(screen);
+    if !rect.is_positive() {
+        return RgbaImage::new(0, 0);
+    }
+    let region = image.region(&rect, Some(pixels_per_point));
+    let pixels = region
+        .pixels
+        .iter()
+        .flat_map(|pixel| pixel.to_srgba_unmultiplied())
+        .collect();
+    RgbaImage::from_raw(region.width() as u32, region.height() as u32, pixels).unwrap()
+}
+
+fn has_transparency(img: &RgbaImage) -> bool {
+    img.pixels().any(|p| p[3] < u8::MAX)
+}
+
+/// Paints the checkerboard image editors show behind transparent pixels. Only the squares
+/// within the painter's clip rect are painted.
+fn paint_checkerboard(painter: &egui::Painter, rect: Rect) {
+    const SQUARE: f32 = 8.;
+    let visible = rect.intersect(painter.clip_rect());
+    if !visible.is_positive() {
+        return;
     }
+    painter.rect_filled(visible, 0., Color32::from_gray(204));
+    let first = ((visible.min - rect.min) / SQUARE).floor();
+    let last = ((visible.max - rect.min) / SQUARE).ceil();
+    for row in first.y as usize..last.y as usize {
+        for column in first.x as usize..last.x as usize {
+            if (row + column) % 2 == 0 {
+                continue;
+            }
+            let min = rect.min + Vec2::new(column as f32, row as f32) * SQUARE;
+            let square = Rect::from_min_size(min, Vec2::splat(SQUARE)).intersect(visible);
+            painter.rect_filled(square, 0., Color32::from_gray(153));
+        }
+    }
+}
 
-    fn draw_central_panel(&mut self, ctx: &egui::Context) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            if let Some(img) = self.image_texture.as_ref() {
-                ui.image(img);
-            }
-        });
+/// Marks the range a limiter clamps to, so clipped areas stand out on the waveform.
+fn draw_limits(plot_ui: &mut egui_plot::PlotUi, limits: Option<[f64; 2]>) {
+    for limit in limits.into_iter().flatten() {
+        plot_ui.hline(HLine::new(limit).color(Color32::YELLOW).width(1.));
     }
+}
 
-    fn handle_events(&mut self, ctx: &egui::Context) {
-        if let Ok(response) = self.response_rx.try_recv() {
-            match response {
-                Response::Image(mut img) => {
-                    self.error = None;
-                    self.waveform = Some(Waveform::from_image(&img));
-                    self.waiting_for_image = false;
-                    self.state.preview_manipulation.apply(&mut img);
-                    let pixels = img.as_flat_samples();
-                    let img = ColorImage::from_rgba_unmultiplied(
-                        [img.width() as _, img.height() as _],
-                        pixels.as_slice(),
-                    );
-                    self.image_texture = Some(ctx.load_texture("img", img, Default::default()));
-                }
-                Response::Error(error) => self.error = Some(error),
-            }
+/// Levels in IRE that the waveform graticule always marks.
+const GRATICULE_LEVELS: [f64; 4] = [0., 7.5, 50., 100.];
+
+/// Labeled reference lines in the waveforms.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+struct Graticule {
+    is_active: bool,
+    show_broadcast_limits: bool,
+    /// Lower and upper limit in IRE.
+    broadcast_limits: [f64; 2],
+}
+
+impl Default for Graticule {
+    fn default() -> Self {
+        Self {
+            is_active: true,
+            show_broadcast_limits: false,
+            // EBU R 103
+            broadcast_limits: [-1., 103.],
         }
     }
 }
 
-impl App for ColorustApp {
-    fn save(&mut self, storage: &mut dyn eframe::Storage) {
-        eframe::set_value(storage, eframe::APP_KEY, &self.state);
+impl Graticule {
+    /// Lines to draw with their labels, limited to what is visible at `zoom`.
+    fn lines(&self, zoom: &WaveformZoom) -> Vec<(f64, String, Color32)> {
+        if !self.is_active {
+            return vec![];
+        }
+        let mut lines: Vec<_> = GRATICULE_LEVELS
+            .iter()
+            .map(|&level| (level, format!("{level} IRE"), Color32::GRAY))
+            .collect();
+        if self.show_broadcast_limits {
+            let [min, max] = self.broadcast_limits;
+            lines.push((min, format!("{min} IRE min"), Color32::LIGHT_RED));
+            lines.push((max, format!("{max} IRE max"), Color32::LIGHT_RED));
+        }
+        lines.retain(|(level, _, _)| (zoom.min - 10.0..=zoom.max + 10.).contains(level));
+        lines
     }
 
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if self.waiting_for_image {
-            ctx.request_repaint_after(Duration::from_millis(50));
+    fn draw(&self, plot_ui: &mut egui_plot::PlotUi, zoom: &WaveformZoom) {
+        for (level, label, color) in self.lines(zoom) {
+            plot_ui.hline(HLine::new(level).color(color.gamma_multiply(0.5)).width(1.));
+            plot_ui.text(
+                Text::new(PlotPoint::new(0., level), label)
+                    .color(color)
+                    .anchor(Align2::LEFT_BOTTOM),
+            );
         }
+    }
 
-        self.handle_events(ctx);
+    fn draw_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.is_active, "Graticule")
+                .on_hover_text(format!(
+                    "Lines at {} IRE",
+                    GRATICULE_LEVELS.map(|level| level.to_string()).join(", ")
+                ));
+            ui.add_enabled_ui(self.is_active, |ui| {
+                ui.checkbox(&mut self.show_broadcast_limits, "Broadcast limits");
+                let [min, max] = &mut self.broadcast_limits;
+                ui.add_enabled(
+                    self.show_broadcast_limits,
+                    DragValue::new(min)
+                        .range(-10.0..=*max)
+                        .speed(0.1)
+                        .suffix(" IRE"),
+                );
+                ui.add_enabled(
+                    self.show_broadcast_limits,
+                    DragValue::new(max)
+                        .range(*min..=110.)
+                        .speed(0.1)
+                        .suffix(" IRE"),
+                );
+            });
+        });
+    }
+}
 
-        self.draw_side_panel(ctx);
-        self.draw_bottom_panel(ctx);
-        self.draw_central_panel(ctx);
-        self.draw_windows(ctx);
+#[derive(Debug, Copy, Clone, serde::Deserialize, serde::Serialize)]
+struct WaveformZoom {
+    min: f64,
+    max: f64,
+}
+
+impl Default for WaveformZoom {
+    fn default() -> Self {
+        Self { min: 0., max: 100. }
     }
 }
 
-#[typetag::serde(tag = "type")]
-pub trait GuiElement {
-    fn draw(&mut self, ctx: &egui::Context, ui: &mut egui::Ui);
-    fn name(&self) -> &'static str;
-    fn is_active(&self) -> bool {
-        true
+impl WaveformZoom {
+    fn is_full_range(&self) -> bool {
+        self.min <= 0. && self.max >= 100.
+    }
+
+    fn draw(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Zoom");
+            ui.add(
+                DragValue::new(&mut self.min)
+                    .range(0.0..=self.max - 1.)
+                    .suffix(" IRE"),
+            );
+            ui.label("to");
+            ui.add(
+                DragValue::new(&mut self.max)
+                    .range(self.min + 1.0..=100.)
+                    .suffix(" IRE"),
+            );
+            if ui.button("Reset").clicked() {
+                *self = Self::default();
+            }
+        });
     }
 }
 
-#[derive(Debug)]
-enum RgbComponent {
-    Red,
-    Green,
-    Blue,
+/// Rough client-side model of the exposure and eq filters applied to all channels alike.
+///
+/// It ignores filter order and everything else in the chain. It only exists to make the scopes
+/// follow slider drags instantly and is replaced by the real frame on the next preview.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ToneApproximation {
+    exposure: f32,
+    black: f32,
+    contrast: f32,
+    brightness: f32,
+    gamma: f32,
+}
+
+impl Default for ToneApproximation {
+    fn default() -> Self {
+        Self {
+            exposure: 0.,
+            black: 0.,
+            contrast: 1.,
+            brightness: 0.,
+            gamma: 1.,
+        }
+    }
+}
+
+impl ToneApproximation {
+    fn from_filters(filter_options: &FilterOption) -> Self {
+        let mut tone = Self::default();
+        if let Some(exposure) = filter_options
+            .find::<FilterExposure>()
+            .filter(|f| f.is_active)
+        {
+            tone.exposure = exposure.exposure;
+            tone.black = exposure.black;
+        }
+        if let Some(eq) = filter_options.find::<FilterEq>().filter(|f| f.is_active) {
+            tone.contrast = eq.contrast;
+            tone.brightness = eq.brightness;
+            tone.gamma = eq.gamma;
+        }
+        tone
+    }
+
+    fn apply(&self, v: f32) -> f32 {
+        let v = (v - self.black) / (2f32.powf(-self.exposure) - self.black);
+        let v = (v - 0.5) * self.contrast + 0.5 + self.brightness;
+        v.clamp(0., 1.).powf(1. / self.gamma)
+    }
+
+    fn invert(&self, v: f32) -> f32 {
+        let v = v.powf(self.gamma);
+        let v = if self.contrast.abs() < 1e-3 {
+            v
+        } else {
+            (v - 0.5 - self.brightness) / self.contrast + 0.5
+        };
+        (v * (2f32.powf(-self.exposure) - self.black) + self.black).clamp(0., 1.)
+    }
+
+    /// Maps values produced with `self` to values as they would be produced with `other`.
+    fn lut_to(&self, other: &Self) -> [u8; 256] {
+        std::array::from_fn(|v| {
+            let v = other.apply(self.invert(v as f32 / 255.));
+            (v * 255.).round() as u8
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -545,7 +4315,13 @@ struct Waveform {
 }
 
 impl Waveform {
-    fn from_image(img: &RgbaImage) -> Self {
+    /// Values are binned in steps of 0.01 %, so frames with more than 8 bits per channel keep
+    /// their finer detail. Levels outside of black and white are kept.
+    fn from_image<P: Pixel>(
+        img: &ImageBuffer<P, Vec<P::Subpixel>>,
+        level: impl Fn(P::Subpixel) -> f64,
+    ) -> Self {
+        let bin = |v: P::Subpixel| (level(v) * 10000.).round() as i32;
         let width = img.width();
         let height = img.height();
 
@@ -560,15 +4336,9 @@ impl Waveform {
 
             for y in 0..height {
                 let pixel = img.get_pixel(x, y);
-                *row_r
-                    .entry(pixel.0[0] as u32 * 10000 / u8::MAX as u32)
-                    .or_default() += 1;
-                *row_g
-                    .entry(pixel.0[1] as u32 * 10000 / u8::MAX as u32)
-                    .or_default() += 1;
-                *row_b
-                    .entry(pixel.0[2] as u32 * 10000 / u8::MAX as u32)
-                    .or_default() += 1;
+                *row_r.entry(bin(pixel.channels()[0])).or_default() += 1;
+                *row_g.entry(bin(pixel.channels()[1])).or_default() += 1;
+                *row_b.entry(bin(pixel.channels()[2])).or_default() += 1;
             }
 
             values_r.push(row_r);
@@ -584,7 +4354,7 @@ impl Waveform {
     }
 
     fn values_to_plot_points(
-        values: Vec<HashMap<u32, u64>>,
+        values: Vec<HashMap<i32, u64>>,
         max_value: u64,
     ) -> Vec<(Vec<[f64; 2]>, f64)> {
         let mut points = Vec::new();
@@ -606,16 +4376,1061 @@ impl Waveform {
         points
     }
 
-    fn get_plot_points(&self, component: RgbComponent) -> Vec<(PlotPoints, f64)> {
+    /// Only returns points inside `zoom`. If the range is narrowed, densities are rescaled so
+    /// that the densest bin inside the range is as bright as the densest bin overall.
+    fn get_plot_points(
+        &self,
+        component: RgbComponent,
+        zoom: &WaveformZoom,
+    ) -> Vec<(PlotPoints, f64)> {
         let values = match component {
             RgbComponent::Red => &self.plot_points_r,
             RgbComponent::Green => &self.plot_points_g,
             RgbComponent::Blue => &self.plot_points_b,
         };
-        values
+        let zoomed: Vec<_> = values
+            .iter()
+            .map(|(points, value)| {
+                let points: Vec<_> = points
+                    .iter()
+                    .filter(|[_, y]| zoom.is_full_range() || (zoom.min..=zoom.max).contains(y))
+                    .copied()
+                    .collect();
+                (points, *value)
+            })
+            .filter(|(points, _)| !points.is_empty())
+            .collect();
+
+        let max_value = |values: &[(Vec<[f64; 2]>, f64)]| {
+            values.iter().map(|(_, value)| *value).fold(0., f64::max)
+        };
+        let scale = if zoom.is_full_range() || zoomed.is_empty() {
+            1.
+        } else {
+            max_value(values) / max_value(&zoomed)
+        };
+
+        zoomed
+            .into_iter()
+            .map(|(points, value)| (points.into(), value * scale))
+            .collect()
+    }
+}
+
+/// Filters at the end of a precise preview's chain. The frame is brought to limited range and
+/// tagged as full range, so the conversion to RGB keeps the code values and nothing above
+/// white is clipped. [`precise_levels`] undoes this.
+const PRECISE_FRAME_FILTERS: &str =
+    "scale=out_range=limited,format=yuva444p16le,setparams=range=full";
+
+/// Levels of a precise preview, with black at 0 and white at 1. Values above white end up
+/// above 1, below black below 0.
+fn precise_levels(img: &Rgba16Image) -> Rgba32FImage {
+    // Limited range black and white in 16 bits
+    let (black, white) = (16. * 256., 235. * 256.);
+    Rgba32FImage::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b, a] = img.get_pixel(x, y).0;
+        let level = |v: u16| (v as f32 - black) / (white - black);
+        Rgba([level(r), level(g), level(b), a as f32 / u16::MAX as f32])
+    })
+}
+
+/// The 8 bit display frame of [`precise_levels`], clipped to black and white.
+fn levels_to_rgba8(img: &Rgba32FImage) -> RgbaImage {
+    RgbaImage::from_fn(img.width(), img.height(), |x, y| {
+        Rgba(
+            img.get_pixel(x, y)
+                .0
+                .map(|v| (v * 255.).round().clamp(0., 255.) as u8),
+        )
+    })
+}
+
+fn level_8bit(v: u8) -> f64 {
+    v as f64 / u8::MAX as f64
+}
+
+/// Scopes of an RGBA frame, `level` maps a channel value to 0 for black and 1 for white.
+fn scopes_of<P: Pixel>(
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    level: impl Fn(P::Subpixel) -> f64 + Copy,
+) -> (Waveform, SaturationScope) {
+    (
+        Waveform::from_image(img, level),
+        SaturationScope::from_image(img, level),
+    )
+}
+
+/// The frame the scopes are computed from. With `fast` it is downscaled so that the scopes can
+/// keep up with live changes on large frames.
+fn scope_frame<P: Pixel + 'static>(
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    fast: bool,
+) -> Cow<'_, ImageBuffer<P, Vec<P::Subpixel>>> {
+    if !fast || img.width() <= FAST_SCOPES_WIDTH {
+        return Cow::Borrowed(img);
+    }
+    let height = (img.height() as u64 * FAST_SCOPES_WIDTH as u64 / img.width() as u64).max(1);
+    Cow::Owned(image::imageops::resize(
+        img,
+        FAST_SCOPES_WIDTH,
+        height as u32,
+        image::imageops::FilterType::Triangle,
+    ))
+}
+
+/// `img` downscaled to fit into a texture with sides of at most `max_side` pixels, keeping the
+/// aspect ratio.
+fn texture_frame(img: RgbaImage, max_side: usize) -> RgbaImage {
+    let max_side = max_side.min(u32::MAX as usize) as u32;
+    let (width, height) = img.dimensions();
+    if width <= max_side && height <= max_side {
+        return img;
+    }
+    let scale = max_side as f64 / width.max(height) as f64;
+    let size = |side: u32| ((side as f64 * scale).floor() as u32).clamp(1, max_side);
+    image::imageops::resize(
+        &img,
+        size(width),
+        size(height),
+        image::imageops::FilterType::Triangle,
+    )
+}
+
+const SATURATION_SCOPE_LEVELS: u64 = 16;
+
+#[derive(Debug)]
+struct SaturationScope {
+    plot_points: Vec<(Vec<[f64; 2]>, f64)>,
+}
+
+impl SaturationScope {
+    fn from_image<P: Pixel>(
+        img: &ImageBuffer<P, Vec<P::Subpixel>>,
+        level: impl Fn(P::Subpixel) -> f64,
+    ) -> Self {
+        let mut bins: HashMap<(u32, i32), u64> = HashMap::new();
+
+        for pixel in img.pixels() {
+            let [r, g, b] = [0, 1, 2].map(|i| level(pixel.channels()[i]));
+            // Rec.709 luma and color difference components
+            let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            let cb = (b - luma) / 1.8556;
+            let cr = (r - luma) / 1.5748;
+            let saturation = (cb * cb + cr * cr).sqrt();
+            *bins
+                .entry(((saturation * 100.) as u32, (luma * 100.).floor() as i32))
+                .or_default() += 1;
+        }
+
+        let max_count = bins.values().copied().max().unwrap_or(1);
+        let mut levels: HashMap<u64, Vec<[f64; 2]>> = HashMap::new();
+        for ((saturation, luma), count) in bins {
+            let level = (count * SATURATION_SCOPE_LEVELS).div_ceil(max_count);
+            levels
+                .entry(level)
+                .or_default()
+                .push([saturation as f64, luma as f64]);
+        }
+
+        Self {
+            plot_points: levels
+                .into_iter()
+                .map(|(level, points)| (points, level as f64 / SATURATION_SCOPE_LEVELS as f64))
+                .collect(),
+        }
+    }
+
+    fn get_plot_points(&self) -> Vec<(PlotPoints, f64)> {
+        self.plot_points
             .iter()
             .cloned()
             .map(|(points, value)| (points.into(), value))
             .collect()
     }
+
+    /// Pixels outside of this boundary have luma +/- chroma beyond the 0-100% range.
+    fn legal_boundary() -> PlotPoints {
+        vec![[0., 0.], [50., 50.], [0., 100.]].into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn luma_modes() {
+        let gray = Rgba([119, 119, 119, 255]);
+        let red = Rgba([255, 0, 0, 255]);
+        let approx = |a: f64, b: f64| (a - b).abs() < 0.1;
+        assert!(approx(LumaMode::Srgb.luma(&gray), 46.67));
+        assert!(approx(LumaMode::LinearEncoded.luma(&gray), 46.67));
+        assert!(approx(LumaMode::Linear.luma(&gray), 18.4));
+        assert!(approx(LumaMode::Srgb.luma(&red), 21.18));
+        assert!(approx(LumaMode::Linear.luma(&red), 21.26));
+        assert!(approx(LumaMode::LinearEncoded.luma(&red), 49.85));
+    }
+
+    #[test]
+    fn preview_manipulations_from_old_state() {
+        let manipulation = serde_json::json!({
+            "is_active": true,
+            "manip_type": "Zebra",
+            "zebra_value": 90,
+            "zebra_range": 5,
+        });
+        let state: ColorustState =
+            serde_json::from_value(serde_json::json!({ "preview_manipulation": manipulation }))
+                .unwrap();
+        assert_eq!(state.preview_manipulations.len(), 1);
+        assert_eq!(state.preview_manipulations[0].zebra_value, 90);
+
+        let state: ColorustState = serde_json::from_value(serde_json::json!({
+            "preview_manipulations": [manipulation, manipulation],
+        }))
+        .unwrap();
+        assert_eq!(state.preview_manipulations.len(), 2);
+
+        let state: ColorustState = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert_eq!(state.preview_manipulations.len(), 1);
+        assert!(!state.preview_manipulations[0].is_active);
+    }
+
+    #[test]
+    fn default_chain() {
+        let names: Vec<_> = default_filters()
+            .filters
+            .iter()
+            .map(|filter| filter.name())
+            .collect();
+        assert_eq!(names.len(), 15);
+        assert!(!names.contains(&"ASC CDL"));
+        assert!(!names.contains(&"RGB gain"));
+    }
+
+    #[test]
+    fn fuzzy_matching() {
+        assert!(fuzzy_score("ctemp", "Color temperature").is_some());
+        assert!(fuzzy_score("xyz", "Color temperature").is_none());
+        assert!(fuzzy_score("", "Eq").is_some());
+        assert!(fuzzy_score("cb", "Color balance") > fuzzy_score("cb", "Custom filter(s)"));
+
+        let palette = FilterPalette {
+            query: "lut".to_string(),
+            selected: 0,
+        };
+        assert_eq!(FILTER_REGISTRY[palette.matches()[0]].0, "LUT");
+    }
+
+    #[test]
+    fn look_diff() {
+        let old = serde_json::json!({
+            "filters": [{"type": "FilterEq", "contrast": 1.0, "gamma": 1.0}],
+            "path": "a.mov"
+        });
+        let new = serde_json::json!({
+            "filters": [
+                {"type": "FilterEq", "contrast": 1.2, "gamma": 1.0},
+                {"type": "FilterLut", "file": "x.cube"}
+            ],
+            "path": "a.mov"
+        });
+        assert_eq!(
+            json_diff(&old, &new),
+            vec![
+                (
+                    "filters.0 FilterEq.contrast".to_string(),
+                    "1.0".to_string(),
+                    "1.2".to_string()
+                ),
+                (
+                    "filters.1 FilterLut".to_string(),
+                    "—".to_string(),
+                    r#"{"file":"x.cube","type":"FilterLut"}"#.to_string()
+                ),
+            ]
+        );
+        assert!(json_diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn preview_zoom() {
+        let image = Vec2::new(1920., 1080.);
+        assert_eq!(
+            PreviewZoom::Fit.image_size(image, Vec2::new(960., 1000.), 2.),
+            Vec2::new(960., 540.)
+        );
+        assert_eq!(
+            PreviewZoom::ActualPixels.image_size(image, Vec2::new(960., 1000.), 2.),
+            Vec2::new(960., 540.)
+        );
+        assert_eq!(
+            PreviewZoom::ActualPixels.image_size(image, Vec2::new(960., 1000.), 1.),
+            image
+        );
+        assert_eq!(
+            PreviewZoom::Fit.image_size(image, Vec2::new(3840., 4000.), 1.),
+            image * 2.
+        );
+    }
+
+    #[test]
+    fn preview_view() {
+        let panel = Rect::from_min_size(Pos2::new(100., 0.), Vec2::new(400., 300.));
+        let size = Vec2::new(400., 200.);
+        let mut view = PreviewView::default();
+        let cursor = Vec2::new(200., 100.);
+        let before = PreviewView::to_image_pixel(
+            view.image_rect(panel, size),
+            panel.min + cursor,
+            [800, 400],
+        );
+        view.zoom_around(2., cursor);
+        assert_eq!(view.zoom, 2.);
+        let after = PreviewView::to_image_pixel(
+            view.image_rect(panel, size),
+            panel.min + cursor,
+            [800, 400],
+        );
+        assert_eq!(before, Some([400, 200]));
+        assert_eq!(before, after);
+        assert_eq!(
+            PreviewView::to_image_pixel(
+                view.image_rect(panel, size),
+                Pos2::new(800., 0.),
+                [800, 400]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn template_validation() {
+        assert!(
+            template_issues("ffmpeg ##input## ##cli## ##filter## ##encoder## ##output##")
+                .is_empty()
+        );
+        assert_eq!(
+            template_issues("ffmpeg ##inpt## ##fliter## ##foo## ##output##"),
+            vec![
+                TemplateIssue::Unknown {
+                    token: "##inpt##".to_string(),
+                    suggestion: Some("##input##")
+                },
+                TemplateIssue::Unknown {
+                    token: "##fliter##".to_string(),
+                    suggestion: Some("##filter##")
+                },
+                TemplateIssue::Unknown {
+                    token: "##foo##".to_string(),
+                    suggestion: None
+                },
+                TemplateIssue::Missing("##input##"),
+            ]
+        );
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn batch_grade_args() {
+        let temp_dir = TempDir::new().unwrap();
+        for name in ["b.JPG", "a.png", "notes.txt"] {
+            std::fs::write(temp_dir.path().join(name), "").unwrap();
+        }
+        let inputs = batch_inputs(temp_dir.path()).unwrap();
+        assert_eq!(
+            inputs,
+            vec![temp_dir.path().join("a.png"), temp_dir.path().join("b.JPG")]
+        );
+
+        let mut file_state = representative_file_state();
+        file_state.input_file.path = PathBuf::from("a.png");
+        file_state.set_output(PathBuf::from("graded/a.png"));
+        let args = file_state.to_ffmpeg_args(ArgPurpose::Still);
+        assert_eq!(
+            args[3..],
+            [
+                "-i",
+                "a.png",
+                "-vf",
+                "scale=1280:720",
+                "-frames:v",
+                "1",
+                "graded/a.png"
+            ]
+        );
+    }
+
+    #[test]
+    fn thumbnail_args() {
+        let file_state = representative_file_state();
+        let before = file_state.thumbnail_args(None, FfmpegLogLevel::Error);
+        assert_eq!(before[before.len() - 2..], ["-vf", "scale=64:-2"]);
+        let after = file_state.thumbnail_args(Some(0), FfmpegLogLevel::Error);
+        assert_eq!(after.last().unwrap(), "scale=1280:720,scale=64:-2");
+        assert_eq!(before[..before.len() - 2], after[..after.len() - 2]);
+    }
+
+    #[test]
+    fn forced_range() {
+        let mut file_state = representative_file_state();
+        file_state.input_file.range = crate::ffmpeg::InputRange::Limited;
+        let chain = "setparams=range=limited,scale=1280:720";
+        assert_eq!(
+            file_state.to_ffmpeg_args(ArgPurpose::Convert)[3],
+            chain.to_string()
+        );
+        assert_eq!(
+            file_state
+                .to_ffmpeg_args(ArgPurpose::Play {
+                    scopes: PlaybackScopes::None
+                })
+                .last()
+                .unwrap(),
+            chain
+        );
+        let preview = file_state.to_ffmpeg_args(ArgPurpose::Preview {
+            output: &FrameOutput::Pipe(FrameFormat::Bmp),
+            fast_preview: &FastPreview::default(),
+            extra_filter: None,
+            loglevel: FfmpegLogLevel::Warning,
+        });
+        assert!(preview.contains(&chain.to_string()));
+        let original = file_state.original_args(
+            &FrameOutput::Pipe(FrameFormat::Bmp),
+            &FastPreview::default(),
+            FfmpegLogLevel::Warning,
+        );
+        assert!(original.contains(&"setparams=range=limited".to_string()));
+        assert_eq!(
+            file_state
+                .thumbnail_args(None, FfmpegLogLevel::Error)
+                .last()
+                .unwrap(),
+            "setparams=range=limited,scale=64:-2"
+        );
+    }
+
+    #[test]
+    fn display_transform() {
+        let mut img = RgbaImage::from_pixel(1, 1, Rgba([0, 128, 255, 255]));
+        DisplayTransform::None.apply(&mut img);
+        assert_eq!(img.get_pixel(0, 0).0, [0, 128, 255, 255]);
+
+        DisplayTransform::SrgbToDisplayP3.apply(&mut img);
+        let [r, g, b, a] = img.get_pixel(0, 0).0;
+        assert!(r > 0 && g < 140 && b < 255 && a == 255);
+
+        let mut img = RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 255]));
+        DisplayTransform::SrgbToDisplayP3.apply(&mut img);
+        assert_eq!(img.get_pixel(0, 0).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn project_files() {
+        let file_state = |input: &str| {
+            let mut file_state = FileState::with_filters(Default::default());
+            file_state.input_file.path = PathBuf::from(input);
+            file_state
+        };
+        let inputs = |state: &ColorustState| {
+            (0..state.project_len())
+                .map(|i| {
+                    state
+                        .project_file(i)
+                        .input_file
+                        .path
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut state = ColorustState {
+            active_file_state: file_state("a"),
+            ..Default::default()
+        };
+        assert_eq!(inputs(&state), ["a"]);
+
+        state.add_project_file(file_state("b"));
+        state.add_project_file(file_state("c"));
+        assert_eq!(inputs(&state), ["a", "b", "c"]);
+        assert_eq!(state.project_active, 2);
+        assert_eq!(state.active_file_state.input_file.path, Path::new("c"));
+
+        state.switch_project_file(0);
+        assert_eq!(inputs(&state), ["a", "b", "c"]);
+        assert_eq!(state.active_file_state.input_file.path, Path::new("a"));
+        state.switch_project_file(1);
+        assert_eq!(inputs(&state), ["a", "b", "c"]);
+        assert_eq!(state.active_file_state.input_file.path, Path::new("b"));
+
+        state.remove_active_project_file();
+        assert_eq!(inputs(&state), ["a", "c"]);
+        assert_eq!(state.active_file_state.input_file.path, Path::new("c"));
+        state.remove_active_project_file();
+        state.remove_active_project_file();
+        assert_eq!(inputs(&state), ["a"]);
+    }
+
+    #[test]
+    fn input_templates() {
+        let mut state = ColorustState::default();
+        state.input_templates.insert(
+            PathBuf::from("seq_%04d.png"),
+            "ffmpeg -framerate 24 ##input##".to_string(),
+        );
+        assert_eq!(
+            state.conversion_template(Path::new("seq_%04d.png")),
+            "ffmpeg -framerate 24 ##input##"
+        );
+        assert_eq!(
+            state.conversion_template(Path::new("in.mov")),
+            state.conversion_template
+        );
+    }
+
+    #[test]
+    fn command_scripts() {
+        let commands = "ffmpeg -i a.mov a.mp4\n\n  ffmpeg -i b.mov b.mp4\n";
+        assert_eq!(
+            ScriptKind::Shell.script(commands),
+            "#!/bin/sh\nset -e\n\nffmpeg -i a.mov a.mp4\nffmpeg -i b.mov b.mp4\n"
+        );
+        assert_eq!(
+            ScriptKind::Batch.script(commands),
+            "@echo off\r\n\r\nffmpeg -i a.mov a.mp4\r\nif errorlevel 1 exit /b 1\r\n\
+             ffmpeg -i b.mov b.mp4\r\nif errorlevel 1 exit /b 1\r\n"
+        );
+        assert_eq!(
+            ScriptKind::Batch.script("ffmpeg -i \"seq %04d.png\" a.mp4"),
+            "@echo off\r\n\r\nffmpeg -i \"seq %%04d.png\" a.mp4\r\nif errorlevel 1 exit /b 1\r\n"
+        );
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.child("convert.sh");
+        write_script(&path, &ScriptKind::Shell.script(commands)).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o100, 0o100);
+        }
+    }
+
+    #[test]
+    fn texture_limits() {
+        let img = RgbaImage::new(800, 432);
+        let fitted = texture_frame(img.clone(), 1024);
+        assert_eq!(fitted.dimensions(), (800, 432));
+        let fitted = texture_frame(img, 400);
+        assert_eq!(fitted.dimensions(), (400, 216));
+        let tall = texture_frame(RgbaImage::new(10, 5000), 2048);
+        assert_eq!(tall.dimensions(), (4, 2048));
+    }
+
+    #[test]
+    fn fast_scopes() {
+        let img = RgbaImage::from_pixel(1920, 1080, Rgba([10, 128, 250, 255]));
+        assert!(matches!(scope_frame(&img, false), Cow::Borrowed(_)));
+        let fast = scope_frame(&img, true);
+        assert_eq!(fast.dimensions(), (FAST_SCOPES_WIDTH, 144));
+        assert!(fast.pixels().all(|p| p.0 == [10, 128, 250, 255]));
+
+        let small = RgbaImage::new(200, 100);
+        assert!(matches!(scope_frame(&small, true), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn graticule() {
+        let levels = |graticule: &Graticule, zoom: &WaveformZoom| {
+            graticule
+                .lines(zoom)
+                .into_iter()
+                .map(|(level, label, _)| (level, label))
+                .collect::<Vec<_>>()
+        };
+        let mut graticule = Graticule::default();
+        assert_eq!(
+            levels(&graticule, &WaveformZoom::default()),
+            [
+                (0., "0 IRE".to_string()),
+                (7.5, "7.5 IRE".to_string()),
+                (50., "50 IRE".to_string()),
+                (100., "100 IRE".to_string()),
+            ]
+        );
+        graticule.show_broadcast_limits = true;
+        let zoom = WaveformZoom {
+            min: 80.,
+            max: 100.,
+        };
+        assert_eq!(
+            levels(&graticule, &zoom),
+            [
+                (100., "100 IRE".to_string()),
+                (103., "103 IRE max".to_string()),
+            ]
+        );
+        graticule.is_active = false;
+        assert!(graticule.lines(&zoom).is_empty());
+    }
+
+    #[test]
+    fn waveform_zoom() {
+        let img = RgbaImage::from_fn(2, 4, |_, y| {
+            if y == 0 {
+                Rgba([128, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        });
+        let waveform = Waveform::from_image(&img, level_8bit);
+        let full = waveform.get_plot_points(RgbComponent::Red, &WaveformZoom::default());
+        assert_eq!(full.len(), 2);
+
+        let zoomed =
+            waveform.get_plot_points(RgbComponent::Red, &WaveformZoom { min: 40., max: 60. });
+        assert_eq!(zoomed.len(), 1);
+        assert_eq!(zoomed[0].1, 0.75);
+    }
+
+    #[test]
+    fn tone_approximation() {
+        let neutral = ToneApproximation::default();
+        let lut = neutral.lut_to(&neutral);
+        assert!(lut.iter().enumerate().all(|(i, v)| i as u8 == *v));
+
+        let brighter = ToneApproximation {
+            exposure: 1.,
+            ..Default::default()
+        };
+        let lut = neutral.lut_to(&brighter);
+        assert_eq!(lut[64], 128);
+        assert_eq!(lut[200], 255);
+        let lut = brighter.lut_to(&neutral);
+        assert_eq!(lut[128], 64);
+    }
+
+    fn representative_file_state() -> FileState {
+        let mut file_state: FileState = serde_json::from_value(serde_json::json!({
+            "input_file": {"path": "in.mov"},
+            "output_file": {"path": "out.mp4"},
+            "encoder": {"expression": "libx264"},
+            "skip_seconds": {"seconds": 10},
+            "cli_options": [],
+            "filter_options": {"filters": []},
+        }))
+        .unwrap();
+        file_state.filter_options.filters = vec![
+            Box::new(FilterScale {
+                is_active: true,
+                width: 1280,
+                height: 720,
+            }),
+            Box::<FilterExposure>::default(),
+        ];
+        file_state
+    }
+
+    #[test]
+    fn ffmpeg_args() {
+        let file_state = representative_file_state();
+        let args = |a: &[&str]| a.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            file_state.to_ffmpeg_args(ArgPurpose::Convert),
+            args(&[
+                "-i",
+                "in.mov",
+                "-vf",
+                "scale=1280:720",
+                "-c:v",
+                "libx264",
+                "out.mp4"
+            ])
+        );
+        assert_eq!(
+            file_state.to_ffmpeg_args(ArgPurpose::Play {
+                scopes: PlaybackScopes::None
+            }),
+            args(&["-ss", "10", "-i", "in.mov", "-vf", "scale=1280:720"])
+        );
+        assert_eq!(
+            file_state
+                .to_ffmpeg_args(ArgPurpose::Play {
+                    scopes: PlaybackScopes::Vectorscope
+                })
+                .last()
+                .unwrap(),
+            "scale=1280:720,split[main][scope];[scope]vectorscope=mode=color3:graticule=green[vs];\
+             [main][vs]overlay=W-w:0"
+        );
+        assert_eq!(
+            file_state.to_ffmpeg_args(ArgPurpose::Proxy {
+                output: Path::new("proxy-1.mp4"),
+                seconds: 30.,
+            }),
+            args(&[
+                "-y",
+                "-loglevel",
+                "level+error",
+                "-ss",
+                "10",
+                "-i",
+                "in.mov",
+                "-vf",
+                "scale=1280:720",
+                "-c:v",
+                "libx264",
+                "-preset",
+                "veryfast",
+                "-crf",
+                "18",
+                "-pix_fmt",
+                "yuv420p",
+                "-c:a",
+                "aac",
+                "-t",
+                "30",
+                "proxy-1.mp4"
+            ])
+        );
+        assert_eq!(
+            file_state.to_ffmpeg_args(ArgPurpose::Preview {
+                output: &FrameOutput::Pipe(FrameFormat::Bmp),
+                fast_preview: &FastPreview::default(),
+                extra_filter: Some("waveform"),
+                loglevel: FfmpegLogLevel::Warning,
+            }),
+            args(&[
+                "-y",
+                "-loglevel",
+                "level+warning",
+                "-ss",
+                "10",
+                "-i",
+                "in.mov",
+                "-frames:v",
+                "1",
+                "-vf",
+                "scale=1280:720,waveform",
+                "-f",
+                "image2pipe",
+                "-vcodec",
+                "bmp",
+                "pipe:1"
+            ])
+        );
+        assert_eq!(
+            file_state.to_ffmpeg_args(ArgPurpose::Preview {
+                output: &FrameOutput::Pipe(FrameFormat::Jpeg),
+                fast_preview: &FastPreview {
+                    is_active: true,
+                    ..Default::default()
+                },
+                extra_filter: None,
+                loglevel: FfmpegLogLevel::Warning,
+            })[9..13],
+            args(&["-vf", "scale=1280:720,scale=640:-2", "-q:v", "5"])
+        );
+
+        let mut file_state = file_state;
+        file_state.skip_seconds.accurate = true;
+        assert_eq!(
+            file_state.to_ffmpeg_args(ArgPurpose::Preview {
+                output: &FrameOutput::Pipe(FrameFormat::Bmp),
+                fast_preview: &FastPreview::default(),
+                extra_filter: None,
+                loglevel: FfmpegLogLevel::Warning,
+            })[3..7],
+            args(&["-i", "in.mov", "-ss", "10"])
+        );
+    }
+
+    #[test]
+    fn file_history_eviction() {
+        let mut state = ColorustState {
+            file_history_limit: 2,
+            ..Default::default()
+        };
+        for input in ["a", "b", "c"] {
+            state
+                .file_history
+                .insert(PathBuf::from(input), String::new());
+        }
+        state.touch_history(Path::new("b"));
+        state.touch_history(Path::new("c"));
+        state.touch_history(Path::new("b"));
+        state.evict_history();
+        assert!(!state.file_history.contains_key(Path::new("a")));
+
+        state.file_history_limit = 1;
+        state.evict_history();
+        assert_eq!(
+            state.file_history.keys().collect::<Vec<_>>(),
+            vec![Path::new("b")]
+        );
+        assert_eq!(state.file_history_order, vec![PathBuf::from("b")]);
+    }
+
+    #[test]
+    fn suffixed_output_path() {
+        assert_eq!(
+            path_with_suffix(Path::new("/videos/out.mp4"), "warm"),
+            PathBuf::from("/videos/out_warm.mp4")
+        );
+        assert_eq!(
+            path_with_suffix(Path::new("out"), "warm"),
+            PathBuf::from("out_warm")
+        );
+    }
+
+    #[test]
+    fn batch_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let input_dir = temp_dir.path();
+        assert!(batch_output_dir(input_dir, Path::new("")).is_err());
+        assert!(batch_output_dir(input_dir, input_dir).is_err());
+        assert!(batch_output_dir(input_dir, &input_dir.join(".")).is_err());
+        let output_dir = input_dir.join("graded");
+        assert_eq!(batch_output_dir(input_dir, &output_dir), Ok(output_dir));
+    }
+
+    #[test]
+    fn transparency() {
+        let mut img = RgbaImage::from_pixel(4, 4, Rgba([10, 20, 30, 255]));
+        assert!(!has_transparency(&img));
+        img.put_pixel(3, 2, Rgba([10, 20, 30, 128]));
+        assert!(has_transparency(&img));
+    }
+
+    #[test]
+    fn scope_screenshot_crop() {
+        let mut image = ColorImage::new([20, 10], Color32::BLACK);
+        image[(5, 3)] = Color32::RED;
+        let rect = Rect::from_min_size(Pos2::new(2., 1.), Vec2::new(4., 4.));
+        let img = crop_screenshot(&image, rect, 1.);
+        assert_eq!(img.dimensions(), (4, 4));
+        assert_eq!(img.get_pixel(3, 2), &Rgba([255, 0, 0, 255]));
+
+        let img = crop_screenshot(&image, rect, 2.);
+        assert_eq!(img.dimensions(), (8, 8));
+        assert_eq!(img.get_pixel(1, 1), &Rgba([255, 0, 0, 255]));
+
+        let partly_off_screen = Rect::from_min_size(Pos2::new(15., 8.), Vec2::new(10., 10.));
+        assert_eq!(
+            crop_screenshot(&image, partly_off_screen, 1.).dimensions(),
+            (5, 2)
+        );
+        let off_screen = Rect::from_min_size(Pos2::new(30., 0.), Vec2::new(10., 10.));
+        assert_eq!(crop_screenshot(&image, off_screen, 1.).dimensions(), (0, 0));
+    }
+
+    #[test]
+    fn compare_frames() {
+        let graded = RgbaImage::from_pixel(4, 2, Rgba([200, 100, 0, 255]));
+        let original = RgbaImage::from_pixel(4, 2, Rgba([0, 100, 200, 255]));
+
+        let mut img = graded.clone();
+        Compare {
+            mode: CompareMode::Wipe,
+            position: 0.25,
+        }
+        .apply(&mut img, &original);
+        assert_eq!(img.get_pixel(0, 1), graded.get_pixel(0, 1));
+        assert_eq!(img.get_pixel(1, 1), original.get_pixel(1, 1));
+
+        let mut img = graded.clone();
+        Compare {
+            mode: CompareMode::Crossfade,
+            position: 0.75,
+        }
+        .apply(&mut img, &original);
+        assert_eq!(img.get_pixel(2, 0).0, [150, 100, 50, 255]);
+
+        let mut img = graded.clone();
+        let smaller = RgbaImage::from_pixel(2, 1, Rgba([0, 100, 200, 255]));
+        Compare {
+            mode: CompareMode::Wipe,
+            position: 0.,
+        }
+        .apply(&mut img, &smaller);
+        assert_eq!(img, original);
+
+        let mut img = graded.clone();
+        Compare::default().apply(&mut img, &original);
+        assert_eq!(img, graded);
+    }
+
+    #[test]
+    fn compare_seek() {
+        let mut file_state = representative_file_state();
+        let output = FrameOutput::Pipe(FrameFormat::Bmp);
+        let fast_preview = FastPreview::default();
+        for accurate in [false, true] {
+            file_state.skip_seconds.seconds = 12;
+            file_state.skip_seconds.accurate = accurate;
+            let preview =
+                file_state.preview_args(&output, &fast_preview, None, FfmpegLogLevel::Error);
+            let original = file_state.original_args(&output, &fast_preview, FfmpegLogLevel::Error);
+            let seek_index = |args: &[String]| args.iter().position(|arg| arg == "-ss");
+            let input_index = |args: &[String]| args.iter().position(|arg| arg == "-i");
+            assert_eq!(seek_index(&preview), seek_index(&original));
+            assert_eq!(seek_index(&preview) > input_index(&preview), accurate);
+            let seek = seek_index(&preview).unwrap();
+            assert_eq!(preview[seek + 1], "12");
+            assert_eq!(original[seek + 1], "12");
+        }
+        assert_eq!(
+            file_state.frame_seek().to_string(),
+            "-ss 12 (frame accurate)"
+        );
+    }
+
+    #[test]
+    fn quoted_conversion_command() {
+        let mut file_state = representative_file_state();
+        file_state.input_file.path = PathBuf::from("/videos/my clip.mov");
+        file_state.set_output(PathBuf::from("/videos/it's done.mp4"));
+        file_state.filter_options.filters.truncate(1);
+
+        let args = file_state.to_ffmpeg_args(ArgPurpose::Convert);
+        assert!(args.iter().any(|arg| arg == "/videos/my clip.mov"));
+        assert!(args.iter().any(|arg| arg == "/videos/it's done.mp4"));
+
+        let template = "ffmpeg ##input## ##filter## ##output##";
+        assert_eq!(
+            file_state.conversion_command(template, ScriptKind::Batch),
+            r#"ffmpeg -i "/videos/my clip.mov" -vf scale=1280:720 "/videos/it's done.mp4""#
+        );
+        assert_eq!(
+            file_state.conversion_command(template, ScriptKind::Shell),
+            r"ffmpeg -i '/videos/my clip.mov' -vf scale=1280:720 '/videos/it'\''s done.mp4'"
+        );
+    }
+
+    #[test]
+    fn shell_quoting() {
+        assert_eq!(quote_posix("scale=1280:720"), "scale=1280:720");
+        assert_eq!(quote_posix(""), "''");
+        assert_eq!(quote_posix("a b"), "'a b'");
+        assert_eq!(
+            quote_posix("curves=all='0/0 1/1'"),
+            r"'curves=all='\''0/0 1/1'\'''"
+        );
+        assert_eq!(quote_posix("$HOME;rm"), "'$HOME;rm'");
+        assert_eq!(quote_cmd(r"C:\My Videos\a.mov"), r#""C:\My Videos\a.mov""#);
+        assert_eq!(quote_cmd(r#"say "hi""#), r#""say ""hi""""#);
+        assert_eq!(quote_posix("seq_%04d.png"), "seq_%04d.png");
+        assert_eq!(quote_cmd("seq_%04d.png"), r#""seq_%04d.png""#);
+    }
+
+    #[test]
+    fn conversion_args() {
+        let file_state = representative_file_state();
+        let template = "ffmpeg -hide_banner ##input## ##filter## ##output##";
+        assert_eq!(
+            file_state.conversion_args(template),
+            [
+                "-hide_banner",
+                "-i",
+                "in.mov",
+                "-vf",
+                "scale=1280:720",
+                "out.mp4"
+            ]
+        );
+
+        let template = r#"/opt/ffmpeg/bin/ffmpeg -y ##input## -metadata "title=My clip" -metadata 'comment=a "b"' ##output##"#;
+        assert_eq!(
+            file_state.conversion_args(template),
+            [
+                "-y",
+                "-i",
+                "in.mov",
+                "-metadata",
+                "title=My clip",
+                "-metadata",
+                r#"comment=a "b""#,
+                "out.mp4"
+            ]
+        );
+        // Without a program
+        assert_eq!(
+            file_state.conversion_args("##input## ##output##"),
+            ["-i", "in.mov", "out.mp4"]
+        );
+    }
+
+    #[test]
+    fn precise_scopes() {
+        let levels = |waveform: &Waveform| {
+            let mut levels: Vec<_> = waveform
+                .plot_points_r
+                .iter()
+                .flat_map(|(points, _)| points.iter().map(|[_, y]| *y))
+                .collect();
+            levels.sort_by(f64::total_cmp);
+            levels
+        };
+        // Limited range code values: black, just above black, white and above white
+        let codes = [4096, 4112, 60160, 65280];
+        let img = Rgba16Image::from_fn(1, 4, |_, y| Rgba([codes[y as usize], 0, 0, 65535]));
+        let precise = precise_levels(&img);
+        let (waveform, _) = scopes_of(&precise, |v| v as f64);
+        assert_eq!(levels(&waveform), vec![0., 0.03, 100., 109.13]);
+
+        let display = levels_to_rgba8(&precise);
+        let reds: Vec<_> = display.pixels().map(|p| p.0[0]).collect();
+        assert_eq!(reds, vec![0, 0, 255, 255]);
+        let (waveform, _) = scopes_of(&display, level_8bit);
+        assert_eq!(levels(&waveform), vec![0., 100.]);
+        assert!(PRECISE_FRAME_FILTERS.ends_with("setparams=range=full"));
+    }
+
+    #[test]
+    fn scope_throttle() {
+        let interval = Duration::from_millis(200);
+        let now = Instant::now();
+        let mut throttle = ScopeThrottle::default();
+        assert_eq!(throttle.remaining(interval, now), Duration::ZERO);
+
+        throttle.last_update = Some(now);
+        assert_eq!(
+            throttle.remaining(interval, now + Duration::from_millis(50)),
+            Duration::from_millis(150)
+        );
+        assert_eq!(
+            throttle.remaining(interval, now + Duration::from_millis(250)),
+            Duration::ZERO
+        );
+        assert_eq!(throttle.remaining(Duration::ZERO, now), Duration::ZERO);
+    }
+
+    #[test]
+    fn input_options_before_input() {
+        let mut file_state = representative_file_state();
+        file_state.add_missing_input_options();
+        assert_eq!(file_state.cli_options.len(), INPUT_OPTION_REGISTRY.len());
+        file_state.add_missing_input_options();
+        assert_eq!(file_state.cli_options.len(), INPUT_OPTION_REGISTRY.len());
+        let no_audio: Box<dyn CliOption> =
+            serde_json::from_str(r#"{"type": "NoAudioOption", "is_active": true}"#).unwrap();
+        let framerate: Box<dyn CliOption> = serde_json::from_str(
+            r#"{"type": "InputFramerateOption", "is_active": true, "framerate": "24000/1001"}"#,
+        )
+        .unwrap();
+        file_state.cli_options = vec![no_audio, framerate];
+
+        let args = file_state.to_ffmpeg_args(ArgPurpose::Convert);
+        assert_eq!(
+            args[..5],
+            ["-an", "-r", "24000/1001", "-i", "in.mov"].map(String::from)
+        );
+        let args = file_state.single_frame_args(FfmpegLogLevel::Error);
+        let input = args.iter().position(|arg| arg == "-i").unwrap();
+        assert_eq!(args[input - 3..input], ["-an", "-r", "24000/1001"]);
+        assert_eq!(args.iter().filter(|arg| *arg == "-an").count(), 1);
+    }
 }
@@ -1,16 +1,19 @@
 use color_eyre::Result;
 use log::LevelFilter;
+use logging::BufferedLogger;
 use simple_logger::SimpleLogger;
 
+pub mod cdl;
 pub mod ffmpeg;
+pub mod filtergraph;
 pub mod gui;
+pub mod logging;
 pub mod mlt;
 
 pub fn init_logging() -> Result<()> {
-    SimpleLogger::new()
-        .with_level(LevelFilter::Info)
-        .env()
-        .init()?;
+    let logger = SimpleLogger::new().with_level(LevelFilter::Info).env();
+    log::set_max_level(logger.max_level());
+    log::set_boxed_logger(Box::new(BufferedLogger::new(logger)))?;
     color_eyre::install()?;
 
     Ok(())
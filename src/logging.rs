@@ -0,0 +1,105 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use log::{Level, Log, Metadata, Record};
+use simple_logger::SimpleLogger;
+
+const LOG_BUFFER_CAPACITY: usize = 1000;
+
+pub static LOG_BUFFER: LogBuffer = LogBuffer::new();
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub struct LogBuffer {
+    entries: Mutex<VecDeque<LogEntry>>,
+}
+
+impl LogBuffer {
+    pub const fn new() -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn push(&self, entry: LogEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= LOG_BUFFER_CAPACITY {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    pub fn entries(&self) -> Vec<LogEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Forwards records to `SimpleLogger` and keeps a copy in [`LOG_BUFFER`] for the GUI.
+pub struct BufferedLogger {
+    inner: SimpleLogger,
+}
+
+impl BufferedLogger {
+    pub fn new(inner: SimpleLogger) -> Self {
+        Self { inner }
+    }
+}
+
+impl Log for BufferedLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.inner.log(record);
+            LOG_BUFFER.push(LogEntry {
+                level: record.level(),
+                target: record.target().to_string(),
+                message: record.args().to_string(),
+            });
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffer_is_capped() {
+        let buffer = LogBuffer::new();
+        for i in 0..LOG_BUFFER_CAPACITY + 10 {
+            buffer.push(LogEntry {
+                level: Level::Info,
+                target: "test".to_string(),
+                message: i.to_string(),
+            });
+        }
+        let entries = buffer.entries();
+        assert_eq!(entries.len(), LOG_BUFFER_CAPACITY);
+        assert_eq!(entries[0].message, "10");
+
+        buffer.clear();
+        assert!(buffer.entries().is_empty());
+    }
+}
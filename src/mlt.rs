@@ -1,10 +1,29 @@
-use std::{borrow::Cow, collections::HashMap, str::FromStr};
+use std::{borrow::Cow, collections::HashMap, io::Read, path::Path, str::FromStr};
 
+use flate2::read::GzDecoder;
 use regex::Regex;
 use roxmltree::Node;
 
 use crate::ffmpeg::{Filter, FilterColortemp, FilterEq, FilterExposure, FilterLut};
 
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Reads an MLT/kdenlive project file which may be gzip-compressed.
+pub fn read_mlt(path: impl AsRef<Path>) -> std::io::Result<String> {
+    decode_mlt(std::fs::read(path)?)
+}
+
+fn decode_mlt(bytes: Vec<u8>) -> std::io::Result<String> {
+    if bytes.starts_with(&GZIP_MAGIC) {
+        let mut mlt = String::new();
+        GzDecoder::new(bytes.as_slice()).read_to_string(&mut mlt)?;
+        Ok(mlt)
+    } else {
+        String::from_utf8(bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
 pub fn get_property_value<T: FromStr>(node: &Node, name: &str) -> Option<T> {
     node.descendants()
         .find(|n| n.tag_name().name() == "property" && n.attribute("name") == Some(name))
@@ -21,16 +40,53 @@ pub fn get_property_value<T: FromStr>(node: &Node, name: &str) -> Option<T> {
         .flatten()
 }
 
-pub fn get_filter_strings(root: &Node) -> HashMap<String, String> {
-    let mut filter_strings = HashMap::new();
+/// A position as MLT writes it, a frame number or a clock time like `00:01:02.500`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MltTime {
+    Frames(u64),
+    Seconds(f64),
+}
+
+impl FromStr for MltTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(frames) = s.parse() {
+            return Ok(Self::Frames(frames));
+        }
+        let parts: Vec<&str> = s.split(':').collect();
+        let [hours, minutes, seconds] = parts[..] else {
+            return Err(format!("Unsupported MLT time {s:?}"));
+        };
+        let parse = |v: &str| {
+            v.parse::<f64>()
+                .map_err(|_| format!("Invalid MLT time {s:?}"))
+        };
+        Ok(Self::Seconds(
+            parse(hours)? * 3600. + parse(minutes)? * 60. + parse(seconds)?,
+        ))
+    }
+}
+
+/// The filters of one playlist entry and the portion of the producer the entry uses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryFilters {
+    pub in_point: Option<MltTime>,
+    pub out_point: Option<MltTime>,
+    pub filter_string: String,
+}
+
+/// The filters of each playlist entry, keyed by the file of the entry's producer.
+pub fn get_filter_strings(root: &Node) -> HashMap<String, Vec<EntryFilters>> {
+    let mut filter_strings: HashMap<String, Vec<EntryFilters>> = HashMap::new();
     for entry in root
-        .first_child()
-        .unwrap()
-        .children()
+        .descendants()
         .filter(|n| n.has_tag_name("playlist"))
         .flat_map(|n| n.children().filter(|n| n.has_tag_name("entry")))
     {
-        let producer = entry.attribute("producer").unwrap();
+        let Some(producer) = entry.attribute("producer") else {
+            continue;
+        };
         let filter_string = entry
             .children()
             .filter(|n| n.has_tag_name("filter"))
@@ -53,47 +109,102 @@ pub fn get_filter_strings(root: &Node) -> HashMap<String, String> {
             })
             .collect::<Vec<_>>()
             .join(",");
-        if !filter_string.is_empty() {
-            filter_strings.insert(
-                get_url_from_producer(root, producer).unwrap(),
-                filter_string,
-            );
+        if filter_string.is_empty() {
+            continue;
+        }
+        let time = |name: &str| {
+            let value = entry.attribute(name)?;
+            value
+                .parse()
+                .inspect_err(|e| log::warn!("Entry of producer {producer}: {e}"))
+                .ok()
+        };
+        let entry_filters = EntryFilters {
+            in_point: time("in"),
+            out_point: time("out"),
+            filter_string,
+        };
+        match get_url_from_producer(root, producer) {
+            Some(url) => {
+                filter_strings.entry(url).or_default().push(entry_filters);
+            }
+            None => log::warn!("Could not find the file of producer {producer}"),
         }
     }
     filter_strings
 }
 
+/// Producers referencing other producers are followed this deep at most.
+const MAX_PRODUCER_DEPTH: usize = 8;
+
 fn get_url_from_producer(root: &Node, producer: &str) -> Option<String> {
-    let producer_properties: Vec<_> = root
-        .first_child()?
-        .children()
-        .find(|n| {
-            (n.has_tag_name("producer") || n.has_tag_name("chain"))
-                && n.attribute("id").unwrap() == producer
-        })?
-        .children()
-        .filter(|n| n.has_tag_name("property"))
-        .collect();
-
-    producer_properties
-        .iter()
-        .find(|n| n.attribute("name") == Some("kdenlive:originalurl"))
-        .or_else(|| {
-            producer_properties
-                .iter()
-                .find(|n| n.attribute("name") == Some("resource"))
-        })
-        .and_then(|n| Some(n.text()?.to_string()))
+    producer_url(root, &find_producer(root, producer)?, 0)
+}
+
+fn find_producer<'a, 'input>(root: &Node<'a, 'input>, id: &str) -> Option<Node<'a, 'input>> {
+    root.descendants().find(|n| {
+        (n.has_tag_name("producer") || n.has_tag_name("chain")) && n.attribute("id") == Some(id)
+    })
+}
+
+/// The file of `node`. Producers without one of their own, e.g. chains wrapping or referencing
+/// the actual producer, are resolved through their children.
+fn producer_url(root: &Node, node: &Node, depth: usize) -> Option<String> {
+    let property = |name: &str| {
+        node.children()
+            .find(|n| n.has_tag_name("property") && n.attribute("name") == Some(name))
+            .and_then(|n| Some(n.text()?.to_string()))
+    };
+    if let Some(url) = property("kdenlive:originalurl").or_else(|| property("resource")) {
+        return Some(url);
+    }
+    if depth >= MAX_PRODUCER_DEPTH {
+        return None;
+    }
+    node.children().filter(Node::is_element).find_map(|child| {
+        if child.has_tag_name("producer") || child.has_tag_name("chain") {
+            producer_url(root, &child, depth + 1)
+        } else {
+            let referenced = find_producer(root, child.attribute("producer")?)?;
+            producer_url(root, &referenced, depth + 1)
+        }
+    })
+}
+
+/// Formats `filter_string` as the `filtergraph` property of an MLT producer.
+pub fn filtergraph_property(filter_string: &str) -> String {
+    let mut escaped = String::with_capacity(filter_string.len());
+    for c in filter_string.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    format!("<property name=\"filtergraph\">{escaped}</property>")
 }
 
+/// Producers get the filters of their last entry, the filtergraph property applies to the
+/// whole producer.
 pub fn add_filtergraph_to_producers(
     xml: String,
-    filter_strings: &HashMap<String, String>,
+    filter_strings: &HashMap<String, Vec<EntryFilters>>,
     delete_existing: bool,
     append_filter: Option<String>,
 ) -> String {
     let re_property = Regex::new(r#"<property name=".*">(?P<value>.*)</property>"#).unwrap();
 
+    for (url, entries) in filter_strings {
+        if entries
+            .iter()
+            .any(|e| e.filter_string != entries[entries.len() - 1].filter_string)
+        {
+            log::warn!("Entries of {url} have different filters, using those of the last one");
+        }
+    }
+
     let mut output = Vec::new();
     for line in xml.lines() {
         if delete_existing && line.contains("name=\"filtergraph\"") {
@@ -104,16 +215,17 @@ pub fn add_filtergraph_to_producers(
         {
             let url = || Some(re_property.captures(line)?.name("value")?.as_str());
             if let Some(url) = url() {
-                if let Some(mut filter_string) = filter_strings.get(&url.to_string()).map(Cow::from)
+                if let Some(mut filter_string) = filter_strings
+                    .get(url)
+                    .and_then(|entries| entries.last())
+                    .map(|e| Cow::from(&e.filter_string))
                 {
                     if let Some(append_filter) = &append_filter {
                         filter_string
                             .to_mut()
                             .push_str(&format!(",{append_filter}"));
                     }
-                    output.push(format!(
-                        "  <property name=\"filtergraph\">{filter_string}</property>",
-                    ));
+                    output.push(format!("  {}", filtergraph_property(&filter_string)));
                 }
             }
         }
@@ -149,4 +261,176 @@ mod tests {
         );
         assert_eq!(get_property_value(&root, "av.exposure"), Some(0.0));
     }
+
+    #[test]
+    fn filtergraph() {
+        assert_eq!(
+            filtergraph_property("lut3d=file=/luts/a&b.cube,eq=gamma=0.9"),
+            r#"<property name="filtergraph">lut3d=file=/luts/a&amp;b.cube,eq=gamma=0.9</property>"#
+        );
+
+        let mut filter_strings = HashMap::new();
+        filter_strings.insert(
+            "/clips/a.mp4".to_string(),
+            vec![EntryFilters {
+                in_point: None,
+                out_point: None,
+                filter_string: "eq=gamma=0.9".to_string(),
+            }],
+        );
+        let xml = add_filtergraph_to_producers(
+            "  <property name=\"resource\">/clips/a.mp4</property>".to_string(),
+            &filter_strings,
+            false,
+            Some("scale=1280:720".to_string()),
+        );
+        assert_eq!(
+            xml,
+            "  <property name=\"filtergraph\">eq=gamma=0.9,scale=1280:720</property>\n  <property name=\"resource\">/clips/a.mp4</property>"
+        );
+    }
+
+    #[test]
+    fn gzipped_mlt() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let xml = r#"<mlt><playlist id="main_bin"/></mlt>"#;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(xml.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        assert_eq!(decode_mlt(gzipped).unwrap(), xml);
+        assert_eq!(decode_mlt(xml.as_bytes().to_vec()).unwrap(), xml);
+        assert!(decode_mlt(vec![0x1f, 0x8b, 0x00]).is_err());
+    }
+
+    #[test]
+    fn mixed_producers() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+            <mlt>
+             <producer>
+              <property name="resource">/clips/no-id.mp4</property>
+             </producer>
+             <producer id="producer0">
+              <property name="resource">/clips/a.mp4</property>
+             </producer>
+             <chain id="chain0">
+              <property name="kdenlive:originalurl">/clips/b.mp4</property>
+              <property name="resource">/proxies/b.mp4</property>
+             </chain>
+             <chain id="chain1">
+              <producer>
+               <property name="resource">/clips/c.mp4</property>
+              </producer>
+             </chain>
+             <chain id="chain2">
+              <track producer="producer0"/>
+             </chain>
+             <chain id="chain3">
+              <track producer="chain3"/>
+             </chain>
+             <playlist id="playlist0">
+              <entry producer="producer0">
+               <filter>
+                <property name="mlt_service">avfilter.lut3d</property>
+                <property name="av.file">/luts/a.cube</property>
+                <property name="av.interp">tetrahedral</property>
+               </filter>
+              </entry>
+              <entry producer="chain0"/>
+              <entry>
+               <filter>
+                <property name="mlt_service">avfilter.lut3d</property>
+                <property name="av.file">/luts/a.cube</property>
+                <property name="av.interp">tetrahedral</property>
+               </filter>
+              </entry>
+              <entry producer="missing">
+               <filter>
+                <property name="mlt_service">avfilter.lut3d</property>
+                <property name="av.file">/luts/a.cube</property>
+                <property name="av.interp">tetrahedral</property>
+               </filter>
+              </entry>
+              <entry producer="chain3">
+               <filter>
+                <property name="mlt_service">avfilter.lut3d</property>
+                <property name="av.file">/luts/a.cube</property>
+                <property name="av.interp">tetrahedral</property>
+               </filter>
+              </entry>
+             </playlist>
+            </mlt>"#;
+        let doc = Document::parse(xml).unwrap();
+        let root = doc.root();
+
+        assert_eq!(
+            get_url_from_producer(&root, "producer0").as_deref(),
+            Some("/clips/a.mp4")
+        );
+        assert_eq!(
+            get_url_from_producer(&root, "chain0").as_deref(),
+            Some("/clips/b.mp4")
+        );
+        assert_eq!(
+            get_url_from_producer(&root, "chain1").as_deref(),
+            Some("/clips/c.mp4")
+        );
+        assert_eq!(
+            get_url_from_producer(&root, "chain2").as_deref(),
+            Some("/clips/a.mp4")
+        );
+        assert_eq!(get_url_from_producer(&root, "chain3"), None);
+        assert_eq!(get_url_from_producer(&root, "missing"), None);
+
+        let filter_strings = get_filter_strings(&root);
+        assert_eq!(filter_strings.len(), 1);
+        assert_eq!(
+            filter_strings["/clips/a.mp4"][0].filter_string,
+            "lut3d=file=/luts/a.cube:interp=tetrahedral"
+        );
+    }
+
+    #[test]
+    fn entry_in_out() {
+        let xml = r#"<mlt>
+             <producer id="producer0">
+              <property name="resource">/clips/a.mp4</property>
+             </producer>
+             <playlist id="playlist0">
+              <entry producer="producer0" in="00:00:01.500" out="00:01:02.000">
+               <filter>
+                <property name="mlt_service">avfilter.lut3d</property>
+                <property name="av.file">/luts/a.cube</property>
+                <property name="av.interp">tetrahedral</property>
+               </filter>
+              </entry>
+              <entry producer="producer0" in="250" out="00:00:20:05">
+               <filter>
+                <property name="mlt_service">avfilter.lut3d</property>
+                <property name="av.file">/luts/b.cube</property>
+                <property name="av.interp">tetrahedral</property>
+               </filter>
+              </entry>
+             </playlist>
+            </mlt>"#;
+        let doc = Document::parse(xml).unwrap();
+        let filter_strings = get_filter_strings(&doc.root());
+        let entries = &filter_strings["/clips/a.mp4"];
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].in_point, Some(MltTime::Seconds(1.5)));
+        assert_eq!(entries[0].out_point, Some(MltTime::Seconds(62.)));
+        assert_eq!(entries[1].in_point, Some(MltTime::Frames(250)));
+        // SMPTE timecodes need the frame rate of the profile
+        assert_eq!(entries[1].out_point, None);
+
+        let xml = add_filtergraph_to_producers(
+            "  <property name=\"resource\">/clips/a.mp4</property>".to_string(),
+            &filter_strings,
+            false,
+            None,
+        );
+        assert!(xml.contains("lut3d=file=/luts/b.cube"));
+    }
 }